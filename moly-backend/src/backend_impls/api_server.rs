@@ -7,13 +7,13 @@ use moly_protocol::{
         ChatResponse, ChatResponseChunkData, ChatResponseData, ChunkChoiceData, MessageData, Role,
         StopReason,
     },
-    protocol::LoadModelOptions,
+    protocol::{LoadModelOptions, LoadModelStage},
 };
 use wasmedge_sdk::{wasi::WasiModule, Module, Store, Vm};
 
 use crate::store::download_files::DownloadedFile;
 
-use super::BackendModel;
+use super::{send_load_progress, BackendModel};
 
 // From https://github.com/L-jasmine/LlamaEdge/tree/feat/support_unload_and_exit
 // A repo that fork from LlamaEdge/LlamaEdge for support unload model and exit
@@ -214,11 +214,12 @@ impl BackendModel for LLamaEdgeApiServer {
         };
 
         if !need_reload {
+            send_load_progress(&tx, &file.id, LoadModelStage::Ready);
             let _ = tx.send(Ok(moly_protocol::protocol::LoadModelResponse::Completed(
                 moly_protocol::protocol::LoadedModelInfo {
                     file_id: file.id.to_string(),
                     model_id: file.model_id,
-                    information: "".to_string(),
+                    information: file.information,
                     listen_port: listen_addr.port(),
                 },
             )));
@@ -245,12 +246,15 @@ impl BackendModel for LLamaEdgeApiServer {
 
         let embedding_ = embedding.clone();
 
+        let instantiating_tx = tx.clone();
         let model_thread = std::thread::spawn(move || {
+            send_load_progress(&instantiating_tx, &file.id, LoadModelStage::Instantiating);
             run_wasm_by_downloaded_file(listen_addr, wasm_module_, file, options, embedding_)
         });
 
         let mut test_server = false;
-        for _i in 0..5 {
+        send_load_progress(&tx, &file_id, LoadModelStage::StartingServer);
+        for _ in 0..5 {
             let r = reqwest::blocking::ClientBuilder::new()
                 .timeout(Duration::from_secs(3))
                 .no_proxy()
@@ -267,11 +271,12 @@ impl BackendModel for LLamaEdgeApiServer {
             let _ = std::thread::sleep(std::time::Duration::from_secs(1));
         }
         if test_server {
+            send_load_progress(&tx, &file_id, LoadModelStage::Ready);
             let _ = tx.send(Ok(moly_protocol::protocol::LoadModelResponse::Completed(
                 moly_protocol::protocol::LoadedModelInfo {
                     file_id: file_.id.to_string(),
                     model_id: file_.model_id,
-                    information: "".to_string(),
+                    information: file_.information,
                     listen_port,
                 },
             )));
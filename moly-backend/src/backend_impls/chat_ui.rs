@@ -38,7 +38,7 @@ use moly_protocol::{
         ChatRequestData, ChatResponse, ChatResponseChunkData, ChatResponseData, ChoiceData,
         ChunkChoiceData, MessageData, Role, StopReason, UsageData,
     },
-    protocol::{LoadModelOptions, LoadModelResponse, LoadedModelInfo},
+    protocol::{LoadModelOptions, LoadModelResponse, LoadModelStage, LoadedModelInfo},
 };
 use wasmedge_sdk::{
     error::{CoreError, CoreExecutionError},
@@ -48,6 +48,8 @@ use wasmedge_sdk::{
 
 use crate::store::download_files::DownloadedFile;
 
+use super::send_load_progress;
+
 #[derive(Debug)]
 pub struct ChatBotUi {
     pub current_req: std::io::Cursor<Vec<u8>>,
@@ -225,10 +227,11 @@ fn get_input(
             if let Some((file, _, tx)) = data.load_model_state.take() {
                 let file_id = file.id.as_ref().clone();
                 let model_id = file.model_id;
+                send_load_progress(&tx, &file_id, LoadModelStage::Ready);
                 let _ = tx.send(Ok(LoadModelResponse::Completed(LoadedModelInfo {
                     file_id,
                     model_id,
-                    information: String::new(),
+                    information: file.information,
                     listen_port: 0,
                 })));
             }
@@ -389,6 +392,7 @@ pub fn run_wasm_by_downloaded_file(
     use wasmedge_sdk::vm::SyncInst;
     use wasmedge_sdk::AsInstance;
 
+    send_load_progress(&tx, &file.id, LoadModelStage::Instantiating);
     let mut instances: HashMap<String, &mut (dyn SyncInst)> = HashMap::new();
 
     let mut wasi = create_wasi(&file, &load_model, embedding).unwrap();
@@ -446,10 +450,11 @@ impl super::BackendModel for ChatBotModel {
         };
 
         if !need_reload {
+            send_load_progress(&tx, &file.id, LoadModelStage::Ready);
             let _ = tx.send(Ok(LoadModelResponse::Completed(LoadedModelInfo {
                 file_id: file.id.to_string(),
                 model_id: file.model_id,
-                information: "".to_string(),
+                information: file.information,
                 listen_port: 0,
             })));
             return old_model.unwrap();
@@ -0,0 +1,434 @@
+use std::{
+    convert::Infallible,
+    net::SocketAddr,
+    sync::{mpsc::Sender, Arc, Mutex},
+    time::Instant,
+};
+
+use hyper::{
+    body::Bytes,
+    header,
+    service::{make_service_fn, service_fn},
+    Body, Method, Request, Response, Server, StatusCode,
+};
+use moly_protocol::{
+    data::FileID,
+    open_ai::{ChatRequestData, ChatResponse, Message, Role},
+    protocol::{Command, LocalServerConfig, LocalServerResponse},
+};
+
+/// OpenAI compatible HTTP server that forwards chat completions to whatever model
+/// the backend has loaded at the moment of the request.
+///
+/// Requests are routed through the regular `Command::Chat` path, so the server can
+/// be started before any model is loaded and keeps working across model reloads.
+/// Until a model is loaded, chat requests are answered with 503.
+pub struct LocalServer {
+    pub config: LocalServerConfig,
+    shutdown_tx: tokio::sync::oneshot::Sender<()>,
+    server_task: tokio::task::JoinHandle<()>,
+}
+
+#[derive(Clone)]
+struct ServerState {
+    config: LocalServerConfig,
+    command_tx: Sender<Command>,
+    log_tx: Sender<anyhow::Result<LocalServerResponse>>,
+    // Kept up to date by the backend, `None` while no model is loaded
+    loaded_model: Arc<Mutex<Option<FileID>>>,
+    // Only one request reaches the model at a time. With `request_queuing` the
+    // rest wait for their turn, otherwise they are rejected right away.
+    model_permit: Arc<tokio::sync::Semaphore>,
+}
+
+impl ServerState {
+    fn log(&self, line: String) {
+        log::debug!("local server: {line}");
+        let _ = self.log_tx.send(Ok(LocalServerResponse::Log(line)));
+    }
+}
+
+impl LocalServer {
+    pub fn start(
+        async_rt: &tokio::runtime::Runtime,
+        config: LocalServerConfig,
+        command_tx: Sender<Command>,
+        log_tx: Sender<anyhow::Result<LocalServerResponse>>,
+        loaded_model: Arc<Mutex<Option<FileID>>>,
+    ) -> anyhow::Result<Self> {
+        let addr = SocketAddr::from(([127, 0, 0, 1], config.port));
+        let listener = std::net::TcpListener::bind(addr)
+            .map_err(|e| anyhow::anyhow!("Failed to bind the local server to {addr}: {e}"))?;
+        listener.set_nonblocking(true)?;
+
+        let state = ServerState {
+            config: config.clone(),
+            command_tx,
+            log_tx: log_tx.clone(),
+            loaded_model,
+            model_permit: Arc::new(tokio::sync::Semaphore::new(1)),
+        };
+
+        let server = {
+            let _guard = async_rt.enter();
+            let state = state.clone();
+            Server::from_tcp(listener)?.serve(make_service_fn(move |_conn| {
+                let state = state.clone();
+                async move {
+                    Ok::<_, Infallible>(service_fn(move |req| handle_request(state.clone(), req)))
+                }
+            }))
+        };
+
+        let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel();
+        let server_task = async_rt.spawn(async move {
+            let server = server.with_graceful_shutdown(async {
+                let _ = shutdown_rx.await;
+            });
+            if let Err(e) = server.await {
+                state.log(format!("Server error: {e}"));
+            }
+            state.log("Server stopped".to_string());
+        });
+
+        let _ = log_tx.send(Ok(LocalServerResponse::Started));
+        let _ = log_tx.send(Ok(LocalServerResponse::Log(format!(
+            "Server listening on http://{addr} (cors: {}, request queuing: {}, prompt formatting: {})",
+            config.cors, config.request_queuing, config.apply_prompt_formatting
+        ))));
+
+        Ok(Self {
+            config,
+            shutdown_tx,
+            server_task,
+        })
+    }
+
+    pub fn stop(self, async_rt: &tokio::runtime::Runtime) {
+        let _ = self.shutdown_tx.send(());
+        let _ = async_rt.block_on(self.server_task);
+    }
+}
+
+async fn handle_request(
+    state: ServerState,
+    req: Request<Body>,
+) -> Result<Response<Body>, Infallible> {
+    let started_at = Instant::now();
+    let method = req.method().clone();
+    let path = req.uri().path().to_string();
+
+    if state.config.verbose_server_logs {
+        state.log(format!("{method} {path} {:?}", req.headers()));
+    }
+
+    let mut response = match (&method, path.as_str()) {
+        // Preflight requests are only answered with CORS on
+        (&Method::OPTIONS, _) if state.config.cors => Response::new(Body::empty()),
+        (&Method::OPTIONS, _) => error_response(
+            StatusCode::METHOD_NOT_ALLOWED,
+            "Cross-origin requests are not enabled",
+        ),
+        (&Method::GET, "/v1/models") => models(&state),
+        (&Method::POST, "/v1/chat/completions") => chat_completions(&state, req).await,
+        _ => error_response(StatusCode::NOT_FOUND, format!("Unknown endpoint {path}")),
+    };
+
+    if state.config.cors {
+        let headers = response.headers_mut();
+        headers.insert(
+            header::ACCESS_CONTROL_ALLOW_ORIGIN,
+            header::HeaderValue::from_static("*"),
+        );
+        headers.insert(
+            header::ACCESS_CONTROL_ALLOW_METHODS,
+            header::HeaderValue::from_static("GET, POST, OPTIONS"),
+        );
+        headers.insert(
+            header::ACCESS_CONTROL_ALLOW_HEADERS,
+            header::HeaderValue::from_static("*"),
+        );
+    }
+
+    state.log(format!(
+        "{method} {path} {} ({} ms)",
+        response.status(),
+        started_at.elapsed().as_millis()
+    ));
+
+    Ok(response)
+}
+
+/// The loaded model, listed the way OpenAI clients expect. Empty while no model is
+/// loaded.
+fn models(state: &ServerState) -> Response<Body> {
+    let loaded_model = state.loaded_model.lock().unwrap().clone();
+    let data: Vec<_> = loaded_model
+        .into_iter()
+        .map(|id| {
+            serde_json::json!({
+                "id": id,
+                "object": "model",
+                "created": 0,
+                "owned_by": "moly",
+            })
+        })
+        .collect();
+    let body = serde_json::json!({ "object": "list", "data": data }).to_string();
+
+    Response::builder()
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(body))
+        .unwrap()
+}
+
+async fn chat_completions(state: &ServerState, req: Request<Body>) -> Response<Body> {
+    if state.loaded_model.lock().unwrap().is_none() {
+        return error_response(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "No model is loaded, load one in Moly first",
+        );
+    }
+
+    let body = match hyper::body::to_bytes(req.into_body()).await {
+        Ok(body) => body,
+        Err(e) => return error_response(StatusCode::BAD_REQUEST, e.to_string()),
+    };
+
+    if state.config.verbose_server_logs {
+        state.log(format!("Request body: {}", String::from_utf8_lossy(&body)));
+    }
+
+    let mut data: ChatRequestData = match serde_json::from_slice(&body) {
+        Ok(data) => data,
+        Err(e) => return error_response(StatusCode::BAD_REQUEST, format!("Invalid request: {e}")),
+    };
+
+    if state.config.apply_prompt_formatting {
+        data.messages = format_messages(data.messages);
+    }
+
+    let permit = if state.config.request_queuing {
+        match state.model_permit.clone().acquire_owned().await {
+            Ok(permit) => permit,
+            Err(_) => return error_response(StatusCode::SERVICE_UNAVAILABLE, "Server stopping"),
+        }
+    } else {
+        match state.model_permit.clone().try_acquire_owned() {
+            Ok(permit) => permit,
+            Err(_) => {
+                return error_response(
+                    StatusCode::SERVICE_UNAVAILABLE,
+                    "The model is busy with another request",
+                )
+            }
+        }
+    };
+
+    let is_stream = data.stream.unwrap_or(false);
+    let (tx, rx) = std::sync::mpsc::channel();
+    if state.command_tx.send(Command::Chat(data, tx)).is_err() {
+        return error_response(StatusCode::SERVICE_UNAVAILABLE, "Backend is not running");
+    }
+
+    if is_stream {
+        let (chunk_tx, chunk_rx) = tokio::sync::mpsc::unbounded_channel::<std::io::Result<Bytes>>();
+        let verbose = state.config.verbose_server_logs;
+        let log_state = state.clone();
+
+        tokio::task::spawn_blocking(move || {
+            let _permit = permit;
+            while let Ok(response) = rx.recv() {
+                let (event, finished) = match response {
+                    Ok(ChatResponse::ChatResponseChunk(chunk)) => {
+                        let finished = !matches!(
+                            chunk.choices.first(),
+                            Some(choice) if choice.finish_reason.is_none()
+                        );
+                        (serde_json::to_string(&chunk).unwrap_or_default(), finished)
+                    }
+                    Ok(ChatResponse::ChatFinalResponseData(data)) => {
+                        (serde_json::to_string(&data).unwrap_or_default(), true)
+                    }
+                    Err(e) => (error_body(e.to_string()), true),
+                };
+
+                if verbose {
+                    log_state.log(format!("Stream chunk: {event}"));
+                }
+
+                if chunk_tx
+                    .send(Ok(Bytes::from(format!("data: {event}\n\n"))))
+                    .is_err()
+                {
+                    // The client went away, stop generating tokens for it.
+                    let (stop_tx, _) = std::sync::mpsc::channel();
+                    let _ = log_state
+                        .command_tx
+                        .send(Command::StopChatCompletion(stop_tx));
+                    break;
+                }
+
+                if finished {
+                    let _ = chunk_tx.send(Ok(Bytes::from_static(b"data: [DONE]\n\n")));
+                    break;
+                }
+            }
+        });
+
+        let stream = futures_util::stream::unfold(chunk_rx, |mut chunk_rx| async move {
+            chunk_rx.recv().await.map(|chunk| (chunk, chunk_rx))
+        });
+
+        Response::builder()
+            .header(header::CONTENT_TYPE, "text/event-stream")
+            .header(header::CACHE_CONTROL, "no-cache")
+            .body(Body::wrap_stream(stream))
+            .unwrap()
+    } else {
+        let response = tokio::task::spawn_blocking(move || {
+            let _permit = permit;
+            rx.recv()
+        })
+        .await;
+
+        match response {
+            Ok(Ok(Ok(ChatResponse::ChatFinalResponseData(data)))) => {
+                let body = serde_json::to_string(&data).unwrap_or_default();
+                if state.config.verbose_server_logs {
+                    state.log(format!("Response body: {body}"));
+                }
+                Response::builder()
+                    .header(header::CONTENT_TYPE, "application/json")
+                    .body(Body::from(body))
+                    .unwrap()
+            }
+            Ok(Ok(Ok(ChatResponse::ChatResponseChunk(_)))) => error_response(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Unexpected streamed response",
+            ),
+            Ok(Ok(Err(e))) => error_response(StatusCode::SERVICE_UNAVAILABLE, e.to_string()),
+            Ok(Err(_)) | Err(_) => error_response(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Chat request was dropped",
+            ),
+        }
+    }
+}
+
+/// Normalize the conversation into the shape most chat templates expect: a single
+/// leading system message followed by alternating turns, without empty messages.
+fn format_messages(messages: Vec<Message>) -> Vec<Message> {
+    let (system, turns): (Vec<_>, Vec<_>) = messages
+        .into_iter()
+        .filter(|m| !m.content.trim().is_empty())
+        .partition(|m| m.role == Role::System);
+
+    let mut formatted: Vec<Message> = Vec::with_capacity(turns.len() + 1);
+
+    if !system.is_empty() {
+        formatted.push(Message {
+            content: system
+                .into_iter()
+                .map(|m| m.content)
+                .collect::<Vec<_>>()
+                .join("\n"),
+            role: Role::System,
+            name: None,
+        });
+    }
+
+    for message in turns {
+        match formatted.last_mut() {
+            Some(last) if last.role == message.role => {
+                last.content.push('\n');
+                last.content.push_str(&message.content);
+            }
+            _ => formatted.push(message),
+        }
+    }
+
+    formatted
+}
+
+fn error_body(message: String) -> String {
+    serde_json::json!({ "error": { "message": message } }).to_string()
+}
+
+fn error_response<S: Into<String>>(status: StatusCode, message: S) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(error_body(message.into())))
+        .unwrap()
+}
+
+#[test]
+fn test_format_messages() {
+    let message = |role, content: &str| Message {
+        content: content.to_string(),
+        role,
+        name: None,
+    };
+
+    let formatted = format_messages(vec![
+        message(Role::User, "hello"),
+        message(Role::System, "be brief"),
+        message(Role::User, "are you there?"),
+        message(Role::Assistant, ""),
+        message(Role::Assistant, "yes"),
+    ]);
+
+    assert_eq!(formatted.len(), 3);
+    assert_eq!(formatted[0].role, Role::System);
+    assert_eq!(formatted[0].content, "be brief");
+    assert_eq!(formatted[1].content, "hello\nare you there?");
+    assert_eq!(formatted[2].role, Role::Assistant);
+}
+
+#[test]
+fn test_routes() {
+    let (command_tx, _command_rx) = std::sync::mpsc::channel();
+    let (log_tx, _log_rx) = std::sync::mpsc::channel();
+    let state = ServerState {
+        config: LocalServerConfig {
+            port: 0,
+            cors: false,
+            request_queuing: false,
+            verbose_server_logs: false,
+            apply_prompt_formatting: false,
+        },
+        command_tx,
+        log_tx,
+        loaded_model: Default::default(),
+        model_permit: Arc::new(tokio::sync::Semaphore::new(1)),
+    };
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    let request = |method, path: &str| {
+        let req = Request::builder()
+            .method(method)
+            .uri(path)
+            .body(Body::from("{\"messages\": []}"))
+            .unwrap();
+        rt.block_on(handle_request(state.clone(), req)).unwrap()
+    };
+    let body = |response: Response<Body>| {
+        let bytes = rt.block_on(hyper::body::to_bytes(response.into_body()));
+        serde_json::from_slice::<serde_json::Value>(&bytes.unwrap()).unwrap()
+    };
+
+    assert_eq!(
+        request(Method::OPTIONS, "/v1/chat/completions").status(),
+        StatusCode::METHOD_NOT_ALLOWED
+    );
+    assert_eq!(
+        request(Method::POST, "/v1/chat/completions").status(),
+        StatusCode::SERVICE_UNAVAILABLE
+    );
+    let models = body(request(Method::GET, "/v1/models"));
+    assert_eq!(models["data"], serde_json::json!([]));
+
+    *state.loaded_model.lock().unwrap() = Some("org/model#model.gguf".to_string());
+    let models = body(request(Method::GET, "/v1/models"));
+    assert_eq!(models["data"][0]["id"], "org/model#model.gguf");
+}
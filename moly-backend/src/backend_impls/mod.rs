@@ -1,41 +1,94 @@
 use std::{
     path::{Path, PathBuf},
     sync::{
+        atomic::{AtomicBool, Ordering},
         mpsc::{Receiver, Sender},
         Arc, Mutex,
     },
+    time::{Duration, Instant},
 };
 
 use chrono::Utc;
 use moly_protocol::{
-    data::{DownloadedFile, FileID, Model, PendingDownload},
+    data::{
+        BandwidthSettings, CatalogSourceConfig, CatalogStatus, CatalogUpdate, DownloadWindow,
+        DownloadedFile, FileID, LibraryIssue, LibraryRoot, Model, PendingDownload, ProxyConfig,
+        StorageInfo,
+    },
     open_ai::{ChatRequestData, ChatResponse},
     protocol::{
-        Command, FileDownloadResponse, LoadModelOptions, LoadModelResponse, LocalServerConfig,
-        LocalServerResponse,
+        Command, FileDownloadResponse, FileVerification, ImportResponse, ImportedFile,
+        LoadModelOptions, LoadModelResponse, LoadModelStage, LocalServerConfig,
+        LocalServerResponse, ModelsDirMigrationResponse,
     },
 };
 
 use crate::store::{
     self,
-    model_cards::{ModelCard, ModelCardManager},
+    catalog::Catalog,
+    compatibility::SystemInfo,
+    credentials::Credentials,
+    model_cards::{ModelCard, ModelCardManager, ModelIndex},
+    preferences::Preferences,
     ModelFileDownloader,
 };
 
 mod api_server;
 mod chat_ui;
+mod local_server;
+mod resource_usage;
+
+// How long running downloads get to stop before the models directory moves
+const MIGRATION_STOP_TIMEOUT: Duration = Duration::from_secs(30);
 
 #[derive(Clone, Debug)]
 enum ModelManagementCommand {
     GetFeaturedModels(Sender<anyhow::Result<Vec<Model>>>),
     SearchModels(String, Sender<anyhow::Result<Vec<Model>>>),
     DownloadFile(FileID, Sender<anyhow::Result<FileDownloadResponse>>),
+    SetDownloadMirrors(Vec<String>, Sender<anyhow::Result<()>>),
+    GetDownloadMirrors(Sender<anyhow::Result<Vec<String>>>),
+    SetModelCardsMirrors(Vec<String>, Sender<anyhow::Result<()>>),
+    GetModelCardsMirrors(Sender<anyhow::Result<Vec<String>>>),
     PauseDownload(FileID, Sender<anyhow::Result<()>>),
     CancelDownload(FileID, Sender<anyhow::Result<()>>),
+    ReorderDownloads(Vec<FileID>, Sender<anyhow::Result<()>>),
+    PrioritizeDownload(FileID, Sender<anyhow::Result<()>>),
+    DeferDownload(FileID, Sender<anyhow::Result<()>>),
+    SetMaxConcurrentDownloads(usize, Sender<anyhow::Result<()>>),
+    SetAutoResumeDownloads(bool, Sender<anyhow::Result<()>>),
+    SetDownloadSpeedLimit(Option<u64>, Sender<anyhow::Result<()>>),
+    SetFileDownloadSpeedLimit(FileID, Option<u64>, Sender<anyhow::Result<()>>),
+    SetDownloadWindows(Vec<DownloadWindow>, Sender<anyhow::Result<()>>),
+    GetBandwidthSettings(Sender<anyhow::Result<BandwidthSettings>>),
+    SetAccessToken(Option<String>, Sender<anyhow::Result<()>>),
+    HasAccessToken(Sender<anyhow::Result<bool>>),
+    SetProxyConfig(ProxyConfig, Sender<anyhow::Result<()>>),
+    GetProxyConfig(Sender<anyhow::Result<ProxyConfig>>),
+    SetModelsDirQuota(Option<u64>, Sender<anyhow::Result<()>>),
+    GetStorageInfo(Sender<anyhow::Result<StorageInfo>>),
+    SetLibraryRoots(Vec<LibraryRoot>, Sender<anyhow::Result<()>>),
+    GetLibraryRoots(Sender<anyhow::Result<Vec<LibraryRoot>>>),
+    SetDownloadRoot(Option<PathBuf>, Sender<anyhow::Result<()>>),
+    SetCatalogSources(Vec<CatalogSourceConfig>, Sender<anyhow::Result<()>>),
+    GetCatalogSources(Sender<anyhow::Result<Vec<CatalogSourceConfig>>>),
+    SetOfflineMode(bool, Sender<anyhow::Result<()>>),
+    GetCatalogStatus(Sender<anyhow::Result<CatalogStatus>>),
+    RefreshCatalog(Sender<anyhow::Result<()>>),
+    WatchCatalog(Sender<anyhow::Result<CatalogUpdate>>),
+    SetModelCardsRevision(Option<String>, Sender<anyhow::Result<()>>),
+    GetModelCardsRevision(Sender<anyhow::Result<Option<String>>>),
+    RollbackCatalog(Sender<anyhow::Result<String>>),
+    ImportLocalFiles(Vec<PathBuf>, Sender<anyhow::Result<ImportResponse>>),
+    CheckLibrary(bool, Sender<anyhow::Result<Vec<LibraryIssue>>>),
     GetCurrentDownloads(Sender<anyhow::Result<Vec<PendingDownload>>>),
     GetDownloadedFiles(Sender<anyhow::Result<Vec<DownloadedFile>>>),
     DeleteFile(FileID, Sender<anyhow::Result<()>>),
-    ChangeModelsLocation(PathBuf),
+    VerifyFile(FileID, Sender<anyhow::Result<FileVerification>>),
+    ChangeModelsLocation(
+        PathBuf,
+        Sender<anyhow::Result<ModelsDirMigrationResponse>>,
+    ),
 }
 
 #[derive(Clone, Debug)]
@@ -75,15 +128,107 @@ impl From<Command> for BuiltInCommand {
             Command::DownloadFile(file_id, tx) => {
                 Self::Model(ModelManagementCommand::DownloadFile(file_id, tx))
             }
+            Command::SetDownloadMirrors(mirrors, tx) => {
+                Self::Model(ModelManagementCommand::SetDownloadMirrors(mirrors, tx))
+            }
+            Command::GetDownloadMirrors(tx) => {
+                Self::Model(ModelManagementCommand::GetDownloadMirrors(tx))
+            }
+            Command::SetModelCardsMirrors(mirrors, tx) => {
+                Self::Model(ModelManagementCommand::SetModelCardsMirrors(mirrors, tx))
+            }
+            Command::GetModelCardsMirrors(tx) => {
+                Self::Model(ModelManagementCommand::GetModelCardsMirrors(tx))
+            }
             Command::PauseDownload(file_id, tx) => {
                 Self::Model(ModelManagementCommand::PauseDownload(file_id, tx))
             }
             Command::CancelDownload(file_id, tx) => {
                 Self::Model(ModelManagementCommand::CancelDownload(file_id, tx))
             }
+            Command::ReorderDownloads(file_ids, tx) => {
+                Self::Model(ModelManagementCommand::ReorderDownloads(file_ids, tx))
+            }
+            Command::PrioritizeDownload(file_id, tx) => {
+                Self::Model(ModelManagementCommand::PrioritizeDownload(file_id, tx))
+            }
+            Command::DeferDownload(file_id, tx) => {
+                Self::Model(ModelManagementCommand::DeferDownload(file_id, tx))
+            }
+            Command::SetMaxConcurrentDownloads(max, tx) => {
+                Self::Model(ModelManagementCommand::SetMaxConcurrentDownloads(max, tx))
+            }
+            Command::SetAutoResumeDownloads(enabled, tx) => {
+                Self::Model(ModelManagementCommand::SetAutoResumeDownloads(enabled, tx))
+            }
+            Command::SetDownloadSpeedLimit(limit, tx) => {
+                Self::Model(ModelManagementCommand::SetDownloadSpeedLimit(limit, tx))
+            }
+            Command::SetFileDownloadSpeedLimit(file_id, limit, tx) => Self::Model(
+                ModelManagementCommand::SetFileDownloadSpeedLimit(file_id, limit, tx),
+            ),
+            Command::SetDownloadWindows(windows, tx) => {
+                Self::Model(ModelManagementCommand::SetDownloadWindows(windows, tx))
+            }
+            Command::GetBandwidthSettings(tx) => {
+                Self::Model(ModelManagementCommand::GetBandwidthSettings(tx))
+            }
+            Command::SetAccessToken(token, tx) => {
+                Self::Model(ModelManagementCommand::SetAccessToken(token, tx))
+            }
+            Command::HasAccessToken(tx) => Self::Model(ModelManagementCommand::HasAccessToken(tx)),
+            Command::SetProxyConfig(config, tx) => {
+                Self::Model(ModelManagementCommand::SetProxyConfig(config, tx))
+            }
+            Command::GetProxyConfig(tx) => Self::Model(ModelManagementCommand::GetProxyConfig(tx)),
+            Command::SetModelsDirQuota(quota, tx) => {
+                Self::Model(ModelManagementCommand::SetModelsDirQuota(quota, tx))
+            }
+            Command::GetStorageInfo(tx) => Self::Model(ModelManagementCommand::GetStorageInfo(tx)),
+            Command::SetLibraryRoots(roots, tx) => {
+                Self::Model(ModelManagementCommand::SetLibraryRoots(roots, tx))
+            }
+            Command::GetLibraryRoots(tx) => {
+                Self::Model(ModelManagementCommand::GetLibraryRoots(tx))
+            }
+            Command::SetDownloadRoot(path, tx) => {
+                Self::Model(ModelManagementCommand::SetDownloadRoot(path, tx))
+            }
+            Command::SetCatalogSources(sources, tx) => {
+                Self::Model(ModelManagementCommand::SetCatalogSources(sources, tx))
+            }
+            Command::GetCatalogSources(tx) => {
+                Self::Model(ModelManagementCommand::GetCatalogSources(tx))
+            }
+            Command::SetOfflineMode(offline, tx) => {
+                Self::Model(ModelManagementCommand::SetOfflineMode(offline, tx))
+            }
+            Command::GetCatalogStatus(tx) => {
+                Self::Model(ModelManagementCommand::GetCatalogStatus(tx))
+            }
+            Command::RefreshCatalog(tx) => Self::Model(ModelManagementCommand::RefreshCatalog(tx)),
+            Command::WatchCatalog(tx) => Self::Model(ModelManagementCommand::WatchCatalog(tx)),
+            Command::SetModelCardsRevision(revision, tx) => {
+                Self::Model(ModelManagementCommand::SetModelCardsRevision(revision, tx))
+            }
+            Command::GetModelCardsRevision(tx) => {
+                Self::Model(ModelManagementCommand::GetModelCardsRevision(tx))
+            }
+            Command::RollbackCatalog(tx) => {
+                Self::Model(ModelManagementCommand::RollbackCatalog(tx))
+            }
+            Command::ImportLocalFiles(paths, tx) => {
+                Self::Model(ModelManagementCommand::ImportLocalFiles(paths, tx))
+            }
+            Command::CheckLibrary(repair, tx) => {
+                Self::Model(ModelManagementCommand::CheckLibrary(repair, tx))
+            }
             Command::DeleteFile(file_id, tx) => {
                 Self::Model(ModelManagementCommand::DeleteFile(file_id, tx))
             }
+            Command::VerifyFile(file_id, tx) => {
+                Self::Model(ModelManagementCommand::VerifyFile(file_id, tx))
+            }
             Command::GetCurrentDownloads(tx) => {
                 Self::Model(ModelManagementCommand::GetCurrentDownloads(tx))
             }
@@ -106,8 +251,8 @@ impl From<Command> for BuiltInCommand {
             Command::StopLocalServer(tx) => {
                 Self::Interaction(ModelInteractionCommand::StopLocalServer(tx))
             }
-            Command::ChangeModelsDir(path) => {
-                Self::Model(ModelManagementCommand::ChangeModelsLocation(path))
+            Command::ChangeModelsDir(path, tx) => {
+                Self::Model(ModelManagementCommand::ChangeModelsLocation(path, tx))
             }
         }
     }
@@ -137,6 +282,7 @@ fn test_chat() {
     let cmd = Command::LoadModel(
         file.file.id.clone(),
         LoadModelOptions {
+            override_server_address: None,
             prompt_template: None,
             gpu_layers: moly_protocol::protocol::GPULayers::Max,
             use_mlock: false,
@@ -149,9 +295,8 @@ fn test_chat() {
         tx,
     );
     bk.send(cmd).unwrap();
-    let r = rx.recv();
-    assert!(r.is_ok());
-    assert!(r.unwrap().is_ok());
+    // Progress comes first
+    while !matches!(rx.recv().unwrap().unwrap(), LoadModelResponse::Completed(_)) {}
 
     let (tx, rx) = std::sync::mpsc::channel();
     let cmd = Command::Chat(
@@ -212,6 +357,7 @@ fn test_chat_stop() {
     let cmd = Command::LoadModel(
         file.file.id.clone(),
         LoadModelOptions {
+            override_server_address: None,
             prompt_template: None,
             gpu_layers: moly_protocol::protocol::GPULayers::Max,
             use_mlock: false,
@@ -224,9 +370,8 @@ fn test_chat_stop() {
         tx,
     );
     bk.send(cmd).unwrap();
-    let r = rx.recv();
-    assert!(r.is_ok());
-    assert!(r.unwrap().is_ok());
+    // Progress comes first
+    while !matches!(rx.recv().unwrap().unwrap(), LoadModelResponse::Completed(_)) {}
 
     let (tx, rx) = std::sync::mpsc::channel();
     let cmd = Command::Chat(
@@ -313,7 +458,13 @@ fn test_download_file() {
     while let Ok(r) = rx.recv() {
         match r {
             Ok(FileDownloadResponse::Progress(file_id, progress)) => {
-                println!("{file_id} progress: {:.2}%", progress);
+                println!("{file_id} progress: {:.2}%", progress.percentage());
+            }
+            Ok(FileDownloadResponse::Retrying(file_id, attempt)) => {
+                println!("{file_id} retrying (attempt {attempt})");
+            }
+            Ok(FileDownloadResponse::Queued(file_id)) => {
+                println!("{file_id} queued");
             }
             Ok(FileDownloadResponse::Completed(file)) => {
                 println!("Completed {file:?}");
@@ -347,6 +498,12 @@ fn test_get_download_file() {
 #[derive(Debug, Clone)]
 pub enum DownloadControlCommand {
     Stop(FileID),
+    // The queue order, its content, the concurrency limit or the windows changed
+    QueueChanged,
+    // Speed limits changed in the preferences
+    BandwidthChanged,
+    // The proxy configuration changed in the preferences
+    ProxyChanged,
 }
 
 pub type ChatModelBackend = BackendImpl<chat_ui::ChatBotModel>;
@@ -373,10 +530,13 @@ pub trait BackendModel: Sized {
 
 pub struct BackendImpl<Model: BackendModel> {
     sql_conn: Arc<Mutex<rusqlite::Connection>>,
+    preferences: Arc<Mutex<Preferences>>,
+    credentials: Arc<Mutex<Credentials>>,
     model_indexs: ModelCardManager,
     #[allow(unused)]
     app_data_dir: PathBuf,
-    models_dir: PathBuf,
+    // Replaced once the files moved to a new directory
+    models_dir: Arc<Mutex<PathBuf>>,
     pub rx: Receiver<Command>,
     download_tx: tokio::sync::mpsc::UnboundedSender<(
         store::models::Model,
@@ -384,10 +544,19 @@ pub struct BackendImpl<Model: BackendModel> {
         Sender<anyhow::Result<FileDownloadResponse>>,
     )>,
     model: Option<Model>,
+    downloader: ModelFileDownloader,
 
     #[allow(unused)]
     async_rt: tokio::runtime::Runtime,
     control_tx: tokio::sync::broadcast::Sender<DownloadControlCommand>,
+
+    // Used by the local server to route its requests through the command loop.
+    command_tx: Sender<Command>,
+    local_server: Option<local_server::LocalServer>,
+    // File of the loaded model, for the local server to tell whether there is one
+    loaded_model: Arc<Mutex<Option<FileID>>>,
+    // Set to stop sampling the resources of the loaded model
+    resources_watch: Option<Arc<AtomicBool>>,
 }
 
 impl<Model: BackendModel + Send + 'static> BackendImpl<Model> {
@@ -412,25 +581,49 @@ impl<Model: BackendModel + Send + 'static> BackendImpl<Model> {
             )
         });
 
-        let model_indexs = store::model_cards::sync_model_cards_repo(&app_data_dir);
-        let model_indexs = match model_indexs {
-            Ok(model_indexs) => {
-                log::info!("sync model cards repo success");
-                model_indexs
-            }
-            Err(e) => {
-                log::error!("sync model cards repo error: {e}");
-                ModelCardManager::empty(app_data_dir.clone())
-            }
-        };
+        let preferences = Preferences::load(&app_data_dir);
+
+        // Behind a firewall, the mirrors may be all there is to reach
+        let mut mirrors: Vec<String> = ["HF_ENDPOINT", "MODEL_CARDS_REPO"]
+            .iter()
+            .filter_map(|name| std::env::var(name).ok())
+            .chain(preferences.download_mirrors.iter().cloned())
+            .collect();
+        if preferences.model_cards_mirrors.is_empty() {
+            mirrors.extend(store::model_cards::DEFAULT_MODEL_CARDS_MIRRORS.map(String::from));
+        } else {
+            mirrors.extend(preferences.model_cards_mirrors.iter().cloned());
+        }
+        let offline =
+            preferences.offline_mode || !store::network::is_online(&preferences.proxy, &mirrors);
+        if offline && !preferences.offline_mode {
+            log::warn!("No network found, starting offline");
+        }
+
+        store::network::configure_git(&preferences.proxy);
+        let model_indexs = ModelCardManager::new(app_data_dir.clone(), &preferences, offline);
 
         let sql_conn = rusqlite::Connection::open(app_data_dir.join("data.sqlite")).unwrap();
 
         // TODO Reorganize these bunch of functions, needs a little more of thought
         let _ = store::models::create_table_models(&sql_conn).unwrap();
         let _ = store::download_files::create_table_download_files(&sql_conn).unwrap();
+        store::download_segments::create_table_download_segments(&sql_conn).unwrap();
+        store::download_shards::create_table_download_shards(&sql_conn).unwrap();
+
+        // Offline, interrupted downloads would only fail
+        let r = if preferences.auto_resume_downloads && !offline {
+            store::download_files::DownloadedFile::requeue_interrupted(&sql_conn)
+        } else {
+            store::download_files::DownloadedFile::pause_interrupted(&sql_conn)
+        };
+        if let Err(e) = r {
+            log::error!("Failed to restore interrupted downloads: {e}");
+        }
 
         let sql_conn = Arc::new(Mutex::new(sql_conn));
+        let preferences = Arc::new(Mutex::new(preferences));
+        let credentials = Arc::new(Mutex::new(Credentials::load(&app_data_dir)));
 
         let (tx, rx) = std::sync::mpsc::channel();
 
@@ -441,30 +634,67 @@ impl<Model: BackendModel + Send + 'static> BackendImpl<Model> {
         let (control_tx, _control_rx) = tokio::sync::broadcast::channel(100);
         let (download_tx, download_rx) = tokio::sync::mpsc::unbounded_channel();
 
-        {
-            let client = reqwest::Client::new();
-            let downloader =
-                ModelFileDownloader::new(client, sql_conn.clone(), control_tx.clone(), 0.1);
-            async_rt.spawn(ModelFileDownloader::run_loop(
-                downloader,
+        let downloader = {
+            let client = store::network::async_client(&preferences.lock().unwrap().proxy)
+                .unwrap_or_else(|e| {
+                    log::error!("Failed to apply the proxy configuration: {e}");
+                    reqwest::Client::new()
+                });
+            let downloader = ModelFileDownloader::new(
+                client,
+                sql_conn.clone(),
+                preferences.clone(),
+                control_tx.clone(),
+                0.1,
                 max_download_threads.max(3),
+                credentials.clone(),
+            );
+            async_rt.spawn(ModelFileDownloader::run_loop(
+                downloader.clone(),
                 download_rx,
             ));
-        }
+            downloader
+        };
 
         let mut backend = Self {
             sql_conn,
+            preferences,
+            credentials,
             model_indexs,
             app_data_dir,
-            models_dir: models_dir.as_ref().into(),
+            models_dir: Arc::new(Mutex::new(models_dir.as_ref().into())),
             rx,
             download_tx,
             model: None,
+            downloader,
             async_rt,
             control_tx,
+            command_tx: tx.clone(),
+            local_server: None,
+            loaded_model: Default::default(),
+            resources_watch: None,
         };
+        backend.remember_model_cards_mirror();
 
         std::thread::spawn(move || {
+            let system = SystemInfo::detect();
+            log::info!(
+                "System memory: {:?}, CPU features: {:?}",
+                system.memory,
+                system.cpu_features
+            );
+            backend.scan_library_roots();
+            // Only reported, `CheckLibrary` with `true` fixes them
+            match backend.check_library(false) {
+                Ok(issues) if issues.is_empty() => log::info!("The library has no issues"),
+                Ok(issues) => {
+                    log::warn!("The library has {} issues", issues.len());
+                    for issue in issues {
+                        log::warn!("Library issue: {issue:?}");
+                    }
+                }
+                Err(e) => log::error!("Failed to check the library: {e}"),
+            }
             backend.run_loop();
         });
         tx
@@ -474,157 +704,458 @@ impl<Model: BackendModel + Send + 'static> BackendImpl<Model> {
         match built_in_cmd {
             BuiltInCommand::Model(file) => match file {
                 ModelManagementCommand::GetFeaturedModels(tx) => {
-                    let res = self.model_indexs.get_featured_model(100, 0);
-                    match res {
-                        Ok(indexs) => {
-                            let mut models = Vec::new();
-                            for index in indexs {
-                                if let Ok(card) = self.model_indexs.load_model_card(&index) {
-                                    models.push(card);
+                    self.send_models(tx, |catalog| catalog.featured(100, 0));
+                }
+                ModelManagementCommand::SearchModels(search_text, tx) => {
+                    self.send_models(tx, move |catalog| {
+                        catalog.search(&search_text, 100, 0)
+                    });
+                }
+                ModelManagementCommand::DownloadFile(file_id, tx) => {
+                    if self.model_indexs.is_offline() {
+                        let _ = tx.send(Err(anyhow::anyhow!(
+                            "Cannot download {file_id} while offline, turn the offline mode off first"
+                        )));
+                        return;
+                    }
+                    if self.downloader.is_suspended() {
+                        // It would be saved to the directory the files are leaving
+                        let _ = tx.send(Err(anyhow::anyhow!(
+                            "Cannot download {file_id} while the models directory is changing, try again once it is done"
+                        )));
+                        return;
+                    }
+                    let download_dir = self.download_dir();
+                    let catalog = self.model_indexs.catalog();
+                    match Self::catalog_entry(&catalog, &file_id, &download_dir) {
+                        Ok((model, file, shards)) => {
+                            let r = {
+                                let conn = self.sql_conn.lock().unwrap();
+                                shards
+                                    .iter()
+                                    .try_for_each(|shard| shard.insert_into_db(&conn))
+                            };
+                            match r {
+                                Ok(()) => {
+                                    let _ = self.download_tx.send((model, file, tx));
+                                }
+                                Err(e) => {
+                                    let _ = tx.send(Err(anyhow::anyhow!("Save shards error: {e}")));
                                 }
                             }
-
-                            let sql_conn = self.sql_conn.lock().unwrap();
-                            let models = ModelCard::to_model(&models, &sql_conn)
-                                .map_err(|e| anyhow::anyhow!("get featured error: {e}"));
-
-                            let _ = tx.send(models);
                         }
                         Err(e) => {
-                            let _ = tx.send(Err(anyhow::anyhow!("get featured models error: {e}")));
+                            let _ = tx.send(Err(e));
                         }
                     }
                 }
-                ModelManagementCommand::SearchModels(search_text, tx) => {
-                    let res = self.model_indexs.search(&search_text, 100, 0);
-                    match res {
-                        Ok(indexs) => {
-                            log::debug!("search models: {}", indexs.len());
-                            let sql_conn = self.sql_conn.lock().unwrap();
-
-                            let mut models = Vec::new();
-                            for index in indexs {
-                                match self.model_indexs.load_model_card(&index) {
-                                    Ok(card) => {
-                                        models.push(card);
-                                    }
-                                    Err(e) => {
-                                        log::error!("load model card {} error: {e}", index.id);
-                                    }
-                                }
-                            }
 
-                            let models = ModelCard::to_model(&models, &sql_conn)
-                                .map_err(|e| anyhow::anyhow!("search models error: {e}"));
+                ModelManagementCommand::SetDownloadMirrors(mirrors, tx) => {
+                    let mut preferences = self.preferences.lock().unwrap();
+                    preferences.download_mirrors = mirrors
+                        .into_iter()
+                        .map(|mirror| mirror.trim().to_string())
+                        .filter(|mirror| !mirror.is_empty())
+                        .collect();
+                    let _ = tx.send(preferences.save());
+                }
 
-                            let _ = tx.send(models);
-                        }
-                        Err(e) => {
-                            let _ = tx.send(Err(anyhow::anyhow!("search models error: {e}")));
-                        }
-                    }
+                ModelManagementCommand::GetDownloadMirrors(tx) => {
+                    let preferences = self.preferences.lock().unwrap();
+                    let _ = tx.send(Ok(preferences.download_mirrors.clone()));
                 }
-                ModelManagementCommand::DownloadFile(file_id, tx) => {
-                    //search model from remote
-                    let mut search_model_from_remote = || -> anyhow::Result<( crate::store::models::Model , crate::store::download_files::DownloadedFile)> {
-                        let (model_id, file) = file_id
-                            .split_once("#")
-                            .ok_or_else(|| anyhow::anyhow!("Illegal file_id"))?;
-
-                        let index = self.model_indexs.get_index_by_id(model_id).ok_or(anyhow::anyhow!("No model found"))?.clone();
-                        let remote_model = self.model_indexs.load_model_card(&index)?;
-                    
-
-                        let remote_file = remote_model
-                            .files
-                            .into_iter()
-                            .find(|f| f.name == file)
-                            .ok_or_else(|| anyhow::anyhow!("file not found"))?;
-
-                        let download_model = crate::store::models::Model {
-                            id: Arc::new(remote_model.id),
-                            name: remote_model.name,
-                            summary: remote_model.summary,
-                            size: remote_model.size,
-                            requires: remote_model.requires,
-                            architecture: remote_model.architecture,
-                            released_at: remote_model.released_at,
-                            prompt_template: remote_model.prompt_template.clone(),
-                            reverse_prompt: remote_model.reverse_prompt.clone(),
-                            author: Arc::new(crate::store::model_cards::Author {
-                                name: remote_model.author.name,
-                                url: remote_model.author.url,
-                                description: remote_model.author.description,
-                            }),
-                            like_count: remote_model.like_count,
-                            download_count: remote_model.download_count,
-                        };
-
-                        let download_file = crate::store::download_files::DownloadedFile {
-                            id: Arc::new(file_id.clone()),
-                            model_id: model_id.to_string(),
-                            name: file.to_string(),
-                            size: remote_file.size,
-                            quantization: remote_file.quantization,
-                            prompt_template: remote_model.prompt_template,
-                            reverse_prompt: remote_model.reverse_prompt,
-                            context_size:remote_model.context_size,
-                            downloaded: false,
-                            file_size: 0,
-                            download_dir: self.models_dir.to_string_lossy().to_string(),
-                            downloaded_at: Utc::now(),
-                            tags:remote_file.tags,
-                            featured: false,
-                            sha256: remote_file.sha256.unwrap_or_default(),
-                        };
-
-                        Ok((download_model,download_file))
-                    };
 
-                    match search_model_from_remote() {
-                        Ok((model, file)) => {
-                            let _ = self.download_tx.send((model, file, tx));
-                        }
-                        Err(e) => {
-                            let _ = tx.send(Err(e));
-                        }
-                    }
+                ModelManagementCommand::SetModelCardsMirrors(mirrors, tx) => {
+                    let mut preferences = self.preferences.lock().unwrap();
+                    preferences.model_cards_mirrors = mirrors
+                        .into_iter()
+                        .map(|mirror| mirror.trim().to_string())
+                        .filter(|mirror| !mirror.is_empty())
+                        .collect();
+                    let _ = tx.send(preferences.save());
+                }
+
+                ModelManagementCommand::GetModelCardsMirrors(tx) => {
+                    let preferences = self.preferences.lock().unwrap();
+                    let _ = tx.send(Ok(preferences.model_cards_mirrors.clone()));
                 }
 
                 ModelManagementCommand::PauseDownload(file_id, tx) => {
+                    // Also covers downloads still waiting in the queue, which are not
+                    // listening to control commands yet
+                    {
+                        let conn = self.sql_conn.lock().unwrap();
+                        let _ = store::download_files::DownloadedFile::update_status(
+                            &file_id,
+                            &store::download_files::DownloadStatus::Paused,
+                            &conn,
+                        );
+                    }
                     let _ = self.control_tx.send(DownloadControlCommand::Stop(file_id));
                     let _ = tx.send(Ok(()));
                 }
 
                 ModelManagementCommand::CancelDownload(file_id, tx) => {
+                    // Never clean up relative to the working directory
+                    let download_dir = match self.writable_dir_of(&file_id) {
+                        Ok(download_dir) => download_dir,
+                        Err(e) => {
+                            let _ = tx.send(Err(e));
+                            return;
+                        }
+                    };
                     let file_id_ = file_id.clone();
                     let _ = self.control_tx.send(DownloadControlCommand::Stop(file_id_));
 
                     {
                         let conn = self.sql_conn.lock().unwrap();
                         let _ = store::download_files::DownloadedFile::remove(&file_id, &conn);
+                        let _ = store::download_segments::DownloadSegment::remove_by_file(
+                            &conn, &file_id,
+                        );
+                        let _ = store::remove_file_shards(
+                            &conn,
+                            &download_dir.to_string_lossy(),
+                            &file_id,
+                        );
+                    }
+                    {
+                        let mut preferences = self.preferences.lock().unwrap();
+                        if preferences
+                            .file_download_speed_limits
+                            .remove(&file_id)
+                            .is_some()
+                        {
+                            let _ = preferences.save();
+                        }
                     }
                     let _ = store::remove_downloaded_file(
-                        self.models_dir.to_string_lossy().to_string(),
+                        download_dir.to_string_lossy().to_string(),
                         file_id,
                     );
 
                     let _ = tx.send(Ok(()));
                 }
 
+                ModelManagementCommand::ReorderDownloads(file_ids, tx) => {
+                    let conn = self.sql_conn.lock().unwrap();
+                    let r = store::download_files::DownloadedFile::set_queue_order(&conn, &file_ids);
+                    let _ = self.control_tx.send(DownloadControlCommand::QueueChanged);
+                    let _ = tx.send(r.map_err(|e| anyhow::anyhow!("Reorder downloads error: {e}")));
+                }
+
+                ModelManagementCommand::PrioritizeDownload(file_id, tx) => {
+                    let conn = self.sql_conn.lock().unwrap();
+                    let r = store::download_files::DownloadedFile::set_queue_order(&conn, &[file_id]);
+                    let _ = self.control_tx.send(DownloadControlCommand::QueueChanged);
+                    let _ = tx.send(r.map_err(|e| anyhow::anyhow!("Prioritize download error: {e}")));
+                }
+
+                ModelManagementCommand::DeferDownload(file_id, tx) => {
+                    let conn = self.sql_conn.lock().unwrap();
+                    let r = store::download_files::DownloadedFile::get_queue_order(&conn)
+                        .and_then(|mut order| {
+                            order.retain(|id| *id != file_id);
+                            order.push(file_id);
+                            store::download_files::DownloadedFile::set_queue_order(&conn, &order)
+                        });
+                    let _ = self.control_tx.send(DownloadControlCommand::QueueChanged);
+                    let _ = tx.send(r.map_err(|e| anyhow::anyhow!("Defer download error: {e}")));
+                }
+
+                ModelManagementCommand::SetMaxConcurrentDownloads(max, tx) => {
+                    let mut preferences = self.preferences.lock().unwrap();
+                    preferences.max_concurrent_downloads = Some(max.max(1));
+                    let r = preferences.save();
+                    let _ = self.control_tx.send(DownloadControlCommand::QueueChanged);
+                    let _ = tx.send(r);
+                }
+
+                ModelManagementCommand::SetAutoResumeDownloads(enabled, tx) => {
+                    let mut preferences = self.preferences.lock().unwrap();
+                    preferences.auto_resume_downloads = enabled;
+                    let _ = tx.send(preferences.save());
+                }
+
+                ModelManagementCommand::SetDownloadSpeedLimit(limit, tx) => {
+                    let mut preferences = self.preferences.lock().unwrap();
+                    preferences.max_download_speed = limit.filter(|limit| *limit > 0);
+                    let r = preferences.save();
+                    let _ = self.control_tx.send(DownloadControlCommand::BandwidthChanged);
+                    let _ = tx.send(r);
+                }
+
+                ModelManagementCommand::SetFileDownloadSpeedLimit(file_id, limit, tx) => {
+                    let mut preferences = self.preferences.lock().unwrap();
+                    match limit.filter(|limit| *limit > 0) {
+                        Some(limit) => {
+                            preferences.file_download_speed_limits.insert(file_id, limit);
+                        }
+                        None => {
+                            preferences.file_download_speed_limits.remove(&file_id);
+                        }
+                    }
+                    let r = preferences.save();
+                    let _ = self.control_tx.send(DownloadControlCommand::BandwidthChanged);
+                    let _ = tx.send(r);
+                }
+
+                ModelManagementCommand::SetDownloadWindows(windows, tx) => {
+                    let mut preferences = self.preferences.lock().unwrap();
+                    preferences.download_windows = windows;
+                    let r = preferences.save();
+                    let _ = self.control_tx.send(DownloadControlCommand::QueueChanged);
+                    let _ = tx.send(r);
+                }
+
+                ModelManagementCommand::GetBandwidthSettings(tx) => {
+                    let preferences = self.preferences.lock().unwrap();
+                    let _ = tx.send(Ok(BandwidthSettings {
+                        max_bytes_per_second: preferences.max_download_speed,
+                        file_max_bytes_per_second: preferences.file_download_speed_limits.clone(),
+                        windows: preferences.download_windows.clone(),
+                    }));
+                }
+
+                ModelManagementCommand::SetAccessToken(token, tx) => {
+                    let mut credentials = self.credentials.lock().unwrap();
+                    let _ = tx.send(credentials.set_token(token));
+                }
+
+                ModelManagementCommand::HasAccessToken(tx) => {
+                    let credentials = self.credentials.lock().unwrap();
+                    let _ = tx.send(Ok(credentials.token().is_some()));
+                }
+
+                ModelManagementCommand::SetProxyConfig(config, tx) => {
+                    // Reject a configuration no client can be built from
+                    let r = store::network::async_client(&config).and_then(|_| {
+                        let mut preferences = self.preferences.lock().unwrap();
+                        preferences.proxy = config;
+                        preferences.save()
+                    });
+                    if r.is_ok() {
+                        let _ = self.control_tx.send(DownloadControlCommand::ProxyChanged);
+                    }
+                    let _ = tx.send(r);
+                }
+
+                ModelManagementCommand::GetProxyConfig(tx) => {
+                    let preferences = self.preferences.lock().unwrap();
+                    let _ = tx.send(Ok(preferences.proxy.clone()));
+                }
+
+                ModelManagementCommand::SetModelsDirQuota(quota, tx) => {
+                    let mut preferences = self.preferences.lock().unwrap();
+                    preferences.models_dir_quota = quota.filter(|quota| *quota > 0);
+                    let _ = tx.send(preferences.save());
+                }
+
+                ModelManagementCommand::GetStorageInfo(tx) => {
+                    let quota = self.preferences.lock().unwrap().models_dir_quota;
+                    let models_dir = self.models_dir();
+                    let r = store::disk_space::available_space(&models_dir).map(
+                        |available_space| StorageInfo {
+                            models_dir_size: store::disk_space::directory_size(&models_dir),
+                            available_space,
+                            quota,
+                        },
+                    );
+                    let _ = tx.send(r.map_err(|e| anyhow::anyhow!("Get storage info error: {e}")));
+                }
+
                 ModelManagementCommand::DeleteFile(file_id, tx) => {
+                    {
+                        let conn = self.sql_conn.lock().unwrap();
+                        let imported =
+                            store::download_files::DownloadedFile::get_by_id(&conn, &file_id)
+                                .is_ok_and(|file| file.imported);
+                        if imported {
+                            let r = store::download_files::DownloadedFile::remove(&file_id, &conn);
+                            let _ = tx.send(r.map_err(Into::into));
+                            return;
+                        }
+                    }
+
+                    let download_dir = match self.writable_dir_of(&file_id) {
+                        Ok(download_dir) => download_dir,
+                        Err(e) => {
+                            let _ = tx.send(Err(e));
+                            return;
+                        }
+                    };
+
                     {
                         let conn = self.sql_conn.lock().unwrap();
                         let _ = store::download_files::DownloadedFile::remove(&file_id, &conn);
+                        let _ = store::download_segments::DownloadSegment::remove_by_file(
+                            &conn, &file_id,
+                        );
+                        let _ = store::remove_file_shards(
+                            &conn,
+                            &download_dir.to_string_lossy(),
+                            &file_id,
+                        );
                     }
 
-                    let _ = store::remove_downloaded_file(
-                        self.models_dir.to_string_lossy().to_string(),
-                        file_id,
-                    );
+                    // The records are gone already, a file left behind shows up in
+                    // the library check
+                    if let Err(e) = store::remove_downloaded_file(
+                        download_dir.to_string_lossy().to_string(),
+                        file_id.clone(),
+                    ) {
+                        log::warn!("Failed to remove the file of {file_id}: {e}");
+                    }
                     let _ = tx.send(Ok(()));
                 }
 
+                ModelManagementCommand::ImportLocalFiles(paths, tx) => {
+                    self.import_local_files(paths, tx);
+                }
+
+                ModelManagementCommand::CheckLibrary(repair, tx) => {
+                    let _ = tx.send(self.check_library(repair));
+                }
+
+                ModelManagementCommand::SetLibraryRoots(roots, tx) => {
+                    {
+                        let mut preferences = self.preferences.lock().unwrap();
+                        preferences.library_roots = roots;
+                        if let Err(e) = preferences.save() {
+                            let _ = tx.send(Err(e));
+                            return;
+                        }
+                    }
+                    self.scan_library_roots();
+                    let _ = tx.send(Ok(()));
+                }
+
+                ModelManagementCommand::GetLibraryRoots(tx) => {
+                    let _ = tx.send(Ok(self.library_roots()));
+                }
+
+                ModelManagementCommand::SetDownloadRoot(path, tx) => {
+                    let writable = match &path {
+                        Some(path) => self.is_writable_root(path),
+                        None => true,
+                    };
+                    if !writable {
+                        let _ = tx.send(Err(anyhow::anyhow!(
+                            "{:?} is not a writable library root",
+                            path.unwrap_or_default()
+                        )));
+                        return;
+                    }
+
+                    let mut preferences = self.preferences.lock().unwrap();
+                    preferences.download_root = path;
+                    let _ = tx.send(preferences.save());
+                }
+
+                ModelManagementCommand::SetCatalogSources(sources, tx) => {
+                    // Not holding the lock while the sources load
+                    let (r, preferences) = {
+                        let mut preferences = self.preferences.lock().unwrap();
+                        preferences.catalog_sources = sources;
+                        (preferences.save(), preferences.clone())
+                    };
+                    self.model_indexs.set_sources(&preferences);
+                    self.remember_model_cards_mirror();
+                    let _ = tx.send(r);
+                }
+
+                ModelManagementCommand::GetCatalogSources(tx) => {
+                    let preferences = self.preferences.lock().unwrap();
+                    let _ = tx.send(Ok(preferences.catalog_sources.clone()));
+                }
+
+                ModelManagementCommand::SetOfflineMode(offline, tx) => {
+                    let (r, preferences) = {
+                        let mut preferences = self.preferences.lock().unwrap();
+                        preferences.offline_mode = offline;
+                        (preferences.save(), preferences.clone())
+                    };
+                    self.model_indexs.set_offline(offline, &preferences);
+                    self.remember_model_cards_mirror();
+                    let _ = tx.send(r);
+                }
+
+                ModelManagementCommand::GetCatalogStatus(tx) => {
+                    let _ = tx.send(Ok(CatalogStatus {
+                        offline: self.model_indexs.is_offline(),
+                        updated_at: self.model_indexs.updated_at(),
+                    }));
+                }
+
+                ModelManagementCommand::RefreshCatalog(tx) => {
+                    let preferences = self.preferences.lock().unwrap().clone();
+                    let _ = tx.send(self.model_indexs.sync(&preferences));
+                }
+
+                ModelManagementCommand::WatchCatalog(tx) => {
+                    self.model_indexs.watch(tx);
+                }
+
+                ModelManagementCommand::SetModelCardsRevision(revision, tx) => {
+                    let (r, preferences) = {
+                        let mut preferences = self.preferences.lock().unwrap();
+                        preferences.model_cards_revision = revision
+                            .map(|revision| revision.trim().to_string())
+                            .filter(|revision| !revision.is_empty());
+                        (preferences.save(), preferences.clone())
+                    };
+                    if r.is_ok() && !self.model_indexs.is_offline() {
+                        if let Err(e) = self.model_indexs.sync(&preferences) {
+                            log::warn!("Failed to sync the pinned catalog: {e}");
+                        }
+                    }
+                    let _ = tx.send(r);
+                }
+
+                ModelManagementCommand::GetModelCardsRevision(tx) => {
+                    let preferences = self.preferences.lock().unwrap();
+                    let _ = tx.send(Ok(preferences.model_cards_revision.clone()));
+                }
+
+                ModelManagementCommand::RollbackCatalog(tx) => {
+                    let r = {
+                        let mut preferences = self.preferences.lock().unwrap();
+                        self.model_indexs
+                            .rollback(&mut preferences)
+                            .and_then(|revision| preferences.save().map(|_| revision))
+                    };
+                    let _ = tx.send(r);
+                }
+
+                ModelManagementCommand::VerifyFile(file_id, tx) => {
+                    let file = {
+                        let conn = self.sql_conn.lock().unwrap();
+                        store::download_files::DownloadedFile::get_by_id(&conn, &file_id).and_then(
+                            |file| {
+                                let shards = store::download_shards::DownloadShard::get_by_file(
+                                    &conn, &file_id,
+                                )?;
+                                Ok((file, shards))
+                            },
+                        )
+                    };
+
+                    match file {
+                        Ok((file, shards)) if file.downloaded => {
+                            // Hashing a multi-GB file takes a while, keep the command loop free
+                            self.async_rt.spawn_blocking(move || {
+                                let _ = tx.send(store::verify_downloaded_file(&file, &shards));
+                            });
+                        }
+                        Ok(_) => {
+                            let _ = tx.send(Err(anyhow::anyhow!("File is not downloaded yet")));
+                        }
+                        Err(e) => {
+                            let _ = tx.send(Err(anyhow::anyhow!("Verify file error: {e}")));
+                        }
+                    }
+                }
+
                 ModelManagementCommand::GetDownloadedFiles(tx) => {
                     let downloads = {
                         let conn = self.sql_conn.lock().unwrap();
@@ -644,18 +1175,33 @@ impl<Model: BackendModel + Send + 'static> BackendImpl<Model> {
                     let _ = tx.send(pending_downloads);
                 }
 
-                ModelManagementCommand::ChangeModelsLocation(path) => self.update_models_dir(path),
+                ModelManagementCommand::ChangeModelsLocation(path, tx) => {
+                    self.change_models_dir(path, tx)
+                }
             },
             BuiltInCommand::Interaction(model_cmd) => match model_cmd {
                 ModelInteractionCommand::LoadModel(file_id, options, tx) => {
-                    let conn = self.sql_conn.lock().unwrap();
-                    let download_file =
-                        store::download_files::DownloadedFile::get_by_id(&conn, &file_id);
+                    self.stop_resources_watch();
+                    // Not holding the connection while the model loads
+                    let download_file = {
+                        let conn = self.sql_conn.lock().unwrap();
+                        store::download_files::DownloadedFile::get_by_id(&conn, &file_id).map(
+                            |mut file| {
+                                store::load_information(&conn, &mut file);
+                                file
+                            },
+                        )
+                    };
 
                     match download_file {
                         Ok(file) => {
+                            send_load_progress(&tx, &file_id, LoadModelStage::Preloading);
                             nn_preload_file(&file, self.model_indexs.embedding_model());
                             let old_model = self.model.take();
+                            let resources_tx = tx.clone();
+                            // Nothing is loaded until the new model says so
+                            *self.loaded_model.lock().unwrap() = None;
+                            let tx = self.track_load(file_id.clone(), tx);
 
                             let model = Model::new_or_reload(
                                 &self.async_rt,
@@ -666,6 +1212,10 @@ impl<Model: BackendModel + Send + 'static> BackendImpl<Model> {
                                 self.model_indexs.embedding_model(),
                             );
                             self.model = Some(model);
+
+                            let watch = Arc::new(AtomicBool::new(false));
+                            resource_usage::watch(resources_tx, watch.clone());
+                            self.resources_watch = Some(watch);
                         }
                         Err(e) => {
                             let _ = tx.send(Err(anyhow::anyhow!("Load model error: {e}")));
@@ -673,9 +1223,11 @@ impl<Model: BackendModel + Send + 'static> BackendImpl<Model> {
                     }
                 }
                 ModelInteractionCommand::EjectModel(tx) => {
+                    self.stop_resources_watch();
                     if let Some(model) = self.model.take() {
                         model.stop(&self.async_rt);
                     }
+                    *self.loaded_model.lock().unwrap() = None;
                     let _ = tx.send(Ok(()));
                 }
                 ModelInteractionCommand::Chat(data, tx) => {
@@ -691,19 +1243,495 @@ impl<Model: BackendModel + Send + 'static> BackendImpl<Model> {
                         .map(|model| model.stop_chat(&self.async_rt));
                     let _ = tx.send(Ok(()));
                 }
-                ModelInteractionCommand::StartLocalServer(_, _) => todo!(),
-                ModelInteractionCommand::StopLocalServer(_) => todo!(),
+                ModelInteractionCommand::StartLocalServer(config, tx) => {
+                    // Restart the server if it is already running, the config may have changed
+                    if let Some(server) = self.local_server.take() {
+                        server.stop(&self.async_rt);
+                    }
+
+                    match local_server::LocalServer::start(
+                        &self.async_rt,
+                        config,
+                        self.command_tx.clone(),
+                        tx.clone(),
+                        self.loaded_model.clone(),
+                    ) {
+                        Ok(server) => {
+                            log::info!("local server started on port {}", server.config.port);
+                            self.local_server = Some(server);
+                        }
+                        Err(e) => {
+                            let _ = tx.send(Err(e));
+                        }
+                    }
+                }
+                ModelInteractionCommand::StopLocalServer(tx) => {
+                    if let Some(server) = self.local_server.take() {
+                        server.stop(&self.async_rt);
+                    }
+                    let _ = tx.send(Ok(()));
+                }
             },
         }
     }
 
-    pub fn update_models_dir<M: AsRef<Path>>(&mut self, models_dir: M) {
-        self.models_dir = models_dir.as_ref().to_path_buf();
+    /// A sender for the load of `file_id` that forwards everything to `tx`, keeping
+    /// `loaded_model` in step: set once the load completes, cleared if it fails.
+    fn track_load(
+        &self,
+        file_id: FileID,
+        tx: Sender<anyhow::Result<LoadModelResponse>>,
+    ) -> Sender<anyhow::Result<LoadModelResponse>> {
+        let (load_tx, load_rx) = std::sync::mpsc::channel();
+        let loaded_model = self.loaded_model.clone();
+        std::thread::spawn(move || {
+            for response in load_rx {
+                match &response {
+                    Ok(LoadModelResponse::Completed(_)) => {
+                        *loaded_model.lock().unwrap() = Some(file_id.clone());
+                    }
+                    Err(_) => {
+                        let mut loaded_model = loaded_model.lock().unwrap();
+                        // A later load may have replaced it already
+                        if loaded_model.as_ref() == Some(&file_id) {
+                            *loaded_model = None;
+                        }
+                    }
+                    _ => {}
+                }
+                let _ = tx.send(response);
+            }
+        });
+        load_tx
+    }
+
+    fn models_dir(&self) -> PathBuf {
+        self.models_dir.lock().unwrap().clone()
+    }
+
+    /// Every library root, the models directory first.
+    fn library_roots(&self) -> Vec<LibraryRoot> {
+        let models_dir = self.models_dir();
+        let preferences = self.preferences.lock().unwrap();
+
+        let mut roots = vec![LibraryRoot {
+            path: models_dir.clone(),
+            read_only: false,
+        }];
+        roots.extend(
+            preferences
+                .library_roots
+                .iter()
+                .filter(|root| root.path != models_dir)
+                .cloned(),
+        );
+        roots
+    }
+
+    /// Where new downloads go: the chosen writable root, or the models directory.
+    fn download_dir(&self) -> PathBuf {
+        let download_root = self.preferences.lock().unwrap().download_root.clone();
+        download_root
+            .filter(|path| self.is_writable_root(path))
+            .unwrap_or_else(|| self.models_dir())
+    }
+
+    fn is_writable_root(&self, path: &Path) -> bool {
+        self.library_roots()
+            .iter()
+            .any(|root| root.path == path && !root.read_only)
+    }
+
+    /// Directory `file_id` was saved to, refusing the read-only roots.
+    fn writable_dir_of(&self, file_id: &str) -> anyhow::Result<PathBuf> {
+        let download_dir = {
+            let conn = self.sql_conn.lock().unwrap();
+            store::download_files::DownloadedFile::get_by_id(&conn, file_id)
+                .map(|file| PathBuf::from(file.download_dir))
+        };
+        let Ok(download_dir) = download_dir else {
+            return Ok(self.models_dir());
+        };
+
+        let read_only = self
+            .library_roots()
+            .iter()
+            .any(|root| root.path == download_dir && root.read_only);
+        if read_only {
+            return Err(anyhow::anyhow!(
+                "{file_id} is in the read-only library {:?}",
+                download_dir
+            ));
+        }
+        Ok(download_dir)
+    }
+
+    fn stop_resources_watch(&mut self) {
+        if let Some(watch) = self.resources_watch.take() {
+            watch.store(true, Ordering::Relaxed);
+        }
+    }
+
+    /// Send the models `find` picks from the catalog, found and loaded on a thread
+    /// of their own as remote sources answer slowly. The database is only locked
+    /// once the cards are loaded.
+    fn send_models(
+        &self,
+        tx: Sender<anyhow::Result<Vec<moly_protocol::data::Model>>>,
+        find: impl FnOnce(&Catalog) -> Vec<ModelIndex> + Send + 'static,
+    ) {
+        let catalog = self.model_indexs.catalog();
+        let sql_conn = self.sql_conn.clone();
+        std::thread::spawn(move || {
+            let indexs = find(&catalog);
+            log::debug!("found models: {}", indexs.len());
+            let cards = catalog.load_cards(&indexs);
+            let sql_conn = sql_conn.lock().unwrap();
+            let models = ModelCard::to_model(&cards, &sql_conn, &SystemInfo::detect())
+                .map_err(|e| anyhow::anyhow!("search models error: {e}"));
+            let _ = tx.send(models);
+        });
+    }
+
+    /// Keep the mirror the catalog was last synced from, in case none answers on
+    /// the next start.
+    fn remember_model_cards_mirror(&self) {
+        let Some(mirror) = self.model_indexs.mirror() else {
+            return;
+        };
+        let mut preferences = self.preferences.lock().unwrap();
+        if preferences.model_cards_mirror.as_deref() != Some(mirror) {
+            preferences.model_cards_mirror = Some(mirror.to_string());
+            if let Err(e) = preferences.save() {
+                log::error!("Failed to save the model cards mirror: {e}");
+            }
+        }
+    }
+
+    fn check_library(&self, repair: bool) -> anyhow::Result<Vec<LibraryIssue>> {
+        let read_only_roots: Vec<_> = self
+            .library_roots()
+            .into_iter()
+            .filter(|root| root.read_only)
+            .map(|root| root.path)
+            .collect();
+        let conn = self.sql_conn.lock().unwrap();
+        store::library::check_library(&conn, &self.models_dir(), &read_only_roots, repair)
+    }
+
+    /// Import the files on a thread of its own, so other commands are still answered
+    /// while files that are not recognized by name get hashed.
+    fn import_local_files(&self, paths: Vec<PathBuf>, tx: Sender<anyhow::Result<ImportResponse>>) {
+        let catalog = self.model_indexs.catalog();
+        let sql_conn = self.sql_conn.clone();
+
+        std::thread::spawn(move || {
+            let cards = catalog.cards();
+            let files = store::import::gguf_files(&paths);
+            let total = files.len();
+            let mut imported = Vec::with_capacity(total);
+            for path in files {
+                let _ = tx.send(Ok(ImportResponse::Progress {
+                    imported: imported.len(),
+                    total,
+                }));
+                let r = Self::import_local_file(&catalog, &sql_conn, &cards, &path).unwrap_or_else(
+                    |e| ImportedFile::Failed {
+                        path,
+                        reason: e.to_string(),
+                    },
+                );
+                imported.push(r);
+            }
+            let _ = tx.send(Ok(ImportResponse::Completed(imported)));
+        });
+    }
+
+    /// List the GGUF file at `path` as downloaded, under its model from the catalog
+    /// when there is one.
+    fn import_local_file(
+        catalog: &Catalog,
+        sql_conn: &Mutex<rusqlite::Connection>,
+        cards: &[store::model_cards::ModelCard],
+        path: &Path,
+    ) -> anyhow::Result<ImportedFile> {
+        let path = path.canonicalize()?;
+        let metadata = store::gguf::read_metadata(&path)?;
+        let dir = path
+            .parent()
+            .ok_or_else(|| anyhow::anyhow!("Invalid file path {:?}", path))?;
+
+        let (model, file, matched) = match store::import::find_in_catalog(cards, &path) {
+            Some((card, remote_file)) => {
+                let file_id = format!("{}#{}", card.id, remote_file.name);
+                let (model, mut file, _) = Self::catalog_entry(catalog, &file_id, dir)?;
+                // Renamed copies are found by their checksum
+                file.name = path
+                    .file_name()
+                    .map(|name| name.to_string_lossy().to_string())
+                    .unwrap_or(file.name);
+                file.file_size = std::fs::metadata(&path)?.len();
+                file.imported = true;
+                if !remote_file.shards.is_empty() {
+                    // Only the first part is listed, its checksum is not the one of
+                    // the whole file
+                    file.sha256.clear();
+                }
+                file.mark_downloads();
+                (model, file, true)
+            }
+            None => {
+                let (model, file) = store::import::local_entry(&path, &metadata)?;
+                (model, file, false)
+            }
+        };
+
+        let conn = sql_conn.lock().unwrap();
+        let file_id = file.id.to_string();
+        if store::download_files::DownloadedFile::get_by_id(&conn, &file_id).is_ok() {
+            return Ok(ImportedFile::AlreadyKnown { path, file_id });
+        }
+
+        model.save_to_db(&conn)?;
+        file.insert_into_db(&conn)?;
+        log::info!("Imported {:?} as {file_id}", path);
+
+        Ok(if matched {
+            ImportedFile::Matched { path, file_id }
+        } else {
+            ImportedFile::Unmatched { path, file_id }
+        })
+    }
+
+    /// The model and the file `file_id` as published in the catalog, to be saved
+    /// in `download_dir`.
+    fn catalog_entry(
+        catalog: &Catalog,
+        file_id: &str,
+        download_dir: &Path,
+    ) -> anyhow::Result<(
+        store::models::Model,
+        store::download_files::DownloadedFile,
+        Vec<store::download_shards::DownloadShard>,
+    )> {
+        let (model_id, file) = file_id
+            .split_once("#")
+            .ok_or_else(|| anyhow::anyhow!("Illegal file_id"))?;
+
+        let index = catalog
+            .find(model_id)
+            .ok_or(anyhow::anyhow!("No model found"))?;
+        let remote_model = catalog.load_card(&index)?;
+
+        let remote_file = remote_model
+            .files
+            .into_iter()
+            .find(|f| f.name == file)
+            .ok_or_else(|| anyhow::anyhow!("file not found"))?;
+
+        if remote_file
+            .shards
+            .first()
+            .is_some_and(|shard| shard.name != file)
+        {
+            return Err(anyhow::anyhow!(
+                "The first shard of {file_id} must be named {file}"
+            ));
+        }
+        let shards = remote_file
+            .shards
+            .into_iter()
+            .enumerate()
+            .map(|(idx, shard)| store::download_shards::DownloadShard {
+                file_id: file_id.to_string(),
+                idx: idx as u32,
+                name: shard.name,
+                download_url: shard.download.default,
+                sha256: shard.sha256.unwrap_or_default(),
+                ..Default::default()
+            })
+            .collect();
+
+        let download_model = store::models::Model {
+            id: Arc::new(remote_model.id),
+            name: remote_model.name,
+            summary: remote_model.summary,
+            size: remote_model.size,
+            requires: remote_model.requires,
+            architecture: remote_model.architecture,
+            released_at: remote_model.released_at,
+            prompt_template: remote_model.prompt_template.clone(),
+            reverse_prompt: remote_model.reverse_prompt.clone(),
+            author: Arc::new(store::model_cards::Author {
+                name: remote_model.author.name,
+                url: remote_model.author.url,
+                description: remote_model.author.description,
+            }),
+            like_count: remote_model.like_count,
+            download_count: remote_model.download_count,
+        };
+
+        let download_file = store::download_files::DownloadedFile {
+            id: Arc::new(file_id.to_string()),
+            model_id: model_id.to_string(),
+            name: file.to_string(),
+            size: remote_file.size,
+            quantization: remote_file.quantization,
+            prompt_template: remote_model.prompt_template,
+            reverse_prompt: remote_model.reverse_prompt,
+            context_size: remote_model.context_size,
+            downloaded: false,
+            file_size: 0,
+            download_dir: download_dir.to_string_lossy().to_string(),
+            downloaded_at: Utc::now(),
+            tags: remote_file.tags,
+            featured: false,
+            sha256: remote_file.sha256.unwrap_or_default(),
+            download_url: remote_file.download.default,
+            status: store::download_files::DownloadStatus::Queued,
+            queue_position: 0,
+            imported: false,
+            information: String::new(),
+        };
+
+        Ok((download_model, download_file, shards))
+    }
+
+    /// List the files of the catalog found in the library roots as downloaded, and
+    /// forget those that are gone.
+    fn scan_library_roots(&mut self) {
+        // Only models known without the network, this runs on the backend thread
+        let catalog = self.model_indexs.catalog().without_remote();
+        let models_dir = self.models_dir();
+        for root in self.library_roots() {
+            if root.path == models_dir {
+                continue;
+            }
+
+            {
+                let conn = self.sql_conn.lock().unwrap();
+                if let Err(e) = store::library::forget_missing(&conn, &root.path) {
+                    log::error!("Failed to update the files of {:?}: {e}", root.path);
+                }
+            }
+
+            for model_id in store::library::model_ids(&root.path) {
+                let Some(index) = catalog.find(&model_id) else {
+                    continue;
+                };
+                let Ok(card) = catalog.load_card(&index) else {
+                    continue;
+                };
+                for remote_file in card.files {
+                    let file_id = format!("{model_id}#{}", remote_file.name);
+                    if let Err(e) = self.add_library_file(&catalog, &file_id, &root.path) {
+                        log::warn!("Failed to add {file_id} from {:?}: {e}", root.path);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Save `file_id` as downloaded in `root` if all its parts are there. Files the
+    /// app already knows about are left alone.
+    fn add_library_file(
+        &mut self,
+        catalog: &Catalog,
+        file_id: &str,
+        root: &Path,
+    ) -> anyhow::Result<()> {
+        {
+            let conn = self.sql_conn.lock().unwrap();
+            if store::download_files::DownloadedFile::get_by_id(&conn, file_id).is_ok() {
+                return Ok(());
+            }
+        }
+
+        let (model, mut file, mut shards) = Self::catalog_entry(catalog, file_id, root)?;
+        let parts = store::download_shards::parts_of(&file, &shards);
+        let Some(sizes) = store::library::part_sizes(&parts) else {
+            return Ok(());
+        };
+
+        for (shard, size) in shards.iter_mut().zip(&sizes) {
+            shard.file_size = *size;
+            shard.downloaded = true;
+        }
+        file.file_size = sizes.iter().sum();
+        file.mark_downloads();
+
+        let conn = self.sql_conn.lock().unwrap();
+        model.save_to_db(&conn)?;
+        file.insert_into_db(&conn)?;
+        for shard in &shards {
+            shard.insert_into_db(&conn)?;
+        }
+        Ok(())
+    }
+
+    /// Move the files to `new_dir` on a thread of its own, so other commands are
+    /// still answered meanwhile. The download queue is suspended until it is done.
+    fn change_models_dir(
+        &self,
+        new_dir: PathBuf,
+        tx: Sender<anyhow::Result<ModelsDirMigrationResponse>>,
+    ) {
+        let sql_conn = self.sql_conn.clone();
+        let models_dir = self.models_dir.clone();
+        let downloader = self.downloader.clone();
+        let library_roots: Vec<_> = self
+            .library_roots()
+            .into_iter()
+            .skip(1)
+            .map(|root| root.path)
+            .collect();
+
+        // Before returning, so that no download is added to the old directory
+        downloader.suspend();
+        std::thread::spawn(move || {
+            // Wait for the running downloads to let go of their files
+            let start = Instant::now();
+            while !downloader.is_idle() {
+                if start.elapsed() > MIGRATION_STOP_TIMEOUT {
+                    downloader.resume();
+                    let _ = tx.send(Err(anyhow::anyhow!(
+                        "Downloads did not stop, the models directory was not changed"
+                    )));
+                    return;
+                }
+                std::thread::sleep(Duration::from_millis(100));
+            }
+
+            let mut report = |moved_bytes, total_bytes| {
+                let _ = tx.send(Ok(ModelsDirMigrationResponse::Progress {
+                    moved_bytes,
+                    total_bytes,
+                }));
+            };
+            let r = store::models_dir::migrate_models_dir(
+                &sql_conn,
+                &new_dir,
+                &library_roots,
+                &mut report,
+            );
+            if r.is_ok() {
+                *models_dir.lock().unwrap() = new_dir;
+            }
+
+            downloader.resume();
+            let _ = tx.send(r.map(|_| ModelsDirMigrationResponse::Completed));
+        });
     }
 
     fn run_loop(&mut self) {
         loop {
             if let Ok(cmd) = self.rx.recv() {
+                // A background sync may have finished since the last command
+                if self.model_indexs.poll_sync() {
+                    self.remember_model_cards_mirror();
+                    // The shared roots may hold files of models new to the catalog
+                    self.scan_library_roots();
+                }
                 self.handle_command(cmd.into());
             } else {
                 break;
@@ -718,9 +1746,7 @@ pub fn nn_preload_file(
     file: &store::download_files::DownloadedFile,
     embedding: Option<(PathBuf, u64)>,
 ) {
-    let file_path = Path::new(&file.download_dir)
-        .join(&file.model_id)
-        .join(&file.name);
+    let file_path = file.path();
 
     let preloads = wasmedge_sdk::plugin::NNPreload::new(
         "moly-chat",
@@ -742,3 +1768,23 @@ pub fn nn_preload_file(
 
     wasmedge_sdk::plugin::PluginManager::nn_preload(preload_vec);
 }
+
+/// Report the stage a model load reached. The progress is the share of the
+/// stages done, as nothing in between can be measured.
+pub fn send_load_progress(
+    tx: &Sender<anyhow::Result<LoadModelResponse>>,
+    file_id: &str,
+    stage: LoadModelStage,
+) {
+    let progress = match stage {
+        LoadModelStage::Preloading => 0.0,
+        LoadModelStage::Instantiating => 1.0 / 3.0,
+        LoadModelStage::StartingServer => 2.0 / 3.0,
+        LoadModelStage::Ready => 1.0,
+    };
+    let _ = tx.send(Ok(LoadModelResponse::Progress(
+        file_id.to_string(),
+        stage,
+        progress,
+    )));
+}
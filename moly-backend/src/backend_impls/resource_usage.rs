@@ -0,0 +1,170 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::Sender,
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use moly_protocol::protocol::{LoadModelResponse, ModelResourcesInfo};
+
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Usage of the process the models run in. The models are not isolated, so what
+/// the app itself uses is counted too.
+pub struct ResourceSampler {
+    last_cpu_time: Duration,
+    last_sample: Instant,
+}
+
+impl ResourceSampler {
+    pub fn new() -> Self {
+        Self {
+            last_cpu_time: process_cpu_time().unwrap_or_default(),
+            last_sample: Instant::now(),
+        }
+    }
+
+    pub fn sample(&mut self) -> Option<ModelResourcesInfo> {
+        let cpu_time = process_cpu_time()?;
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last_sample).as_secs_f32();
+        let used = cpu_time.saturating_sub(self.last_cpu_time).as_secs_f32();
+        self.last_cpu_time = cpu_time;
+        self.last_sample = now;
+
+        Some(ModelResourcesInfo {
+            ram_usage: process_resident_memory()?,
+            cpu_usage: if elapsed > 0.0 {
+                used / elapsed * 100.0
+            } else {
+                0.0
+            },
+        })
+    }
+}
+
+/// Send the usage to `tx` every few seconds on a thread of its own, until `stop`
+/// is set or nobody listens anymore.
+pub fn watch(tx: Sender<anyhow::Result<LoadModelResponse>>, stop: Arc<AtomicBool>) {
+    std::thread::spawn(move || {
+        let mut sampler = ResourceSampler::new();
+        loop {
+            std::thread::sleep(SAMPLE_INTERVAL);
+            if stop.load(Ordering::Relaxed) {
+                break;
+            }
+            let Some(usage) = sampler.sample() else {
+                break;
+            };
+            if tx
+                .send(Ok(LoadModelResponse::ModelResourcesUsage(usage)))
+                .is_err()
+            {
+                break;
+            }
+        }
+    });
+}
+
+#[cfg(unix)]
+fn process_cpu_time() -> Option<Duration> {
+    let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+    // SAFETY: `usage` is a valid rusage struct
+    if unsafe { libc::getrusage(libc::RUSAGE_SELF, &mut usage) } != 0 {
+        return None;
+    }
+    let to_duration = |time: libc::timeval| {
+        Duration::from_secs(time.tv_sec as u64) + Duration::from_micros(time.tv_usec as u64)
+    };
+    Some(to_duration(usage.ru_utime) + to_duration(usage.ru_stime))
+}
+
+#[cfg(target_os = "linux")]
+fn process_resident_memory() -> Option<u64> {
+    let statm = std::fs::read_to_string("/proc/self/statm").ok()?;
+    let resident_pages = statm.split_whitespace().nth(1)?.parse::<u64>().ok()?;
+    // SAFETY: sysconf has no preconditions
+    let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) };
+    Some(resident_pages * page_size.max(0) as u64)
+}
+
+#[cfg(target_os = "macos")]
+fn process_resident_memory() -> Option<u64> {
+    let mut info: libc::proc_taskinfo = unsafe { std::mem::zeroed() };
+    let size = std::mem::size_of::<libc::proc_taskinfo>() as libc::c_int;
+    // SAFETY: `info` is a valid proc_taskinfo of `size` bytes
+    let written = unsafe {
+        libc::proc_pidinfo(
+            libc::getpid(),
+            libc::PROC_PIDTASKINFO,
+            0,
+            &mut info as *mut libc::proc_taskinfo as *mut libc::c_void,
+            size,
+        )
+    };
+    (written == size).then_some(info.pti_resident_size)
+}
+
+#[cfg(windows)]
+fn process_cpu_time() -> Option<Duration> {
+    use windows_sys::Win32::{
+        Foundation::FILETIME,
+        System::Threading::{GetCurrentProcess, GetProcessTimes},
+    };
+
+    let mut times: [FILETIME; 4] = unsafe { std::mem::zeroed() };
+    let [creation, exit, kernel, user] = &mut times;
+    // SAFETY: the pseudo handle of the current process is always valid
+    if unsafe { GetProcessTimes(GetCurrentProcess(), creation, exit, kernel, user) } == 0 {
+        return None;
+    }
+    // FILETIME counts 100 ns intervals
+    let to_duration = |time: &FILETIME| {
+        let intervals = (time.dwHighDateTime as u64) << 32 | time.dwLowDateTime as u64;
+        Duration::from_nanos(intervals * 100)
+    };
+    Some(to_duration(kernel) + to_duration(user))
+}
+
+#[cfg(windows)]
+fn process_resident_memory() -> Option<u64> {
+    use windows_sys::Win32::System::{
+        ProcessStatus::{GetProcessMemoryInfo, PROCESS_MEMORY_COUNTERS},
+        Threading::GetCurrentProcess,
+    };
+
+    let mut counters: PROCESS_MEMORY_COUNTERS = unsafe { std::mem::zeroed() };
+    let size = std::mem::size_of::<PROCESS_MEMORY_COUNTERS>() as u32;
+    // SAFETY: `counters` is a valid PROCESS_MEMORY_COUNTERS of `size` bytes
+    if unsafe { GetProcessMemoryInfo(GetCurrentProcess(), &mut counters, size) } == 0 {
+        return None;
+    }
+    Some(counters.WorkingSetSize as u64)
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+fn process_resident_memory() -> Option<u64> {
+    None
+}
+
+#[cfg(not(any(unix, windows)))]
+fn process_cpu_time() -> Option<Duration> {
+    None
+}
+
+#[test]
+fn test_resource_usage() {
+    let mut sampler = ResourceSampler::new();
+    // Burn some processor time for the sample to see
+    let mut x = 0u64;
+    for i in 0..10_000_000u64 {
+        x = std::hint::black_box(x.wrapping_add(i * i));
+    }
+    std::thread::sleep(Duration::from_millis(10));
+
+    let usage = sampler.sample().unwrap();
+    assert!(usage.ram_usage > 0);
+    assert!(usage.cpu_usage > 0.0);
+}
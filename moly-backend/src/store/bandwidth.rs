@@ -0,0 +1,95 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// Token bucket holding up to one second worth of bytes. Taking more tokens than
+/// available leaves the bucket in debt, and the caller waits until it is paid off.
+#[derive(Debug)]
+pub struct TokenBucket {
+    // Bytes per second, `None` when unlimited
+    rate: Option<u64>,
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    pub fn new(rate: Option<u64>) -> Self {
+        Self {
+            rate,
+            tokens: rate.unwrap_or(0) as f64,
+            last_refill: Instant::now(),
+        }
+    }
+
+    pub fn set_rate(&mut self, rate: Option<u64>) {
+        if self.rate != rate {
+            *self = Self::new(rate);
+        }
+    }
+
+    /// Take `bytes` tokens and return how long to wait before using them.
+    pub fn take(&mut self, bytes: u64, now: Instant) -> Duration {
+        let Some(rate) = self.rate.filter(|rate| *rate > 0) else {
+            return Duration::ZERO;
+        };
+        let rate = rate as f64;
+
+        let elapsed = now.saturating_duration_since(self.last_refill);
+        self.last_refill = now;
+        self.tokens = (self.tokens + elapsed.as_secs_f64() * rate).min(rate);
+        self.tokens -= bytes as f64;
+
+        if self.tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-self.tokens / rate)
+        }
+    }
+}
+
+/// A token bucket shared between the connections it limits.
+#[derive(Debug, Clone)]
+pub struct BandwidthLimiter(Arc<Mutex<TokenBucket>>);
+
+impl BandwidthLimiter {
+    pub fn new(rate: Option<u64>) -> Self {
+        Self(Arc::new(Mutex::new(TokenBucket::new(rate))))
+    }
+
+    pub fn set_rate(&self, rate: Option<u64>) {
+        self.0.lock().unwrap().set_rate(rate);
+    }
+
+    /// Wait until `bytes` can go through every limiter in `limiters`.
+    pub async fn acquire(limiters: &[BandwidthLimiter], bytes: u64) {
+        for limiter in limiters {
+            let wait = limiter.0.lock().unwrap().take(bytes, Instant::now());
+            if !wait.is_zero() {
+                tokio::time::sleep(wait).await;
+            }
+        }
+    }
+}
+
+#[test]
+fn test_token_bucket() {
+    let start = Instant::now();
+    let mut bucket = TokenBucket::new(Some(1000));
+
+    // One second of burst, then the rate applies
+    assert_eq!(bucket.take(1000, start), Duration::ZERO);
+    assert_eq!(bucket.take(500, start), Duration::from_millis(500));
+    assert_eq!(
+        bucket.take(500, start + Duration::from_millis(500)),
+        Duration::from_millis(500)
+    );
+
+    // Idle time does not build up more than one second of tokens
+    let later = start + Duration::from_secs(10);
+    assert_eq!(bucket.take(1000, later), Duration::ZERO);
+    assert!(bucket.take(1, later) > Duration::ZERO);
+
+    bucket.set_rate(None);
+    assert_eq!(bucket.take(u64::MAX, later), Duration::ZERO);
+}
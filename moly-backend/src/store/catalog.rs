@@ -0,0 +1,791 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+
+use super::{
+    gguf,
+    model_cards::{Author, DownloadUrls, ModelCard, ModelIndex, RemoteFile, RemoteShard},
+};
+
+const HUGGING_FACE_ENDPOINT: &str = "https://huggingface.co";
+
+// Models of the Hub listed without a search, the most downloaded ones
+const HUGGING_FACE_FEATURED: usize = 30;
+
+// Cards loaded at once for a list of results, each one a request to the Hub
+const CARD_LOAD_THREADS: usize = 8;
+
+/// A place model cards are read from. `Catalog` merges the enabled sources, see
+/// `CatalogSourceConfig`.
+pub trait CatalogSource: Send + Sync {
+    /// Name the models of this source are tagged with.
+    fn name(&self) -> &str;
+
+    /// Whether listing the models goes over the network. Such sources are only
+    /// listed when searching, never on the backend thread.
+    fn is_remote(&self) -> bool {
+        false
+    }
+
+    /// Models listed without a search, the featured ones among them.
+    fn indexes(&self) -> anyhow::Result<Vec<ModelIndex>>;
+
+    fn load_card(&self, index: &ModelIndex) -> anyhow::Result<ModelCard>;
+
+    /// At most `limit` chat models matching `text`.
+    fn search(&self, text: &str, limit: usize) -> anyhow::Result<Vec<ModelIndex>> {
+        Ok(self
+            .indexes()?
+            .into_iter()
+            .filter(|index| index.is_chat() && index.matches(text))
+            .take(limit)
+            .collect())
+    }
+
+    /// The model with `id`, also when `indexes` leaves it out.
+    fn find(&self, id: &str) -> anyhow::Result<Option<ModelIndex>> {
+        Ok(self.indexes()?.into_iter().find(|index| index.id == id))
+    }
+}
+
+/// The enabled sources and the models they list, cheap to clone for the searches
+/// to run off the backend thread.
+#[derive(Clone, Default)]
+pub struct Catalog {
+    sources: Vec<Arc<dyn CatalogSource>>,
+    // Models of the sources that are not remote, by id. A model listed by several
+    // sources keeps the first one.
+    indexs: Arc<HashMap<String, ModelIndex>>,
+    // Shared by the clones, dropped with the catalog when the sources change
+    caches: Arc<Mutex<HashMap<String, ModelCard>>>,
+}
+
+impl Catalog {
+    /// List the models of `sources`, the remote ones left for the searches.
+    pub fn new(sources: Vec<Arc<dyn CatalogSource>>) -> Self {
+        let mut indexs = HashMap::new();
+        for source in sources.iter().filter(|source| !source.is_remote()) {
+            let indexes = match source.indexes() {
+                Ok(indexes) => indexes,
+                Err(e) => {
+                    log::error!("Failed to list the models of {}: {e}", source.name());
+                    continue;
+                }
+            };
+            for mut index in indexes {
+                index.source = source.name().to_string();
+                indexs.entry(index.id.clone()).or_insert(index);
+            }
+        }
+        Self {
+            sources,
+            indexs: Arc::new(indexs),
+            caches: Default::default(),
+        }
+    }
+
+    /// The same catalog without the remote sources, for the work that must not wait
+    /// on the network.
+    pub fn without_remote(&self) -> Self {
+        Self {
+            sources: self
+                .sources
+                .iter()
+                .filter(|source| !source.is_remote())
+                .cloned()
+                .collect(),
+            indexs: self.indexs.clone(),
+            caches: self.caches.clone(),
+        }
+    }
+
+    pub fn load_card(&self, index: &ModelIndex) -> anyhow::Result<ModelCard> {
+        if let Some(card) = self.caches.lock().unwrap().get(&index.id) {
+            return Ok(card.clone());
+        }
+
+        let mut last_error = anyhow::anyhow!("No catalog source {} for {}", index.source, index.id);
+        // Several directories can be sources of the same kind
+        for source in self.sources.iter().filter(|s| s.name() == index.source) {
+            match source.load_card(index) {
+                Ok(mut card) => {
+                    card.source = index.source.clone();
+                    let mut caches = self.caches.lock().unwrap();
+                    caches.insert(index.id.clone(), card.clone());
+                    return Ok(card);
+                }
+                Err(e) => last_error = e,
+            }
+        }
+        Err(last_error)
+    }
+
+    /// The cards of `indexes` in the same order, loaded in parallel. The ones that
+    /// fail to load are left out.
+    pub fn load_cards(&self, indexes: &[ModelIndex]) -> Vec<ModelCard> {
+        let next = AtomicUsize::new(0);
+        let cards = Mutex::new(vec![None; indexes.len()]);
+        std::thread::scope(|scope| {
+            for _ in 0..CARD_LOAD_THREADS.min(indexes.len()) {
+                scope.spawn(|| loop {
+                    let i = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(index) = indexes.get(i) else {
+                        break;
+                    };
+                    match self.load_card(index) {
+                        Ok(card) => cards.lock().unwrap()[i] = Some(card),
+                        Err(e) => log::error!("load model card {} error: {e}", index.id),
+                    }
+                });
+            }
+        });
+        cards.into_inner().unwrap().into_iter().flatten().collect()
+    }
+
+    /// The model with `id`, asking the sources for it when it was not listed.
+    pub fn find(&self, id: &str) -> Option<ModelIndex> {
+        if let Some(index) = self.indexs.get(id) {
+            return Some(index.clone());
+        }
+        for source in &self.sources {
+            match source.find(id) {
+                Ok(Some(mut index)) => {
+                    index.source = source.name().to_string();
+                    return Some(index);
+                }
+                Ok(None) => {}
+                Err(e) => log::warn!("Failed to find {id} in {}: {e}", source.name()),
+            }
+        }
+        None
+    }
+
+    /// Every model card listed without the network, the ones that fail to load
+    /// left out.
+    pub fn cards(&self) -> Vec<ModelCard> {
+        self.indexs
+            .values()
+            .filter_map(|index| self.load_card(index).ok())
+            .collect()
+    }
+
+    /// Models of every source matching `search_text`, in the order of the sources.
+    /// A source that fails is left out.
+    pub fn search(&self, search_text: &str, limit: usize, offset: usize) -> Vec<ModelIndex> {
+        let mut found = vec![];
+        let mut seen = HashSet::new();
+        for source in &self.sources {
+            let indexes = match source.search(search_text, offset + limit) {
+                Ok(indexes) => indexes,
+                Err(e) => {
+                    log::error!("Failed to search the models of {}: {e}", source.name());
+                    continue;
+                }
+            };
+            for mut index in indexes {
+                index.source = source.name().to_string();
+                let index = self.indexs.get(&index.id).cloned().unwrap_or(index);
+                if seen.insert(index.id.clone()) {
+                    found.push(index);
+                }
+            }
+        }
+        found.into_iter().skip(offset).take(limit).collect()
+    }
+
+    /// Featured chat models, the ones of the remote sources after the others.
+    pub fn featured(&self, limit: usize, offset: usize) -> Vec<ModelIndex> {
+        let mut featured: Vec<_> = self
+            .indexs
+            .values()
+            .filter(|index| index.is_chat() && index.featured)
+            .cloned()
+            .collect();
+        let mut seen: HashSet<_> = featured.iter().map(|index| index.id.clone()).collect();
+        for source in self.sources.iter().filter(|source| source.is_remote()) {
+            let indexes = match source.indexes() {
+                Ok(indexes) => indexes,
+                Err(e) => {
+                    log::error!("Failed to list the models of {}: {e}", source.name());
+                    continue;
+                }
+            };
+            for mut index in indexes {
+                index.source = source.name().to_string();
+                if index.is_chat() && index.featured && seen.insert(index.id.clone()) {
+                    featured.push(index);
+                }
+            }
+        }
+        featured.into_iter().skip(offset).take(limit).collect()
+    }
+}
+
+/// Model cards in a directory laid out like the model-cards repository. Without an
+/// `index.json`, every card found is listed as a featured chat model.
+pub struct LocalDirectorySource {
+    dir: PathBuf,
+}
+
+impl LocalDirectorySource {
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    fn scan(&self) -> anyhow::Result<Vec<ModelIndex>> {
+        let mut indexes = vec![];
+        for author_dir in sorted_entries(&self.dir)? {
+            if !author_dir.is_dir() {
+                continue;
+            }
+            for path in sorted_entries(&author_dir)? {
+                if path.extension().map_or(true, |ext| ext != "json") {
+                    continue;
+                }
+                let card = std::fs::read_to_string(&path)
+                    .map_err(anyhow::Error::from)
+                    .and_then(|card| Ok(serde_json::from_str::<ModelCard>(&card)?));
+                let card = match card {
+                    Ok(card) => card,
+                    Err(e) => {
+                        log::warn!("Failed to read the model card {:?}: {e}", path);
+                        continue;
+                    }
+                };
+                indexes.push(ModelIndex {
+                    id: card.id,
+                    name: path
+                        .file_stem()
+                        .map(|stem| stem.to_string_lossy().into_owned())
+                        .unwrap_or_default(),
+                    architecture: card.architecture,
+                    model_type: "chat".to_string(),
+                    summary: card.summary,
+                    featured: true,
+                    like_count: card.like_count,
+                    download_count: card.download_count,
+                    source: String::new(),
+                });
+            }
+        }
+        Ok(indexes)
+    }
+}
+
+fn sorted_entries(dir: &Path) -> std::io::Result<Vec<PathBuf>> {
+    let mut paths = std::fs::read_dir(dir)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<std::io::Result<Vec<_>>>()?;
+    paths.sort();
+    Ok(paths)
+}
+
+impl CatalogSource for LocalDirectorySource {
+    fn name(&self) -> &str {
+        "local-directory"
+    }
+
+    fn indexes(&self) -> anyhow::Result<Vec<ModelIndex>> {
+        match std::fs::read_to_string(self.dir.join("index.json")) {
+            Ok(index_list) => Ok(serde_json::from_str(&index_list)?),
+            Err(_) => self.scan(),
+        }
+    }
+
+    fn load_card(&self, index: &ModelIndex) -> anyhow::Result<ModelCard> {
+        index.load_model_card(&self.dir)
+    }
+}
+
+/// GGUF repositories of the Hugging Face Hub, read through its REST API.
+pub struct HuggingFaceSource {
+    endpoint: String,
+    client: reqwest::blocking::Client,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct HubModel {
+    id: String,
+    #[serde(default)]
+    author: Option<String>,
+    #[serde(default)]
+    likes: u32,
+    #[serde(default)]
+    downloads: u32,
+    #[serde(default)]
+    created_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    last_modified: Option<DateTime<Utc>>,
+    #[serde(default, rename = "pipeline_tag")]
+    pipeline_tag: Option<String>,
+    #[serde(default)]
+    siblings: Vec<HubFile>,
+    #[serde(default)]
+    gguf: Option<HubGguf>,
+}
+
+#[derive(Debug, Deserialize)]
+struct HubFile {
+    rfilename: String,
+    #[serde(default)]
+    size: Option<u64>,
+    #[serde(default)]
+    lfs: Option<HubLfs>,
+}
+
+#[derive(Debug, Deserialize)]
+struct HubLfs {
+    sha256: String,
+    size: u64,
+}
+
+// Header of the first GGUF file of the repository, summarized by the Hub
+#[derive(Debug, Deserialize)]
+struct HubGguf {
+    #[serde(default)]
+    total: u64,
+    #[serde(default)]
+    architecture: Option<String>,
+    #[serde(default)]
+    context_length: Option<u64>,
+    #[serde(default)]
+    chat_template: Option<String>,
+}
+
+impl HubModel {
+    /// Embedding and other models that do not chat are left out, models without a
+    /// pipeline tag are kept.
+    fn is_chat(&self) -> bool {
+        match &self.pipeline_tag {
+            Some(tag) => tag == "text-generation",
+            None => true,
+        }
+    }
+
+    fn to_index(&self, featured: bool) -> ModelIndex {
+        ModelIndex {
+            id: self.id.clone(),
+            name: String::new(),
+            architecture: self
+                .gguf
+                .as_ref()
+                .and_then(|gguf| gguf.architecture.clone())
+                .unwrap_or_default(),
+            model_type: "chat".to_string(),
+            summary: String::new(),
+            featured,
+            like_count: self.likes,
+            download_count: self.downloads,
+            source: String::new(),
+        }
+    }
+}
+
+impl HubFile {
+    fn size(&self) -> u64 {
+        match &self.lfs {
+            Some(lfs) => lfs.size,
+            None => self.size.unwrap_or_default(),
+        }
+    }
+
+    fn sha256(&self) -> Option<String> {
+        self.lfs.as_ref().map(|lfs| lfs.sha256.clone())
+    }
+}
+
+impl HuggingFaceSource {
+    /// `endpoint` defaults to huggingface.co.
+    pub fn new(endpoint: Option<String>, client: reqwest::blocking::Client) -> Self {
+        let endpoint = endpoint
+            .as_deref()
+            .map(|endpoint| endpoint.trim().trim_end_matches('/'))
+            .filter(|endpoint| !endpoint.is_empty())
+            .unwrap_or(HUGGING_FACE_ENDPOINT)
+            .to_string();
+        Self { endpoint, client }
+    }
+
+    fn list(&self, search: &str, limit: usize) -> anyhow::Result<Vec<HubModel>> {
+        let limit = limit.to_string();
+        let mut query = vec![
+            ("filter", "gguf"),
+            ("sort", "downloads"),
+            ("direction", "-1"),
+            ("limit", limit.as_str()),
+        ];
+        if !search.is_empty() {
+            query.push(("search", search));
+        }
+        let models = self
+            .client
+            .get(format!("{}/api/models", self.endpoint))
+            .query(&query)
+            .send()?
+            .error_for_status()?
+            .json::<Vec<HubModel>>()?;
+        Ok(models.into_iter().filter(HubModel::is_chat).collect())
+    }
+
+    fn model(&self, id: &str) -> anyhow::Result<Option<HubModel>> {
+        let response = self
+            .client
+            .get(format!("{}/api/models/{id}", self.endpoint))
+            .query(&[("blobs", "true")])
+            .send()?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        Ok(Some(response.error_for_status()?.json()?))
+    }
+
+    fn download_url(&self, id: &str, name: &str) -> DownloadUrls {
+        DownloadUrls {
+            default: format!("{}/{id}/resolve/main/{name}", self.endpoint),
+        }
+    }
+
+    /// The GGUF files at the root of the repository, the parts of split models
+    /// grouped under the first one.
+    fn files(&self, model: &HubModel) -> Vec<RemoteFile> {
+        let mut files = vec![];
+        let mut split_models: BTreeMap<&str, Vec<&HubFile>> = BTreeMap::new();
+        for file in &model.siblings {
+            let name = file.rfilename.as_str();
+            if !name.ends_with(".gguf") || name.contains('/') {
+                continue;
+            }
+            if let Some(prefix) = split_prefix(name) {
+                split_models.entry(prefix).or_default().push(file);
+                continue;
+            }
+            files.push(RemoteFile {
+                name: name.to_string(),
+                size: file.size().to_string(),
+                quantization: quantization_of(name),
+                tags: vec![],
+                sha256: file.sha256(),
+                download: self.download_url(&model.id, name),
+                shards: vec![],
+            });
+        }
+
+        for (_, mut parts) in split_models {
+            parts.sort_by(|a, b| a.rfilename.cmp(&b.rfilename));
+            let first = &parts[0].rfilename;
+            files.push(RemoteFile {
+                name: first.clone(),
+                size: parts
+                    .iter()
+                    .map(|part| part.size())
+                    .sum::<u64>()
+                    .to_string(),
+                quantization: quantization_of(first),
+                tags: vec![],
+                sha256: None,
+                download: self.download_url(&model.id, first),
+                shards: parts
+                    .iter()
+                    .map(|part| RemoteShard {
+                        name: part.rfilename.clone(),
+                        sha256: part.sha256(),
+                        download: self.download_url(&model.id, &part.rfilename),
+                    })
+                    .collect(),
+            });
+        }
+
+        files.sort_by(|a, b| a.name.cmp(&b.name));
+        files
+    }
+}
+
+impl CatalogSource for HuggingFaceSource {
+    fn name(&self) -> &str {
+        "huggingface"
+    }
+
+    fn is_remote(&self) -> bool {
+        true
+    }
+
+    fn indexes(&self) -> anyhow::Result<Vec<ModelIndex>> {
+        Ok(self
+            .list("", HUGGING_FACE_FEATURED)?
+            .iter()
+            .map(|model| model.to_index(true))
+            .collect())
+    }
+
+    fn load_card(&self, index: &ModelIndex) -> anyhow::Result<ModelCard> {
+        let model = self
+            .model(&index.id)?
+            .ok_or_else(|| anyhow::anyhow!("No model {} on {}", index.id, self.endpoint))?;
+
+        let (_, model_name) = model.id.split_once('/').unwrap_or(("", &model.id));
+        let author = model
+            .author
+            .clone()
+            .or_else(|| model.id.split_once('/').map(|(org, _)| org.to_string()))
+            .unwrap_or_default();
+        let gguf = model.gguf.as_ref();
+        let (prompt_template, reverse_prompt) = gguf
+            .and_then(|gguf| gguf.chat_template.as_deref())
+            .and_then(gguf::prompt_template_of)
+            .unwrap_or_default();
+
+        Ok(ModelCard {
+            id: model.id.clone(),
+            name: model_name.to_string(),
+            summary: String::new(),
+            size: gguf::parameters_label(gguf.map(|gguf| gguf.total).unwrap_or_default()),
+            requires: String::new(),
+            architecture: gguf
+                .and_then(|gguf| gguf.architecture.clone())
+                .unwrap_or_default(),
+            released_at: model.created_at.or(model.last_modified).unwrap_or_default(),
+            files: self.files(&model),
+            prompt_template: prompt_template.to_string(),
+            reverse_prompt: reverse_prompt.to_string(),
+            context_size: gguf
+                .and_then(|gguf| gguf.context_length)
+                .unwrap_or_default(),
+            author: Author {
+                url: format!("{}/{author}", self.endpoint),
+                name: author,
+                description: String::new(),
+            },
+            like_count: model.likes,
+            download_count: model.downloads,
+            metrics: None,
+            source: String::new(),
+        })
+    }
+
+    fn search(&self, text: &str, limit: usize) -> anyhow::Result<Vec<ModelIndex>> {
+        Ok(self
+            .list(text.trim(), limit)?
+            .iter()
+            .map(|model| model.to_index(false))
+            .collect())
+    }
+
+    fn find(&self, id: &str) -> anyhow::Result<Option<ModelIndex>> {
+        // Only `<author>/<name>` ids can be repositories of the Hub
+        if id.matches('/').count() != 1 {
+            return Ok(None);
+        }
+        Ok(self.model(id)?.map(|model| model.to_index(false)))
+    }
+}
+
+/// Name shared by the parts of a split model, e.g. `model` for
+/// `model-00001-of-00003.gguf`.
+fn split_prefix(name: &str) -> Option<&str> {
+    let stem = name.strip_suffix(".gguf")?;
+    let mut parts = stem.rsplitn(4, '-');
+    let (Some(total), Some("of"), Some(idx), Some(prefix)) =
+        (parts.next(), parts.next(), parts.next(), parts.next())
+    else {
+        return None;
+    };
+    let is_number = |s: &str| !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit());
+    (is_number(total) && is_number(idx)).then(|| &stem[..prefix.len()])
+}
+
+/// Quantization written in a file name, like `Q4_K_M` in
+/// `Llama-3.2-1B-Instruct-Q4_K_M.gguf`.
+fn quantization_of(name: &str) -> String {
+    let stem = name.strip_suffix(".gguf").unwrap_or(name);
+    stem.rsplit(['-', '.'])
+        .map(str::to_ascii_uppercase)
+        .find(|part| {
+            let digit_after = |prefix: &str| {
+                part.strip_prefix(prefix)
+                    .and_then(|rest| rest.chars().next())
+                    .is_some_and(|c| c.is_ascii_digit())
+            };
+            digit_after("Q") || digit_after("IQ") || ["F16", "F32", "BF16"].contains(&part.as_str())
+        })
+        .unwrap_or_default()
+}
+
+/// Answer requests on a local port with the JSON of the route matching their path,
+/// query left out. Requested paths are recorded in the returned list.
+#[cfg(test)]
+fn serve_json(
+    routes: Vec<(&'static str, String)>,
+) -> (String, std::sync::Arc<std::sync::Mutex<Vec<String>>>) {
+    use std::io::{BufRead, BufReader, Write};
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let endpoint = format!("http://{}", listener.local_addr().unwrap());
+    let requests = std::sync::Arc::new(std::sync::Mutex::new(vec![]));
+    let requested = requests.clone();
+    std::thread::spawn(move || {
+        for mut stream in listener.incoming().flatten() {
+            let mut reader = BufReader::new(&stream);
+            let mut request_line = String::new();
+            let _ = reader.read_line(&mut request_line);
+            let mut header = String::new();
+            while reader.read_line(&mut header).is_ok_and(|n| n > 2) {
+                header.clear();
+            }
+
+            let path = request_line
+                .split(' ')
+                .nth(1)
+                .unwrap_or_default()
+                .to_string();
+            let route = path.split('?').next().unwrap_or_default();
+            let (status, body) = match routes.iter().find(|(prefix, _)| *prefix == route) {
+                Some((_, body)) => ("200 OK", body.clone()),
+                None => ("404 Not Found", "{}".to_string()),
+            };
+            requested.lock().unwrap().push(path);
+            let _ = write!(
+                stream,
+                "HTTP/1.1 {status}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                body.len()
+            );
+        }
+    });
+    (endpoint, requests)
+}
+
+#[test]
+fn test_hugging_face_source() {
+    let search = r#"[
+        {"id": "org/Tiny-GGUF", "likes": 3, "downloads": 100, "pipeline_tag": "text-generation"},
+        {"id": "org/Embed-GGUF", "likes": 1, "downloads": 50, "pipeline_tag": "feature-extraction"}
+    ]"#;
+    let model = r#"{
+        "id": "org/Tiny-GGUF",
+        "author": "org",
+        "likes": 3,
+        "downloads": 100,
+        "createdAt": "2024-05-01T00:00:00.000Z",
+        "pipeline_tag": "text-generation",
+        "gguf": {
+            "total": 1100000000,
+            "architecture": "llama",
+            "context_length": 2048,
+            "chat_template": "<|im_start|>{{ message }}<|im_end|>"
+        },
+        "siblings": [
+            {"rfilename": "README.md", "size": 10},
+            {"rfilename": "Tiny-Q4_K_M.gguf", "size": 700, "lfs": {"sha256": "aa", "size": 700}},
+            {"rfilename": "Tiny-Q8_0-00002-of-00002.gguf", "size": 400, "lfs": {"sha256": "cc", "size": 400}},
+            {"rfilename": "Tiny-Q8_0-00001-of-00002.gguf", "size": 500, "lfs": {"sha256": "bb", "size": 500}},
+            {"rfilename": "old/Tiny-F16.gguf", "size": 2000}
+        ]
+    }"#;
+    let (endpoint, requests) = serve_json(vec![
+        ("/api/models/org/Tiny-GGUF", model.to_string()),
+        ("/api/models", search.to_string()),
+    ]);
+    let client = reqwest::blocking::Client::builder()
+        .no_proxy()
+        .build()
+        .unwrap();
+    let source = HuggingFaceSource::new(Some(format!("{endpoint}/")), client);
+
+    let found = source.search(" tiny ", 10).unwrap();
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0].id, "org/Tiny-GGUF");
+    assert_eq!(found[0].download_count, 100);
+    assert!(!found[0].featured);
+    let search_request = requests.lock().unwrap()[0].clone();
+    assert!(search_request.contains("search=tiny"));
+    assert!(search_request.contains("filter=gguf"));
+    assert!(search_request.contains("limit=10"));
+
+    let featured = source.indexes().unwrap();
+    assert!(featured.iter().all(|index| index.featured));
+
+    let card = source.load_card(&found[0]).unwrap();
+    assert_eq!(card.name, "Tiny-GGUF");
+    assert_eq!(card.size, "1.1B");
+    assert_eq!(card.architecture, "llama");
+    assert_eq!(card.context_size, 2048);
+    assert_eq!(card.prompt_template, "chatml");
+    assert_eq!(card.reverse_prompt, "<|im_end|>");
+    assert_eq!(card.author.url, format!("{endpoint}/org"));
+
+    let names: Vec<_> = card.files.iter().map(|file| file.name.as_str()).collect();
+    assert_eq!(names, ["Tiny-Q4_K_M.gguf", "Tiny-Q8_0-00001-of-00002.gguf"]);
+    let single = &card.files[0];
+    assert_eq!(single.quantization, "Q4_K_M");
+    assert_eq!(single.sha256.as_deref(), Some("aa"));
+    assert_eq!(
+        single.download.default,
+        format!("{endpoint}/org/Tiny-GGUF/resolve/main/Tiny-Q4_K_M.gguf")
+    );
+    let split = &card.files[1];
+    assert_eq!(split.quantization, "Q8_0");
+    assert_eq!(split.size, "900");
+    assert_eq!(split.shards.len(), 2);
+    assert_eq!(split.shards[1].name, "Tiny-Q8_0-00002-of-00002.gguf");
+    assert_eq!(split.shards[1].sha256.as_deref(), Some("cc"));
+
+    assert!(source.find("org/Missing-GGUF").unwrap().is_none());
+    assert!(source.find("not-a-repo").unwrap().is_none());
+    assert_eq!(
+        source.find("org/Tiny-GGUF").unwrap().map(|index| index.id),
+        Some("org/Tiny-GGUF".to_string())
+    );
+
+    assert_eq!(
+        split_prefix("m-Q4_K_M-00001-of-00003.gguf"),
+        Some("m-Q4_K_M")
+    );
+    assert_eq!(split_prefix("m-Q4_K_M.gguf"), None);
+    assert_eq!(quantization_of("phi-2.iq3_xs.gguf"), "IQ3_XS");
+    assert_eq!(quantization_of("model-f16-00001-of-00002.gguf"), "F16");
+    assert_eq!(quantization_of("model.gguf"), "");
+}
+
+#[test]
+fn test_local_directory_source() {
+    let dir = std::env::temp_dir().join(format!("moly-catalog-test-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(dir.join("org")).unwrap();
+    std::fs::write(
+        dir.join("org").join("tiny-chat.json"),
+        r#"{
+            "id": "org/Tiny-Chat",
+            "architecture": "llama",
+            "summary": "A tiny model",
+            "released_at": "2024-05-01T00:00:00Z",
+            "prompt_template": "chatml",
+            "reverse_prompt": "<|im_end|>",
+            "context_size": 2048,
+            "author": {"name": "org", "url": "", "description": ""}
+        }"#,
+    )
+    .unwrap();
+    std::fs::write(dir.join("org").join("broken.json"), "{").unwrap();
+
+    let source = LocalDirectorySource::new(dir.clone());
+    let indexes = source.indexes().unwrap();
+    assert_eq!(indexes.len(), 1);
+    assert_eq!(indexes[0].id, "org/Tiny-Chat");
+    assert_eq!(indexes[0].name, "tiny-chat");
+    assert!(indexes[0].featured);
+
+    assert_eq!(source.search("TINY", 10).unwrap().len(), 1);
+    assert!(source.search("mistral", 10).unwrap().is_empty());
+    let card = source.load_card(&indexes[0]).unwrap();
+    assert_eq!(card.context_size, 2048);
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,55 @@
+use std::{fs::File, io::Read, path::Path};
+
+use sha2::{Digest, Sha256};
+
+/// Feed the current content of `path` into `hasher`, so a resumed download can keep
+/// hashing from where it left off. Returns the number of bytes read.
+pub fn update_from_file<P: AsRef<Path>>(hasher: &mut Sha256, path: P) -> std::io::Result<u64> {
+    let mut file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+        Err(e) => return Err(e),
+    };
+
+    let mut buf = vec![0u8; 1024 * 1024];
+    let mut total = 0;
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+        total += n as u64;
+    }
+
+    Ok(total)
+}
+
+pub fn sha256_of_file<P: AsRef<Path>>(path: P) -> std::io::Result<String> {
+    let mut hasher = Sha256::new();
+    update_from_file(&mut hasher, path)?;
+    Ok(to_hex(hasher))
+}
+
+pub fn to_hex(hasher: Sha256) -> String {
+    format!("{:x}", hasher.finalize())
+}
+
+/// Model cards may publish the checksum in upper case.
+pub fn matches(expected: &str, actual: &str) -> bool {
+    expected.trim().eq_ignore_ascii_case(actual.trim())
+}
+
+#[test]
+fn test_sha256_of_file() {
+    let path = std::env::temp_dir().join("moly_test_sha256_of_file");
+    std::fs::write(&path, b"hello world").unwrap();
+
+    let hash = sha256_of_file(&path).unwrap();
+    assert!(matches(
+        "B94D27B9934D3E08A52E52D7DA7DABFAC484EFE37A5380EE9088F7ACE2EFCDE9",
+        &hash
+    ));
+
+    let _ = std::fs::remove_file(path);
+}
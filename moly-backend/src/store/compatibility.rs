@@ -0,0 +1,330 @@
+use moly_protocol::data::{CompatibilityGuess, MemoryEstimate};
+
+use super::{download_files::DownloadedFile, gguf::GgufInformation};
+
+// Context the server is started with when the user does not choose one
+pub const DEFAULT_CONTEXT_SIZE: u64 = 8 * 1024;
+
+// Compute buffers and the runtime itself, on top of the weights and the KV cache
+const RUNTIME_OVERHEAD: u64 = 512 * 1024 * 1024;
+
+// Without the layout of the model, the KV cache of one token is taken as this
+// fraction of the file, what Llama 3 8B uses at 4 bits per weight.
+const KV_BYTES_PER_FILE_BYTE: u64 = 32 * 1024;
+
+// Share of the available memory a model can take and still leave room for the rest
+const COMFORTABLE_SHARE: f64 = 0.8;
+
+// Share of the total memory past which the system would swap heavily
+const TIGHT_SHARE: f64 = 0.9;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SystemMemory {
+    pub total: u64,
+    pub available: u64,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SystemInfo {
+    pub memory: Option<SystemMemory>,
+    pub cpu_features: Vec<&'static str>,
+}
+
+impl SystemInfo {
+    pub fn detect() -> Self {
+        Self {
+            memory: system_memory(),
+            cpu_features: cpu_features(),
+        }
+    }
+
+    /// Whether the processor has the instructions the inference runtime is built
+    /// with. Only x86 builds need more than the base instruction set.
+    pub fn supports_runtime(&self) -> bool {
+        !cfg!(target_arch = "x86_64") || self.cpu_features.contains(&"avx")
+    }
+
+    pub fn guess(
+        &self,
+        file_size: u64,
+        context_size: u64,
+        kv_bytes_per_token: u64,
+    ) -> CompatibilityGuess {
+        if !self.supports_runtime() {
+            return CompatibilityGuess::NotSupported;
+        }
+        let Some(memory) = self.memory else {
+            return CompatibilityGuess::PossiblySupported;
+        };
+
+        // The sizes come from the file header, a bogus one must not wrap around
+        let kv_cache = kv_bytes_per_token.saturating_mul(context_size);
+        let estimate = MemoryEstimate {
+            required: file_size
+                .saturating_add(kv_cache)
+                .saturating_add(RUNTIME_OVERHEAD),
+            weights: file_size,
+            kv_cache,
+            context_size,
+            available_memory: memory.available,
+            total_memory: memory.total,
+        };
+
+        if estimate.required as f64 <= memory.available as f64 * COMFORTABLE_SHARE {
+            CompatibilityGuess::FitsComfortably(estimate)
+        } else if estimate.required as f64 <= memory.total as f64 * TIGHT_SHARE {
+            CompatibilityGuess::Tight(estimate)
+        } else {
+            CompatibilityGuess::WillNotFit(estimate)
+        }
+    }
+
+    /// Guess for a file of the catalog, known only by its size and the context of
+    /// its model card.
+    pub fn guess_remote(&self, size: &str, context_size: u64) -> CompatibilityGuess {
+        let Ok(file_size) = size.parse::<u64>() else {
+            return CompatibilityGuess::PossiblySupported;
+        };
+        let context_size = match context_size {
+            0 => DEFAULT_CONTEXT_SIZE,
+            context_size => context_size.min(DEFAULT_CONTEXT_SIZE),
+        };
+        self.guess(file_size, context_size, file_size / KV_BYTES_PER_FILE_BYTE)
+    }
+
+    /// Guess for a file on disk, using the model layout read from its header when
+    /// there is one.
+    pub fn guess_downloaded(&self, file: &DownloadedFile) -> CompatibilityGuess {
+        let file_size = match file.file_size {
+            0 => file.size.parse::<u64>().unwrap_or_default(),
+            file_size => file_size,
+        };
+        if file_size == 0 {
+            return CompatibilityGuess::PossiblySupported;
+        }
+
+        let context_size = file.context_size.clamp(1, DEFAULT_CONTEXT_SIZE);
+        let kv_bytes_per_token = serde_json::from_str::<GgufInformation>(&file.information)
+            .ok()
+            .and_then(|information| kv_bytes_per_token(&information))
+            .unwrap_or(file_size / KV_BYTES_PER_FILE_BYTE);
+        self.guess(file_size, context_size, kv_bytes_per_token)
+    }
+}
+
+/// Size of the keys and values one token adds to the cache, stored as f16.
+fn kv_bytes_per_token(information: &GgufInformation) -> Option<u64> {
+    let block_count = information.block_count?;
+    let embedding_length = information.embedding_length?;
+    let head_count = information.head_count.filter(|count| *count > 0)?;
+    let head_count_kv = information.head_count_kv.unwrap_or(head_count);
+
+    let kv_embedding_length = (embedding_length / head_count).saturating_mul(head_count_kv);
+    Some(
+        block_count
+            .saturating_mul(kv_embedding_length)
+            .saturating_mul(2 * 2),
+    )
+}
+
+#[cfg(target_os = "linux")]
+fn system_memory() -> Option<SystemMemory> {
+    let meminfo = std::fs::read_to_string("/proc/meminfo").ok()?;
+    parse_meminfo(&meminfo)
+}
+
+#[cfg(any(target_os = "linux", test))]
+fn parse_meminfo(meminfo: &str) -> Option<SystemMemory> {
+    let field = |name: &str| {
+        meminfo.lines().find_map(|line| {
+            let kb = line.strip_prefix(name)?.strip_prefix(':')?;
+            let kb = kb.trim().strip_suffix("kB")?.trim().parse::<u64>().ok()?;
+            Some(kb * 1024)
+        })
+    };
+    Some(SystemMemory {
+        total: field("MemTotal")?,
+        available: field("MemAvailable").or_else(|| field("MemFree"))?,
+    })
+}
+
+#[cfg(target_os = "macos")]
+fn system_memory() -> Option<SystemMemory> {
+    let page_size = sysctl_u64("hw.pagesize")?;
+    let free_pages = sysctl_u64("vm.page_free_count")?
+        + sysctl_u64("vm.page_purgeable_count").unwrap_or(0)
+        + sysctl_u64("vm.page_speculative_count").unwrap_or(0);
+    Some(SystemMemory {
+        total: sysctl_u64("hw.memsize")?,
+        available: free_pages * page_size,
+    })
+}
+
+#[cfg(target_os = "macos")]
+fn sysctl_u64(name: &str) -> Option<u64> {
+    let c_name = std::ffi::CString::new(name).ok()?;
+    let mut value = 0u64;
+    let mut len = std::mem::size_of::<u64>();
+    // SAFETY: `value` has room for `len` bytes, 32-bit values fill its low half
+    let r = unsafe {
+        libc::sysctlbyname(
+            c_name.as_ptr(),
+            &mut value as *mut u64 as *mut libc::c_void,
+            &mut len,
+            std::ptr::null_mut(),
+            0,
+        )
+    };
+    (r == 0).then_some(value)
+}
+
+#[cfg(windows)]
+fn system_memory() -> Option<SystemMemory> {
+    use windows_sys::Win32::System::SystemInformation::{GlobalMemoryStatusEx, MEMORYSTATUSEX};
+
+    let mut status: MEMORYSTATUSEX = unsafe { std::mem::zeroed() };
+    status.dwLength = std::mem::size_of::<MEMORYSTATUSEX>() as u32;
+    // SAFETY: `status` is a valid MEMORYSTATUSEX with its length set
+    if unsafe { GlobalMemoryStatusEx(&mut status) } == 0 {
+        return None;
+    }
+    Some(SystemMemory {
+        total: status.ullTotalPhys,
+        available: status.ullAvailPhys,
+    })
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+fn system_memory() -> Option<SystemMemory> {
+    None
+}
+
+/// Instruction set extensions of the processor that matter for inference speed.
+#[cfg(target_arch = "x86_64")]
+fn cpu_features() -> Vec<&'static str> {
+    let mut features = vec![];
+    macro_rules! detect {
+        ($($feature:tt),*) => {
+            $(if std::arch::is_x86_feature_detected!($feature) {
+                features.push($feature);
+            })*
+        };
+    }
+    detect!("sse4.2", "avx", "avx2", "fma", "f16c", "avx512f");
+    features
+}
+
+#[cfg(target_arch = "aarch64")]
+fn cpu_features() -> Vec<&'static str> {
+    let mut features = vec![];
+    macro_rules! detect {
+        ($($feature:tt),*) => {
+            $(if std::arch::is_aarch64_feature_detected!($feature) {
+                features.push($feature);
+            })*
+        };
+    }
+    detect!("neon", "dotprod", "fp16", "i8mm");
+    features
+}
+
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+fn cpu_features() -> Vec<&'static str> {
+    vec![]
+}
+
+#[test]
+fn test_compatibility() {
+    const GB: u64 = 1024 * 1024 * 1024;
+
+    let memory = parse_meminfo(
+        "MemTotal:       16384000 kB\nMemFree:  1000 kB\nMemAvailable:    8192000 kB\n",
+    );
+    assert_eq!(
+        memory,
+        Some(SystemMemory {
+            total: 16384000 * 1024,
+            available: 8192000 * 1024,
+        })
+    );
+
+    let system = SystemInfo {
+        memory: Some(SystemMemory {
+            total: 16 * GB,
+            available: 8 * GB,
+        }),
+        cpu_features: vec!["avx", "avx2", "neon"],
+    };
+
+    let CompatibilityGuess::FitsComfortably(estimate) =
+        system.guess_remote(&(4 * GB).to_string(), 0)
+    else {
+        panic!("a 4 GB file should fit in 8 GB");
+    };
+    assert_eq!(estimate.context_size, DEFAULT_CONTEXT_SIZE);
+    assert_eq!(
+        estimate.kv_cache,
+        4 * GB / KV_BYTES_PER_FILE_BYTE * DEFAULT_CONTEXT_SIZE
+    );
+    assert_eq!(
+        estimate.required,
+        4 * GB + estimate.kv_cache + RUNTIME_OVERHEAD
+    );
+
+    assert!(matches!(
+        system.guess_remote(&(10 * GB).to_string(), 4096),
+        CompatibilityGuess::Tight(_)
+    ));
+    assert!(matches!(
+        system.guess_remote(&(15 * GB).to_string(), 4096),
+        CompatibilityGuess::WillNotFit(_)
+    ));
+    assert_eq!(
+        system.guess_remote("3.08 GB", 4096),
+        CompatibilityGuess::PossiblySupported
+    );
+
+    // Llama 3 8B: 32 layers, 8 KV heads of 128 dimensions
+    let information = GgufInformation {
+        block_count: Some(32),
+        embedding_length: Some(4096),
+        head_count: Some(32),
+        head_count_kv: Some(8),
+        ..Default::default()
+    };
+    assert_eq!(kv_bytes_per_token(&information), Some(128 * 1024));
+
+    let file = DownloadedFile {
+        file_size: 5 * GB,
+        context_size: 131072,
+        information: serde_json::to_string(&information).unwrap(),
+        ..Default::default()
+    };
+    let guess = system.guess_downloaded(&file);
+    let estimate = guess.estimate().unwrap();
+    assert_eq!(estimate.context_size, DEFAULT_CONTEXT_SIZE);
+    assert_eq!(estimate.kv_cache, GB);
+
+    // A bogus header does not make it fit
+    let bogus = GgufInformation {
+        block_count: Some(u64::MAX),
+        ..information
+    };
+    let file = DownloadedFile {
+        information: serde_json::to_string(&bogus).unwrap(),
+        ..file
+    };
+    assert!(matches!(
+        system.guess_downloaded(&file),
+        CompatibilityGuess::WillNotFit(_)
+    ));
+
+    let unknown = SystemInfo {
+        memory: None,
+        ..system
+    };
+    assert_eq!(
+        unknown.guess_downloaded(&file),
+        CompatibilityGuess::PossiblySupported
+    );
+}
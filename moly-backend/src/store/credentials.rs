@@ -0,0 +1,98 @@
+use std::path::{Path, PathBuf};
+
+const TOKEN_FILENAME: &str = "hf_token";
+
+/// Hugging Face access token used for gated repositories. It is kept in a file of
+/// its own, readable only by the user, instead of the preferences JSON.
+#[derive(Debug, Clone, Default)]
+pub struct Credentials {
+    path: PathBuf,
+    token: Option<String>,
+}
+
+impl Credentials {
+    pub fn load<P: AsRef<Path>>(app_data_dir: P) -> Self {
+        let path = app_data_dir.as_ref().join(TOKEN_FILENAME);
+        let token = std::fs::read_to_string(&path)
+            .ok()
+            .map(|token| token.trim().to_string())
+            .filter(|token| !token.is_empty());
+
+        Self { path, token }
+    }
+
+    /// The saved token, or the one in `HF_TOKEN` like the Hugging Face tools do.
+    pub fn token(&self) -> Option<String> {
+        self.token.clone().or_else(|| {
+            std::env::var("HF_TOKEN")
+                .ok()
+                .filter(|token| !token.trim().is_empty())
+        })
+    }
+
+    /// Save `token`, or forget the saved one when `None`.
+    pub fn set_token(&mut self, token: Option<String>) -> anyhow::Result<()> {
+        let token = token
+            .map(|token| token.trim().to_string())
+            .filter(|token| !token.is_empty());
+
+        match &token {
+            Some(token) => write_private(&self.path, token)?,
+            None => match std::fs::remove_file(&self.path) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+                _ => {}
+            },
+        }
+
+        self.token = token;
+        Ok(())
+    }
+}
+
+#[cfg(unix)]
+fn write_private(path: &Path, content: &str) -> std::io::Result<()> {
+    use std::io::Write;
+    use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+
+    let mut file = std::fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(path)?;
+    // The mode only applies to new files
+    file.set_permissions(std::fs::Permissions::from_mode(0o600))?;
+    file.write_all(content.as_bytes())
+}
+
+#[cfg(not(unix))]
+fn write_private(path: &Path, content: &str) -> std::io::Result<()> {
+    // Files in the app data directory are only readable by the user on Windows
+    std::fs::write(path, content)
+}
+
+#[test]
+fn test_credentials() {
+    let dir = std::env::temp_dir().join("moly_test_credentials");
+    let _ = std::fs::create_dir_all(&dir);
+    let _ = std::fs::remove_file(dir.join(TOKEN_FILENAME));
+
+    let mut credentials = Credentials::load(&dir);
+    credentials
+        .set_token(Some(" hf_secret\n".to_string()))
+        .unwrap();
+    assert_eq!(Credentials::load(&dir).token, Some("hf_secret".to_string()));
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = std::fs::metadata(dir.join(TOKEN_FILENAME))
+            .unwrap()
+            .permissions()
+            .mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+
+    credentials.set_token(None).unwrap();
+    assert_eq!(Credentials::load(&dir).token, None);
+}
@@ -0,0 +1,77 @@
+use std::path::Path;
+
+/// Bytes the user can still write on the file system holding `path`. The path does
+/// not need to exist yet, its closest existing ancestor is looked up instead.
+pub fn available_space(path: &Path) -> std::io::Result<u64> {
+    let existing = path
+        .ancestors()
+        .find(|dir| dir.exists())
+        .unwrap_or(Path::new("."));
+    fs_available_space(existing)
+}
+
+#[cfg(unix)]
+fn fs_available_space(path: &Path) -> std::io::Result<u64> {
+    use std::os::unix::ffi::OsStrExt;
+
+    let c_path = std::ffi::CString::new(path.as_os_str().as_bytes())?;
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    // SAFETY: `c_path` is a valid C string and `stat` is a valid statvfs struct
+    if unsafe { libc::statvfs(c_path.as_ptr(), &mut stat) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(stat.f_bavail as u64 * stat.f_frsize as u64)
+}
+
+#[cfg(windows)]
+fn fs_available_space(path: &Path) -> std::io::Result<u64> {
+    use std::os::windows::ffi::OsStrExt;
+    use windows_sys::Win32::Storage::FileSystem::GetDiskFreeSpaceExW;
+
+    let wide_path: Vec<u16> = path.as_os_str().encode_wide().chain(Some(0)).collect();
+    let mut available = 0u64;
+    // SAFETY: `wide_path` is NUL terminated, the other pointers may be null
+    let ok = unsafe {
+        GetDiskFreeSpaceExW(
+            wide_path.as_ptr(),
+            &mut available,
+            std::ptr::null_mut(),
+            std::ptr::null_mut(),
+        )
+    };
+    if ok == 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(available)
+}
+
+/// Total length of the files under `path`, 0 if it does not exist.
+pub fn directory_size(path: &Path) -> u64 {
+    let Ok(entries) = std::fs::read_dir(path) else {
+        return 0;
+    };
+
+    entries
+        .flatten()
+        .map(|entry| match entry.file_type() {
+            Ok(file_type) if file_type.is_dir() => directory_size(&entry.path()),
+            Ok(_) => entry.metadata().map_or(0, |meta| meta.len()),
+            Err(_) => 0,
+        })
+        .sum()
+}
+
+#[test]
+fn test_disk_space() {
+    let dir = std::env::temp_dir().join("moly_test_disk_space");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(dir.join("org/model")).unwrap();
+    std::fs::write(dir.join("a.gguf"), [0u8; 10]).unwrap();
+    std::fs::write(dir.join("org/model/b.gguf"), [0u8; 32]).unwrap();
+
+    assert_eq!(directory_size(&dir), 42);
+    assert_eq!(directory_size(&dir.join("missing")), 0);
+
+    // Directories that do not exist yet report the space of their parent
+    assert!(available_space(&dir.join("missing/models")).unwrap() > 0);
+}
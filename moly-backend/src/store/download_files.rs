@@ -1,8 +1,75 @@
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 
 use chrono::{DateTime, Utc};
+use moly_protocol::data::PendingDownloadsStatus;
 use rusqlite::Row;
 
+/// Lifecycle of a file that is not downloaded yet.
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
+pub enum DownloadStatus {
+    // Waiting for a free download slot
+    Queued,
+    // Asking the server for the file size
+    Initializing,
+    Downloading,
+    #[default]
+    Paused,
+    // Waiting before the given attempt after a transient failure
+    Retrying(u32),
+    Error(String),
+    // Checking the SHA-256 of the completed file
+    Verifying,
+}
+
+impl DownloadStatus {
+    /// Whether a download task is working on the file right now.
+    pub fn is_active(&self) -> bool {
+        !matches!(self, DownloadStatus::Paused | DownloadStatus::Error(_))
+    }
+
+    fn to_db(&self) -> (&'static str, String) {
+        match self {
+            DownloadStatus::Queued => ("queued", String::new()),
+            DownloadStatus::Initializing => ("initializing", String::new()),
+            DownloadStatus::Downloading => ("downloading", String::new()),
+            DownloadStatus::Paused => ("paused", String::new()),
+            DownloadStatus::Retrying(attempt) => ("retrying", attempt.to_string()),
+            DownloadStatus::Error(reason) => ("error", reason.clone()),
+            DownloadStatus::Verifying => ("verifying", String::new()),
+        }
+    }
+
+    fn from_db(status: &str, detail: String) -> Self {
+        match status {
+            "queued" => DownloadStatus::Queued,
+            "initializing" => DownloadStatus::Initializing,
+            "downloading" => DownloadStatus::Downloading,
+            "retrying" => DownloadStatus::Retrying(detail.parse().unwrap_or(1)),
+            "error" => DownloadStatus::Error(detail),
+            "verifying" => DownloadStatus::Verifying,
+            _ => DownloadStatus::Paused,
+        }
+    }
+}
+
+impl From<DownloadStatus> for PendingDownloadsStatus {
+    fn from(status: DownloadStatus) -> Self {
+        match status {
+            DownloadStatus::Queued => PendingDownloadsStatus::Queued,
+            DownloadStatus::Initializing => PendingDownloadsStatus::Initializing,
+            DownloadStatus::Downloading => PendingDownloadsStatus::Downloading,
+            DownloadStatus::Paused => PendingDownloadsStatus::Paused,
+            DownloadStatus::Retrying(attempt) => PendingDownloadsStatus::Retrying(attempt),
+            DownloadStatus::Error(reason) => PendingDownloadsStatus::Error(reason),
+            DownloadStatus::Verifying => PendingDownloadsStatus::Verifying,
+        }
+    }
+}
+
 #[derive(Debug, Default, PartialEq, Eq, Clone)]
 pub struct DownloadedFile {
     pub id: Arc<String>,
@@ -20,16 +87,39 @@ pub struct DownloadedFile {
     pub tags: Vec<String>,
     pub featured: bool,
     pub sha256: String,
+    // The URL published in the model card, mirrors are derived from it
+    pub download_url: String,
+    pub status: DownloadStatus,
+    // Order in the download queue, lower values are downloaded first
+    pub queue_position: i64,
+    // Imported from elsewhere on disk: the file sits right in `download_dir`, not
+    // under a directory of its model, and Moly never moves or deletes it
+    pub imported: bool,
+    // JSON read from the header of the file, empty until it is read
+    pub information: String,
 }
 
 impl DownloadedFile {
+    /// Where the file is on disk.
+    pub fn path(&self) -> PathBuf {
+        let dir = Path::new(&self.download_dir);
+        if self.imported {
+            dir.join(&self.name)
+        } else {
+            dir.join(&self.model_id).join(&self.name)
+        }
+    }
+
     pub fn insert_into_db(&self, conn: &rusqlite::Connection) -> rusqlite::Result<()> {
+        let (status, status_detail) = self.status.to_db();
         conn.execute(
             "INSERT OR REPLACE INTO download_files (
                 id, model_id, name, size, quantization,
                 prompt_template, reverse_prompt, context_size,
-                downloaded, file_size, download_dir, downloaded_at, tags, featured, sha256)
-                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15)",
+                downloaded, file_size, download_dir, downloaded_at, tags, featured, sha256,
+                download_url, status, status_detail, queue_position, imported, information)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16,
+                    ?17, ?18, ?19, ?20, ?21)",
             rusqlite::params![
                 self.id,
                 self.model_id,
@@ -46,6 +136,12 @@ impl DownloadedFile {
                 serde_json::to_string(&self.tags).unwrap(),
                 self.featured,
                 self.sha256,
+                self.download_url,
+                status,
+                status_detail,
+                self.queue_position,
+                self.imported,
+                self.information,
             ],
         )?;
 
@@ -70,6 +166,120 @@ impl DownloadedFile {
         Ok(())
     }
 
+    pub fn update_information(&self, conn: &rusqlite::Connection) -> rusqlite::Result<()> {
+        conn.execute(
+            "UPDATE download_files SET information = ?2, context_size = ?3 WHERE id = ?1",
+            rusqlite::params![self.id, self.information, self.context_size],
+        )?;
+        Ok(())
+    }
+
+    pub fn update_status(
+        file_id: &str,
+        status: &DownloadStatus,
+        conn: &rusqlite::Connection,
+    ) -> rusqlite::Result<()> {
+        let (status, status_detail) = status.to_db();
+        conn.execute(
+            "UPDATE download_files SET status = ?2, status_detail = ?3 WHERE id = ?1",
+            rusqlite::params![file_id, status, status_detail],
+        )?;
+        Ok(())
+    }
+
+    /// Point the file at `download_dir`, once its content was moved there.
+    pub fn update_download_dir(
+        conn: &rusqlite::Connection,
+        file_id: &str,
+        download_dir: &str,
+    ) -> rusqlite::Result<()> {
+        conn.execute(
+            "UPDATE download_files SET download_dir = ?2 WHERE id = ?1",
+            rusqlite::params![file_id, download_dir],
+        )?;
+        Ok(())
+    }
+
+    /// Nothing is downloading right after startup, so downloads that were running
+    /// when the app exited are paused.
+    pub fn pause_interrupted(conn: &rusqlite::Connection) -> rusqlite::Result<()> {
+        conn.execute(
+            "UPDATE download_files SET status = 'paused', status_detail = ''
+                WHERE downloaded = FALSE AND status NOT IN ('paused', 'error')",
+            [],
+        )?;
+        Ok(())
+    }
+
+    /// Put downloads that were running when the app exited back in the queue.
+    /// Downloads paused by the user stay paused.
+    pub fn requeue_interrupted(conn: &rusqlite::Connection) -> rusqlite::Result<()> {
+        conn.execute(
+            "UPDATE download_files SET status = 'queued', status_detail = ''
+                WHERE downloaded = FALSE AND status NOT IN ('paused', 'error')",
+            [],
+        )?;
+        Ok(())
+    }
+
+    /// Files waiting for a download slot, in the order they should start.
+    pub fn get_queued(conn: &rusqlite::Connection) -> rusqlite::Result<Vec<Self>> {
+        let mut stmt = conn.prepare(
+            "SELECT * FROM download_files WHERE downloaded = FALSE AND status = 'queued'
+                ORDER BY queue_position, rowid",
+        )?;
+        let rows = stmt.query_map([], Self::from_row)?;
+        rows.collect()
+    }
+
+    pub fn next_queue_position(conn: &rusqlite::Connection) -> rusqlite::Result<i64> {
+        conn.query_row(
+            "SELECT COALESCE(MAX(queue_position), 0) + 1 FROM download_files
+                WHERE downloaded = FALSE",
+            [],
+            |row| row.get(0),
+        )
+    }
+
+    /// Ids of all pending downloads in queue order.
+    pub fn get_queue_order(conn: &rusqlite::Connection) -> rusqlite::Result<Vec<String>> {
+        let mut stmt = conn.prepare(
+            "SELECT id FROM download_files WHERE downloaded = FALSE
+                ORDER BY queue_position, rowid",
+        )?;
+        let rows = stmt.query_map([], |row| row.get(0))?;
+        rows.collect()
+    }
+
+    /// Move `file_ids` to the front of the queue in the given order. The rest of the
+    /// pending downloads keep their relative order behind them.
+    pub fn set_queue_order<S: AsRef<str>>(
+        conn: &rusqlite::Connection,
+        file_ids: &[S],
+    ) -> rusqlite::Result<()> {
+        let current = Self::get_queue_order(conn)?;
+
+        let mut order: Vec<&str> = file_ids
+            .iter()
+            .map(|id| id.as_ref())
+            .filter(|id| current.iter().any(|c| c == id))
+            .collect();
+        for id in &current {
+            if !order.contains(&id.as_str()) {
+                order.push(id);
+            }
+        }
+
+        let tx = conn.unchecked_transaction()?;
+        for (position, id) in order.iter().enumerate() {
+            tx.execute(
+                "UPDATE download_files SET queue_position = ?2 WHERE id = ?1",
+                rusqlite::params![id, position as i64 + 1],
+            )?;
+        }
+        tx.commit()
+    }
+
     fn from_row(row: &Row<'_>) -> rusqlite::Result<Self> {
         let downloaded_at =
             chrono::DateTime::parse_from_rfc3339(&row.get::<_, String>("downloaded_at")?)
@@ -78,6 +288,11 @@ impl DownloadedFile {
 
         let tags = serde_json::from_str(row.get::<_, String>("tags")?.as_str()).unwrap_or_default();
 
+        let status = DownloadStatus::from_db(
+            row.get::<_, String>("status")?.as_str(),
+            row.get("status_detail")?,
+        );
+
         Ok(DownloadedFile {
             id: Arc::new(row.get("id")?),
             model_id: row.get("model_id")?,
@@ -94,6 +309,11 @@ impl DownloadedFile {
             tags,
             featured: row.get("featured")?,
             sha256: row.get("sha256")?,
+            download_url: row.get("download_url")?,
+            status,
+            queue_position: row.get("queue_position")?,
+            imported: row.get("imported")?,
+            information: row.get("information")?,
         })
     }
 
@@ -168,18 +388,23 @@ impl DownloadedFile {
     }
 }
 
-fn check_context_size(conn: &rusqlite::Connection) -> rusqlite::Result<()> {
+/// Add a column to tables created by older versions of the app.
+fn add_column_if_missing(
+    conn: &rusqlite::Connection,
+    column: &str,
+    definition: &str,
+) -> rusqlite::Result<()> {
     let mut stmt = conn.prepare("PRAGMA table_info(download_files)")?;
     let mut rows = stmt.query_map([], |row| {
         let name: String = row.get(1)?;
         Ok(name)
     })?;
 
-    let check = rows.find(|row| matches!(row.as_deref(), Ok("context_size")));
+    let check = rows.find(|row| matches!(row.as_deref(), Ok(name) if name == column));
 
     if check.is_none() {
         conn.execute(
-            "ALTER TABLE download_files ADD COLUMN context_size INT DEFAULT 1024",
+            &format!("ALTER TABLE download_files ADD COLUMN {column} {definition}"),
             [],
         )?;
     }
@@ -204,14 +429,26 @@ pub fn create_table_download_files(conn: &rusqlite::Connection) -> rusqlite::Res
             downloaded_at TEXT NOT NULL,
             tags TEXT NOT NULL,
             featured INTEGER DEFAULT 0,
-            sha256 TEXT NOT NULL DEFAULT ''
+            sha256 TEXT NOT NULL DEFAULT '',
+            download_url TEXT NOT NULL DEFAULT '',
+            status TEXT NOT NULL DEFAULT 'paused',
+            status_detail TEXT NOT NULL DEFAULT '',
+            queue_position INTEGER NOT NULL DEFAULT 0,
+            imported INTEGER NOT NULL DEFAULT 0,
+            information TEXT NOT NULL DEFAULT ''
         );
         CREATE INDEX IF NOT EXISTS index_model_id ON download_files (model_id);
         CREATE INDEX IF NOT EXISTS index_downloaded ON download_files (downloaded);
         COMMIT;",
     )?;
 
-    check_context_size(conn)?;
+    add_column_if_missing(conn, "context_size", "INT DEFAULT 1024")?;
+    add_column_if_missing(conn, "download_url", "TEXT NOT NULL DEFAULT ''")?;
+    add_column_if_missing(conn, "status", "TEXT NOT NULL DEFAULT 'paused'")?;
+    add_column_if_missing(conn, "status_detail", "TEXT NOT NULL DEFAULT ''")?;
+    add_column_if_missing(conn, "queue_position", "INTEGER NOT NULL DEFAULT 0")?;
+    add_column_if_missing(conn, "imported", "INTEGER NOT NULL DEFAULT 0")?;
+    add_column_if_missing(conn, "information", "TEXT NOT NULL DEFAULT ''")?;
 
     Ok(())
 }
@@ -237,10 +474,27 @@ fn test_sql() {
         tags: vec!["test".to_string()],
         featured: false,
         sha256: Default::default(),
+        download_url: Default::default(),
+        status: DownloadStatus::Downloading,
+        queue_position: 1,
+        imported: false,
+        information: String::new(),
     };
 
     downloaded_file.insert_into_db(&conn).unwrap();
 
+    let error = DownloadStatus::Error("Connection reset".to_string());
+    DownloadedFile::update_status(&downloaded_file.id, &error, &conn).unwrap();
+    let pending = DownloadedFile::get_pending(&conn).unwrap();
+    assert_eq!(pending[&downloaded_file.id].status, error);
+
+    DownloadedFile::update_status(&downloaded_file.id, &DownloadStatus::Retrying(2), &conn)
+        .unwrap();
+    DownloadedFile::pause_interrupted(&conn).unwrap();
+    let pending = DownloadedFile::get_pending(&conn).unwrap();
+    assert_eq!(pending[&downloaded_file.id].status, DownloadStatus::Paused);
+    downloaded_file.status = DownloadStatus::Paused;
+
     let files = DownloadedFile::get_finished(&conn).unwrap();
     assert_eq!(files.len(), 0);
 
@@ -251,3 +505,42 @@ fn test_sql() {
     assert_eq!(files.len(), 1);
     assert_eq!(files[&downloaded_file.id], downloaded_file);
 }
+
+#[test]
+fn test_queue_order() {
+    let conn = rusqlite::Connection::open_in_memory().unwrap();
+    create_table_download_files(&conn).unwrap();
+
+    for id in ["a", "b", "c"] {
+        let file = DownloadedFile {
+            id: Arc::new(id.to_string()),
+            status: DownloadStatus::Queued,
+            queue_position: DownloadedFile::next_queue_position(&conn).unwrap(),
+            ..Default::default()
+        };
+        file.insert_into_db(&conn).unwrap();
+    }
+    assert_eq!(
+        DownloadedFile::get_queue_order(&conn).unwrap(),
+        ["a", "b", "c"]
+    );
+
+    // Prioritize
+    DownloadedFile::set_queue_order(&conn, &["c"]).unwrap();
+    assert_eq!(
+        DownloadedFile::get_queue_order(&conn).unwrap(),
+        ["c", "a", "b"]
+    );
+
+    // Unknown ids are ignored
+    DownloadedFile::set_queue_order(&conn, &["b", "x", "a"]).unwrap();
+    assert_eq!(
+        DownloadedFile::get_queue_order(&conn).unwrap(),
+        ["b", "a", "c"]
+    );
+
+    DownloadedFile::update_status("a", &DownloadStatus::Paused, &conn).unwrap();
+    let queued = DownloadedFile::get_queued(&conn).unwrap();
+    let queued: Vec<_> = queued.iter().map(|f| f.id.as_str()).collect();
+    assert_eq!(queued, ["b", "c"]);
+}
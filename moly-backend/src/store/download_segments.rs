@@ -0,0 +1,130 @@
+/// A byte range of a file downloaded over its own connection. `end` is inclusive,
+/// like in HTTP `Range` headers.
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
+pub struct DownloadSegment {
+    pub file_id: String,
+    pub idx: u32,
+    pub start: u64,
+    pub end: u64,
+    pub downloaded: u64,
+}
+
+impl DownloadSegment {
+    /// Split `content_length` bytes in `count` segments of about the same size.
+    pub fn split(file_id: &str, content_length: u64, count: u32) -> Vec<Self> {
+        let count = count.max(1) as u64;
+        let segment_size = content_length.div_ceil(count);
+
+        (0..count)
+            .map(|i| i * segment_size)
+            .take_while(|start| *start < content_length)
+            .enumerate()
+            .map(|(idx, start)| DownloadSegment {
+                file_id: file_id.to_string(),
+                idx: idx as u32,
+                start,
+                end: (start + segment_size).min(content_length) - 1,
+                downloaded: 0,
+            })
+            .collect()
+    }
+
+    pub fn len(&self) -> u64 {
+        self.end - self.start + 1
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.downloaded >= self.len()
+    }
+
+    pub fn insert_into_db(&self, conn: &rusqlite::Connection) -> rusqlite::Result<()> {
+        conn.execute(
+            "INSERT OR REPLACE INTO download_segments (file_id, idx, start, end, downloaded)
+                VALUES (?1, ?2, ?3, ?4, ?5)",
+            rusqlite::params![
+                self.file_id,
+                self.idx,
+                self.start,
+                self.end,
+                self.downloaded
+            ],
+        )?;
+        Ok(())
+    }
+
+    pub fn update_downloaded(&self, conn: &rusqlite::Connection) -> rusqlite::Result<()> {
+        conn.execute(
+            "UPDATE download_segments SET downloaded = ?3 WHERE file_id = ?1 AND idx = ?2",
+            rusqlite::params![self.file_id, self.idx, self.downloaded],
+        )?;
+        Ok(())
+    }
+
+    pub fn get_by_file(conn: &rusqlite::Connection, file_id: &str) -> rusqlite::Result<Vec<Self>> {
+        let mut stmt = conn.prepare(
+            "SELECT file_id, idx, start, end, downloaded FROM download_segments
+                WHERE file_id = ?1 ORDER BY idx",
+        )?;
+        let rows = stmt.query_map([file_id], |row| {
+            Ok(DownloadSegment {
+                file_id: row.get(0)?,
+                idx: row.get(1)?,
+                start: row.get(2)?,
+                end: row.get(3)?,
+                downloaded: row.get(4)?,
+            })
+        })?;
+        rows.collect()
+    }
+
+    pub fn remove_by_file(conn: &rusqlite::Connection, file_id: &str) -> rusqlite::Result<()> {
+        conn.execute(
+            "DELETE FROM download_segments WHERE file_id = ?1",
+            rusqlite::params![file_id],
+        )?;
+        Ok(())
+    }
+}
+
+pub fn create_table_download_segments(conn: &rusqlite::Connection) -> rusqlite::Result<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS download_segments (
+            file_id TEXT NOT NULL,
+            idx INTEGER NOT NULL,
+            start UNSIGNED BIG INT NOT NULL,
+            end UNSIGNED BIG INT NOT NULL,
+            downloaded UNSIGNED BIG INT DEFAULT 0,
+            PRIMARY KEY (file_id, idx)
+        )",
+        (),
+    )?;
+    Ok(())
+}
+
+#[test]
+fn test_sql() {
+    let conn = rusqlite::Connection::open_in_memory().unwrap();
+    create_table_download_segments(&conn).unwrap();
+
+    let mut segments = DownloadSegment::split("test", 10, 3);
+    assert_eq!(segments.len(), 3);
+    assert_eq!((segments[0].start, segments[0].end), (0, 3));
+    assert_eq!((segments[2].start, segments[2].end), (8, 9));
+    assert_eq!(segments.iter().map(DownloadSegment::len).sum::<u64>(), 10);
+
+    for segment in &segments {
+        segment.insert_into_db(&conn).unwrap();
+    }
+
+    segments[1].downloaded = 4;
+    segments[1].update_downloaded(&conn).unwrap();
+
+    let saved = DownloadSegment::get_by_file(&conn, "test").unwrap();
+    assert_eq!(saved, segments);
+    assert!(saved[1].is_complete());
+
+    DownloadSegment::remove_by_file(&conn, "test").unwrap();
+    assert!(DownloadSegment::get_by_file(&conn, "test")
+        .unwrap()
+        .is_empty());
+}
@@ -0,0 +1,173 @@
+use std::sync::Arc;
+
+use super::download_files::DownloadedFile;
+
+/// One of the files a sharded model is split into (`name-00001-of-00005.gguf`). The
+/// group is stored as a single `DownloadedFile` named after its first shard, which
+/// is the path given to the loader.
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
+pub struct DownloadShard {
+    // Id of the group
+    pub file_id: String,
+    pub idx: u32,
+    pub name: String,
+    pub download_url: String,
+    pub sha256: String,
+    // Known once the download started
+    pub file_size: u64,
+    pub downloaded: bool,
+}
+
+impl DownloadShard {
+    /// Save the shard as published in the model card. Size and progress of a shard
+    /// that was already saved are kept.
+    pub fn insert_into_db(&self, conn: &rusqlite::Connection) -> rusqlite::Result<()> {
+        conn.execute(
+            "INSERT INTO download_shards
+                (file_id, idx, name, download_url, sha256, file_size, downloaded)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+                ON CONFLICT (file_id, idx) DO UPDATE SET
+                    name = excluded.name,
+                    download_url = excluded.download_url,
+                    sha256 = excluded.sha256",
+            rusqlite::params![
+                self.file_id,
+                self.idx,
+                self.name,
+                self.download_url,
+                self.sha256,
+                self.file_size,
+                self.downloaded,
+            ],
+        )?;
+        Ok(())
+    }
+
+    pub fn update_progress(&self, conn: &rusqlite::Connection) -> rusqlite::Result<()> {
+        conn.execute(
+            "UPDATE download_shards SET file_size = ?3, downloaded = ?4
+                WHERE file_id = ?1 AND idx = ?2",
+            rusqlite::params![self.file_id, self.idx, self.file_size, self.downloaded],
+        )?;
+        Ok(())
+    }
+
+    pub fn get_by_file(conn: &rusqlite::Connection, file_id: &str) -> rusqlite::Result<Vec<Self>> {
+        let mut stmt = conn.prepare(
+            "SELECT file_id, idx, name, download_url, sha256, file_size, downloaded
+                FROM download_shards WHERE file_id = ?1 ORDER BY idx",
+        )?;
+        let rows = stmt.query_map([file_id], |row| {
+            Ok(DownloadShard {
+                file_id: row.get(0)?,
+                idx: row.get(1)?,
+                name: row.get(2)?,
+                download_url: row.get(3)?,
+                sha256: row.get(4)?,
+                file_size: row.get(5)?,
+                downloaded: row.get(6)?,
+            })
+        })?;
+        rows.collect()
+    }
+
+    pub fn remove_by_file(conn: &rusqlite::Connection, file_id: &str) -> rusqlite::Result<()> {
+        conn.execute(
+            "DELETE FROM download_shards WHERE file_id = ?1",
+            rusqlite::params![file_id],
+        )?;
+        Ok(())
+    }
+
+    /// The shard as a file of its own, to download or verify it like any other.
+    pub fn to_part(&self, group: &DownloadedFile) -> DownloadedFile {
+        DownloadedFile {
+            id: Arc::new(format!("{}#{}", group.model_id, self.name)),
+            name: self.name.clone(),
+            download_url: self.download_url.clone(),
+            sha256: self.sha256.clone(),
+            file_size: self.file_size,
+            downloaded: self.downloaded,
+            ..group.clone()
+        }
+    }
+}
+
+/// Files on disk that make up `file`: its shards, or the file itself.
+pub fn parts_of(file: &DownloadedFile, shards: &[DownloadShard]) -> Vec<DownloadedFile> {
+    if shards.is_empty() {
+        vec![file.clone()]
+    } else {
+        shards.iter().map(|shard| shard.to_part(file)).collect()
+    }
+}
+
+pub fn create_table_download_shards(conn: &rusqlite::Connection) -> rusqlite::Result<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS download_shards (
+            file_id TEXT NOT NULL,
+            idx INTEGER NOT NULL,
+            name TEXT NOT NULL,
+            download_url TEXT NOT NULL DEFAULT '',
+            sha256 TEXT NOT NULL DEFAULT '',
+            file_size UNSIGNED BIG INT DEFAULT 0,
+            downloaded INTEGER DEFAULT 0,
+            PRIMARY KEY (file_id, idx)
+        )",
+        (),
+    )?;
+    Ok(())
+}
+
+#[test]
+fn test_sql() {
+    let conn = rusqlite::Connection::open_in_memory().unwrap();
+    create_table_download_shards(&conn).unwrap();
+
+    let file_id = "org/model#model-00001-of-00002.gguf";
+    let mut shards: Vec<_> = ["model-00001-of-00002.gguf", "model-00002-of-00002.gguf"]
+        .iter()
+        .enumerate()
+        .map(|(idx, name)| DownloadShard {
+            file_id: file_id.to_string(),
+            idx: idx as u32,
+            name: name.to_string(),
+            ..Default::default()
+        })
+        .collect();
+    for shard in &shards {
+        shard.insert_into_db(&conn).unwrap();
+    }
+
+    shards[0].file_size = 10;
+    shards[0].downloaded = true;
+    shards[0].update_progress(&conn).unwrap();
+
+    // Saving the card again keeps the progress
+    DownloadShard {
+        sha256: "abc".to_string(),
+        ..shards[0].clone()
+    }
+    .insert_into_db(&conn)
+    .unwrap();
+    shards[0].sha256 = "abc".to_string();
+
+    assert_eq!(DownloadShard::get_by_file(&conn, file_id).unwrap(), shards);
+
+    let group = DownloadedFile {
+        id: Arc::new(file_id.to_string()),
+        model_id: "org/model".to_string(),
+        name: shards[0].name.clone(),
+        ..Default::default()
+    };
+    let parts = parts_of(&group, &shards);
+    assert_eq!(parts[1].id.as_str(), "org/model#model-00002-of-00002.gguf");
+    assert_eq!(parts[1].name, "model-00002-of-00002.gguf");
+    assert!(parts[0].downloaded);
+    assert_eq!(parts_of(&group, &[]), vec![group.clone()]);
+
+    DownloadShard::remove_by_file(&conn, file_id).unwrap();
+    assert!(DownloadShard::get_by_file(&conn, file_id)
+        .unwrap()
+        .is_empty());
+}
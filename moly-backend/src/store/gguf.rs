@@ -0,0 +1,508 @@
+use std::{
+    collections::BTreeMap,
+    io::{BufReader, Read},
+    path::Path,
+};
+
+use serde::{Deserialize, Serialize};
+
+const GGUF_MAGIC: &[u8; 4] = b"GGUF";
+
+// Bounds on what a header may declare, so a corrupt file fails instead of
+// allocating gigabytes
+const MAX_STRING_LEN: u64 = 64 * 1024 * 1024;
+const MAX_KV_COUNT: u64 = 1024 * 1024;
+const MAX_TENSOR_COUNT: u64 = 1024 * 1024;
+const MAX_TENSOR_DIMS: u32 = 8;
+// Arrays of arrays are read recursively, deeper ones would overflow the stack
+const MAX_ARRAY_DEPTH: u32 = 4;
+
+// Longer arrays, e.g. the tokenizer vocabulary, are skipped and only their length
+// is kept
+const MAX_KEPT_ARRAY_LEN: u64 = 256;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum GgufValue {
+    U8(u8),
+    I8(i8),
+    U16(u16),
+    I16(i16),
+    U32(u32),
+    I32(i32),
+    U64(u64),
+    I64(i64),
+    F32(f32),
+    F64(f64),
+    Bool(bool),
+    String(String),
+    Array {
+        len: u64,
+        // Empty when the array is longer than `MAX_KEPT_ARRAY_LEN`
+        items: Vec<GgufValue>,
+    },
+}
+
+impl GgufValue {
+    pub fn as_u64(&self) -> Option<u64> {
+        match *self {
+            GgufValue::U8(v) => Some(v as u64),
+            GgufValue::U16(v) => Some(v as u64),
+            GgufValue::U32(v) => Some(v as u64),
+            GgufValue::U64(v) => Some(v),
+            GgufValue::I8(v) => u64::try_from(v).ok(),
+            GgufValue::I16(v) => u64::try_from(v).ok(),
+            GgufValue::I32(v) => u64::try_from(v).ok(),
+            GgufValue::I64(v) => u64::try_from(v).ok(),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match *self {
+            GgufValue::F32(v) => Some(v as f64),
+            GgufValue::F64(v) => Some(v),
+            _ => self.as_u64().map(|v| v as f64),
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            GgufValue::String(s) => Some(s),
+            _ => None,
+        }
+    }
+}
+
+/// The header of a GGUF file: its key-value metadata and the shapes of its tensors.
+#[derive(Debug, Clone, Default)]
+pub struct GgufMetadata {
+    pub values: BTreeMap<String, GgufValue>,
+    // Total number of weights in the tensors
+    pub parameter_count: u64,
+}
+
+impl GgufMetadata {
+    pub fn get_str(&self, key: &str) -> Option<&str> {
+        self.values.get(key).and_then(GgufValue::as_str)
+    }
+
+    pub fn get_u64(&self, key: &str) -> Option<u64> {
+        self.values.get(key).and_then(GgufValue::as_u64)
+    }
+
+    pub fn get_f64(&self, key: &str) -> Option<f64> {
+        self.values.get(key).and_then(GgufValue::as_f64)
+    }
+
+    /// A value of the architecture, e.g. `llama.block_count` for `block_count`.
+    fn arch_u64(&self, key: &str) -> Option<u64> {
+        self.get_u64(&format!("{}.{key}", self.architecture()?))
+    }
+
+    fn arch_f64(&self, key: &str) -> Option<f64> {
+        self.get_f64(&format!("{}.{key}", self.architecture()?))
+    }
+
+    pub fn name(&self) -> Option<&str> {
+        self.get_str("general.name")
+    }
+
+    pub fn architecture(&self) -> Option<&str> {
+        self.get_str("general.architecture")
+    }
+
+    /// Context length the model was trained with.
+    pub fn context_length(&self) -> Option<u64> {
+        self.arch_u64("context_length")
+    }
+
+    /// Name of the quantization most tensors use, e.g. `Q4_K_M`.
+    pub fn quantization(&self) -> Option<&'static str> {
+        file_type_name(self.get_u64("general.file_type")?)
+    }
+
+    /// The Jinja chat template embedded by the converter.
+    pub fn chat_template(&self) -> Option<&str> {
+        self.get_str("tokenizer.chat_template")
+    }
+
+    pub fn information(&self) -> GgufInformation {
+        let owned = |s: Option<&str>| s.map(str::to_string);
+        let vocab_size = match self.values.get("tokenizer.ggml.tokens") {
+            Some(GgufValue::Array { len, .. }) => Some(*len),
+            _ => None,
+        };
+
+        GgufInformation {
+            name: owned(self.name()),
+            architecture: owned(self.architecture()),
+            parameter_count: self.parameter_count,
+            quantization: owned(self.quantization()),
+            context_length: self.context_length(),
+            embedding_length: self.arch_u64("embedding_length"),
+            block_count: self.arch_u64("block_count"),
+            head_count: self.arch_u64("attention.head_count"),
+            head_count_kv: self.arch_u64("attention.head_count_kv"),
+            rope: RopeInformation {
+                dimension_count: self.arch_u64("rope.dimension_count"),
+                freq_base: self.arch_f64("rope.freq_base"),
+                scaling_type: owned(
+                    self.architecture()
+                        .and_then(|arch| self.get_str(&format!("{arch}.rope.scaling.type"))),
+                ),
+                scaling_factor: self.arch_f64("rope.scaling.factor"),
+            },
+            tokenizer: TokenizerInformation {
+                model: owned(self.get_str("tokenizer.ggml.model")),
+                pre: owned(self.get_str("tokenizer.ggml.pre")),
+                vocab_size,
+                bos_token_id: self.get_u64("tokenizer.ggml.bos_token_id"),
+                eos_token_id: self.get_u64("tokenizer.ggml.eos_token_id"),
+            },
+            chat_template: owned(self.chat_template()),
+        }
+    }
+}
+
+/// What is known about a model from the header of its file, shown to the user as
+/// JSON.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct GgufInformation {
+    pub name: Option<String>,
+    pub architecture: Option<String>,
+    pub parameter_count: u64,
+    pub quantization: Option<String>,
+    pub context_length: Option<u64>,
+    pub embedding_length: Option<u64>,
+    pub block_count: Option<u64>,
+    pub head_count: Option<u64>,
+    pub head_count_kv: Option<u64>,
+    pub rope: RopeInformation,
+    pub tokenizer: TokenizerInformation,
+    pub chat_template: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RopeInformation {
+    pub dimension_count: Option<u64>,
+    pub freq_base: Option<f64>,
+    pub scaling_type: Option<String>,
+    pub scaling_factor: Option<f64>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TokenizerInformation {
+    pub model: Option<String>,
+    pub pre: Option<String>,
+    pub vocab_size: Option<u64>,
+    pub bos_token_id: Option<u64>,
+    pub eos_token_id: Option<u64>,
+}
+
+/// Read the header of the GGUF file at `path`. The tensor data is never read.
+pub fn read_metadata(path: &Path) -> anyhow::Result<GgufMetadata> {
+    let file = std::fs::File::open(path)?;
+    parse_metadata(&mut BufReader::new(file))
+        .map_err(|e| anyhow::anyhow!("{:?} is not a valid GGUF file: {e}", path))
+}
+
+pub fn parse_metadata<R: Read>(reader: &mut R) -> anyhow::Result<GgufMetadata> {
+    let mut magic = [0u8; 4];
+    reader.read_exact(&mut magic)?;
+    if &magic != GGUF_MAGIC {
+        anyhow::bail!("bad magic number");
+    }
+
+    let version = read_u32(reader)?;
+    // Version 1 used 32 bit lengths and is not produced anymore
+    if version < 2 {
+        anyhow::bail!("unsupported version {version}");
+    }
+
+    let tensor_count = read_u64(reader)?;
+    if tensor_count > MAX_TENSOR_COUNT {
+        anyhow::bail!("{tensor_count} tensors");
+    }
+    let kv_count = read_u64(reader)?;
+    if kv_count > MAX_KV_COUNT {
+        anyhow::bail!("{kv_count} metadata entries");
+    }
+
+    let mut values = BTreeMap::new();
+    for _ in 0..kv_count {
+        let key = read_string(reader)?;
+        let value_type = read_u32(reader)?;
+        let value = read_value(reader, value_type, 0)?;
+        values.insert(key, value);
+    }
+
+    // The tensor infos follow the metadata, each one with its name, its shape, its
+    // type and the offset of its data
+    let mut parameter_count = 0u64;
+    for _ in 0..tensor_count {
+        let _name = read_string(reader)?;
+        let n_dims = read_u32(reader)?;
+        if n_dims > MAX_TENSOR_DIMS {
+            anyhow::bail!("tensor with {n_dims} dimensions");
+        }
+        let mut elements = 1u64;
+        for _ in 0..n_dims {
+            elements = elements.saturating_mul(read_u64(reader)?);
+        }
+        let _type = read_u32(reader)?;
+        let _offset = read_u64(reader)?;
+        parameter_count = parameter_count.saturating_add(elements);
+    }
+
+    Ok(GgufMetadata {
+        values,
+        parameter_count,
+    })
+}
+
+/// `depth` is the number of arrays the value is in.
+fn read_value<R: Read>(reader: &mut R, value_type: u32, depth: u32) -> anyhow::Result<GgufValue> {
+    let value = match value_type {
+        0 => GgufValue::U8(read_array::<1, R>(reader)?[0]),
+        1 => GgufValue::I8(read_array::<1, R>(reader)?[0] as i8),
+        2 => GgufValue::U16(u16::from_le_bytes(read_array(reader)?)),
+        3 => GgufValue::I16(i16::from_le_bytes(read_array(reader)?)),
+        4 => GgufValue::U32(read_u32(reader)?),
+        5 => GgufValue::I32(i32::from_le_bytes(read_array(reader)?)),
+        6 => GgufValue::F32(f32::from_le_bytes(read_array(reader)?)),
+        7 => GgufValue::Bool(read_array::<1, R>(reader)?[0] != 0),
+        8 => GgufValue::String(read_string(reader)?),
+        9 => {
+            if depth == MAX_ARRAY_DEPTH {
+                anyhow::bail!("arrays nested more than {MAX_ARRAY_DEPTH} deep");
+            }
+            let item_type = read_u32(reader)?;
+            let len = read_u64(reader)?;
+            let mut items = vec![];
+            for _ in 0..len {
+                let item = read_value(reader, item_type, depth + 1)?;
+                if len <= MAX_KEPT_ARRAY_LEN {
+                    items.push(item);
+                }
+            }
+            GgufValue::Array { len, items }
+        }
+        10 => GgufValue::U64(read_u64(reader)?),
+        11 => GgufValue::I64(i64::from_le_bytes(read_array(reader)?)),
+        12 => GgufValue::F64(f64::from_le_bytes(read_array(reader)?)),
+        _ => anyhow::bail!("unknown value type {value_type}"),
+    };
+    Ok(value)
+}
+
+fn read_array<const N: usize, R: Read>(reader: &mut R) -> std::io::Result<[u8; N]> {
+    let mut buf = [0u8; N];
+    reader.read_exact(&mut buf)?;
+    Ok(buf)
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> std::io::Result<u32> {
+    Ok(u32::from_le_bytes(read_array(reader)?))
+}
+
+fn read_u64<R: Read>(reader: &mut R) -> std::io::Result<u64> {
+    Ok(u64::from_le_bytes(read_array(reader)?))
+}
+
+fn read_string<R: Read>(reader: &mut R) -> anyhow::Result<String> {
+    let len = read_u64(reader)?;
+    if len > MAX_STRING_LEN {
+        anyhow::bail!("string of {len} bytes");
+    }
+    let mut buf = vec![0u8; len as usize];
+    reader.read_exact(&mut buf)?;
+    Ok(String::from_utf8_lossy(&buf).into_owned())
+}
+
+/// Name of a `general.file_type` as llama.cpp writes it.
+pub fn file_type_name(file_type: u64) -> Option<&'static str> {
+    let name = match file_type {
+        0 => "F32",
+        1 => "F16",
+        2 => "Q4_0",
+        3 => "Q4_1",
+        7 => "Q8_0",
+        8 => "Q5_0",
+        9 => "Q5_1",
+        10 => "Q2_K",
+        11 => "Q3_K_S",
+        12 => "Q3_K_M",
+        13 => "Q3_K_L",
+        14 => "Q4_K_S",
+        15 => "Q4_K_M",
+        16 => "Q5_K_S",
+        17 => "Q5_K_M",
+        18 => "Q6_K",
+        19 => "IQ2_XXS",
+        20 => "IQ2_XS",
+        21 => "Q2_K_S",
+        22 => "IQ3_XS",
+        23 => "IQ3_XXS",
+        24 => "IQ1_S",
+        25 => "IQ4_NL",
+        26 => "IQ3_S",
+        27 => "IQ3_M",
+        28 => "IQ2_S",
+        29 => "IQ2_M",
+        30 => "IQ4_XS",
+        31 => "IQ1_M",
+        32 => "BF16",
+        _ => return None,
+    };
+    Some(name)
+}
+
+/// Number of parameters as model cards write it, e.g. `7.2B`.
+pub fn parameters_label(count: u64) -> String {
+    match count {
+        0 => String::new(),
+        count if count >= 1_000_000_000 => format!("{:.1}B", count as f64 / 1e9),
+        count => format!("{:.0}M", count as f64 / 1e6),
+    }
+}
+
+/// The llama-api-server prompt template and reverse prompt matching a Jinja chat
+/// template, recognized by its special tokens.
+pub fn prompt_template_of(chat_template: &str) -> Option<(&'static str, &'static str)> {
+    let known = [
+        ("<|start_header_id|>", "llama-3-chat", "<|eot_id|>"),
+        ("<|im_start|>", "chatml", "<|im_end|>"),
+        ("<start_of_turn>", "gemma-instruct", "<end_of_turn>"),
+        ("<|assistant|>", "phi-3-chat", "<|end|>"),
+        ("[INST]", "mistral-instruct", "</s>"),
+    ];
+    known
+        .into_iter()
+        .find(|(token, _, _)| chat_template.contains(token))
+        .map(|(_, template, reverse_prompt)| (template, reverse_prompt))
+}
+
+#[cfg(test)]
+pub(crate) fn write_test_file(path: &Path, values: &[(&str, GgufValue)], tensor_shapes: &[&[u64]]) {
+    fn write_value(buf: &mut Vec<u8>, value: &GgufValue) {
+        match value {
+            GgufValue::U32(v) => buf.extend(v.to_le_bytes()),
+            GgufValue::U64(v) => buf.extend(v.to_le_bytes()),
+            GgufValue::F32(v) => buf.extend(v.to_le_bytes()),
+            GgufValue::String(s) => {
+                buf.extend((s.len() as u64).to_le_bytes());
+                buf.extend(s.as_bytes());
+            }
+            GgufValue::Array { items, .. } => {
+                buf.extend(8u32.to_le_bytes());
+                buf.extend((items.len() as u64).to_le_bytes());
+                items.iter().for_each(|item| write_value(buf, item));
+            }
+            _ => unimplemented!(),
+        }
+    }
+
+    let mut buf = GGUF_MAGIC.to_vec();
+    buf.extend(3u32.to_le_bytes());
+    buf.extend((tensor_shapes.len() as u64).to_le_bytes());
+    buf.extend((values.len() as u64).to_le_bytes());
+    for (key, value) in values {
+        write_value(&mut buf, &GgufValue::String(key.to_string()));
+        let value_type: u32 = match value {
+            GgufValue::U32(_) => 4,
+            GgufValue::F32(_) => 6,
+            GgufValue::String(_) => 8,
+            GgufValue::Array { .. } => 9,
+            GgufValue::U64(_) => 10,
+            _ => unimplemented!(),
+        };
+        buf.extend(value_type.to_le_bytes());
+        write_value(&mut buf, value);
+    }
+    for (i, shape) in tensor_shapes.iter().enumerate() {
+        write_value(&mut buf, &GgufValue::String(format!("blk.{i}.weight")));
+        buf.extend((shape.len() as u32).to_le_bytes());
+        shape.iter().for_each(|dim| buf.extend(dim.to_le_bytes()));
+        // F32, at the start of the data
+        buf.extend(0u32.to_le_bytes());
+        buf.extend(0u64.to_le_bytes());
+    }
+    // Stands for the tensor data, which must never be read
+    buf.extend([0xffu8; 32]);
+    std::fs::write(path, buf).unwrap();
+}
+
+#[test]
+fn test_gguf_metadata() {
+    let path = std::env::temp_dir().join("moly_test_gguf_metadata.gguf");
+    let tokens: Vec<_> = (0..1000)
+        .map(|i| GgufValue::String(format!("t{i}")))
+        .collect();
+    write_test_file(
+        &path,
+        &[
+            ("general.architecture", GgufValue::String("llama".into())),
+            ("general.name", GgufValue::String("Tiny Llama".into())),
+            ("general.file_type", GgufValue::U32(15)),
+            ("llama.context_length", GgufValue::U64(4096)),
+            ("llama.rope.freq_base", GgufValue::F32(10000.0)),
+            ("tokenizer.ggml.model", GgufValue::String("gpt2".into())),
+            (
+                "tokenizer.chat_template",
+                GgufValue::String("<|im_start|>{{ message }}<|im_end|>".into()),
+            ),
+            (
+                "tokenizer.ggml.tokens",
+                GgufValue::Array {
+                    len: 1000,
+                    items: tokens,
+                },
+            ),
+        ],
+        &[&[64, 1000], &[64]],
+    );
+
+    let metadata = read_metadata(&path).unwrap();
+    assert_eq!(metadata.name(), Some("Tiny Llama"));
+    assert_eq!(metadata.architecture(), Some("llama"));
+    assert_eq!(metadata.context_length(), Some(4096));
+    assert_eq!(metadata.quantization(), Some("Q4_K_M"));
+    assert_eq!(
+        metadata.values.get("tokenizer.ggml.tokens"),
+        Some(&GgufValue::Array {
+            len: 1000,
+            items: vec![]
+        })
+    );
+    assert_eq!(
+        prompt_template_of(metadata.chat_template().unwrap()),
+        Some(("chatml", "<|im_end|>"))
+    );
+
+    let information = metadata.information();
+    assert_eq!(information.parameter_count, 64 * 1000 + 64);
+    assert_eq!(information.rope.freq_base, Some(10000.0));
+    assert_eq!(information.tokenizer.model.as_deref(), Some("gpt2"));
+    assert_eq!(information.tokenizer.vocab_size, Some(1000));
+    assert_eq!(information.head_count, None);
+
+    std::fs::write(&path, b"not a gguf file").unwrap();
+    assert!(read_metadata(&path).is_err());
+
+    // Arrays of arrays of arrays...
+    let mut buf = GGUF_MAGIC.to_vec();
+    buf.extend(3u32.to_le_bytes());
+    buf.extend(0u64.to_le_bytes());
+    buf.extend(1u64.to_le_bytes());
+    buf.extend(6u64.to_le_bytes());
+    buf.extend(b"nested");
+    buf.extend(9u32.to_le_bytes());
+    for _ in 0..100_000 {
+        buf.extend(9u32.to_le_bytes());
+        buf.extend(1u64.to_le_bytes());
+    }
+    std::fs::write(&path, buf).unwrap();
+    assert!(read_metadata(&path).is_err());
+
+    let _ = std::fs::remove_file(path);
+}
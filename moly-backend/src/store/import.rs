@@ -0,0 +1,269 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use chrono::Utc;
+
+use super::{
+    checksum,
+    download_files::DownloadedFile,
+    gguf::{self, GgufMetadata},
+    model_cards::{Author, ModelCard, RemoteFile},
+    models::Model,
+};
+
+// Models made up for files that are not in the catalog are listed under this author
+pub const LOCAL_AUTHOR: &str = "local";
+
+/// GGUF files in `paths`. Directories are scanned, subdirectories included. Only the
+/// first part of split models is kept, the loader finds the others next to it.
+pub fn gguf_files(paths: &[PathBuf]) -> Vec<PathBuf> {
+    let mut files = vec![];
+    for path in paths {
+        if path.is_dir() {
+            scan_dir(path, &mut files);
+        } else {
+            files.push(path.clone());
+        }
+    }
+    files
+}
+
+fn scan_dir(dir: &Path, files: &mut Vec<PathBuf>) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    let mut entries: Vec<_> = entries.flatten().map(|entry| entry.path()).collect();
+    entries.sort();
+
+    for path in entries {
+        if path.is_dir() {
+            scan_dir(&path, files);
+        } else if is_gguf(&path) && !is_later_shard(&path) {
+            files.push(path);
+        }
+    }
+}
+
+fn is_gguf(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("gguf"))
+}
+
+/// Whether `path` is a part of a split model other than the first one, named like
+/// `model-00002-of-00003.gguf`.
+fn is_later_shard(path: &Path) -> bool {
+    let Some(stem) = path.file_stem().and_then(|stem| stem.to_str()) else {
+        return false;
+    };
+    let mut parts = stem.rsplitn(4, '-');
+    let (Some(total), Some("of"), Some(idx)) = (parts.next(), parts.next(), parts.next()) else {
+        return false;
+    };
+    let is_number = |s: &str| !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit());
+    is_number(total) && is_number(idx) && idx.trim_start_matches('0') != "1"
+}
+
+/// The catalog file `path` is a copy of, recognized by its name, or by its SHA-256
+/// when the name does not tell. The file is only hashed when a catalog file with a
+/// checksum has the same size.
+pub fn find_in_catalog<'a>(
+    cards: &'a [ModelCard],
+    path: &Path,
+) -> Option<(&'a ModelCard, &'a RemoteFile)> {
+    let name = path.file_name()?.to_str()?;
+    let all_files = || {
+        cards
+            .iter()
+            .flat_map(|card| card.files.iter().map(move |file| (card, file)))
+    };
+
+    let named: Vec<_> = all_files()
+        .filter(|(_, file)| file.name.eq_ignore_ascii_case(name))
+        .collect();
+    if let [found] = named[..] {
+        return Some(found);
+    }
+
+    let Ok(len) = std::fs::metadata(path).map(|metadata| metadata.len()) else {
+        return named.first().copied();
+    };
+    let same_size: Vec<_> = all_files()
+        .filter(|(_, file)| {
+            file.shards.is_empty()
+                && file.sha256.as_deref().is_some_and(|s| !s.is_empty())
+                && file.size.parse::<u64>().is_ok_and(|size| size == len)
+        })
+        .collect();
+    if same_size.is_empty() {
+        return named.first().copied();
+    }
+
+    let sha256 = match checksum::sha256_of_file(path) {
+        Ok(sha256) => sha256,
+        Err(e) => {
+            log::warn!("Failed to hash {:?}: {e}", path);
+            return named.first().copied();
+        }
+    };
+    same_size
+        .into_iter()
+        .find(|(_, file)| checksum::matches(file.sha256.as_deref().unwrap_or_default(), &sha256))
+        .or_else(|| named.first().copied())
+}
+
+/// A model made up from the metadata of a file that is not in the catalog, and the
+/// file itself, listed where it is.
+pub fn local_entry(
+    path: &Path,
+    metadata: &GgufMetadata,
+) -> anyhow::Result<(Model, DownloadedFile)> {
+    let name = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or_else(|| anyhow::anyhow!("Invalid file name {:?}", path))?;
+    let stem = Path::new(name)
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or(name);
+    let dir = path
+        .parent()
+        .ok_or_else(|| anyhow::anyhow!("Invalid file path {:?}", path))?;
+    let file_size = std::fs::metadata(path)?.len();
+
+    let model_id = format!("{LOCAL_AUTHOR}/{}", model_dir_name(stem));
+    let (prompt_template, reverse_prompt) = metadata
+        .chat_template()
+        .and_then(gguf::prompt_template_of)
+        .unwrap_or_default();
+
+    let model = Model {
+        id: Arc::new(model_id.clone()),
+        name: metadata.name().unwrap_or(stem).to_string(),
+        summary: format!("Imported from {}", path.display()),
+        size: gguf::parameters_label(metadata.parameter_count),
+        architecture: metadata.architecture().unwrap_or_default().to_string(),
+        released_at: Utc::now(),
+        prompt_template: prompt_template.to_string(),
+        reverse_prompt: reverse_prompt.to_string(),
+        author: Arc::new(Author {
+            name: LOCAL_AUTHOR.to_string(),
+            url: String::new(),
+            description: "Files imported from this computer".to_string(),
+        }),
+        ..Default::default()
+    };
+
+    let file = DownloadedFile {
+        id: Arc::new(format!("{model_id}#{name}")),
+        model_id,
+        name: name.to_string(),
+        size: file_size.to_string(),
+        quantization: metadata.quantization().unwrap_or_default().to_string(),
+        prompt_template: prompt_template.to_string(),
+        reverse_prompt: reverse_prompt.to_string(),
+        context_size: metadata.context_length().unwrap_or(1024),
+        downloaded: true,
+        file_size,
+        download_dir: dir.to_string_lossy().to_string(),
+        downloaded_at: Utc::now(),
+        imported: true,
+        ..Default::default()
+    };
+
+    Ok((model, file))
+}
+
+/// `name` made safe to use as the model part of an id.
+fn model_dir_name(name: &str) -> String {
+    name.chars()
+        .map(|c| match c {
+            '/' | '\\' | '#' => '_',
+            c => c,
+        })
+        .collect()
+}
+
+#[test]
+fn test_import() {
+    use super::gguf::GgufValue;
+    use super::model_cards::DownloadUrls;
+
+    let dir = std::env::temp_dir().join("moly_test_import");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(dir.join("nested")).unwrap();
+    let path = dir.join("nested/tiny-Q4_K_M.gguf");
+    gguf::write_test_file(
+        &path,
+        &[
+            ("general.architecture", GgufValue::String("qwen2".into())),
+            ("general.file_type", GgufValue::U32(15)),
+            ("qwen2.context_length", GgufValue::U64(32768)),
+            (
+                "tokenizer.chat_template",
+                GgufValue::String("<|im_start|>user".into()),
+            ),
+        ],
+        &[],
+    );
+    std::fs::write(dir.join("big-00001-of-00002.gguf"), "").unwrap();
+    std::fs::write(dir.join("big-00002-of-00002.gguf"), "").unwrap();
+    std::fs::write(dir.join("notes.txt"), "").unwrap();
+
+    assert_eq!(
+        gguf_files(std::slice::from_ref(&dir)),
+        vec![dir.join("big-00001-of-00002.gguf"), path.clone()]
+    );
+
+    let metadata = gguf::read_metadata(&path).unwrap();
+    let (model, file) = local_entry(&path, &metadata).unwrap();
+    assert_eq!(model.id.as_str(), "local/tiny-Q4_K_M");
+    assert_eq!(model.architecture, "qwen2");
+    assert_eq!(gguf::parameters_label(7_241_732_096), "7.2B");
+    assert_eq!(file.id.as_str(), "local/tiny-Q4_K_M#tiny-Q4_K_M.gguf");
+    assert_eq!(file.quantization, "Q4_K_M");
+    assert_eq!(file.context_size, 32768);
+    assert_eq!(file.prompt_template, "chatml");
+    assert_eq!(file.path(), path);
+
+    let sha256 = checksum::sha256_of_file(&path).unwrap();
+    let len = std::fs::metadata(&path).unwrap().len();
+    let card = |id: &str, name: &str, size: u64, sha256: &str| ModelCard {
+        id: id.to_string(),
+        name: String::new(),
+        summary: String::new(),
+        size: String::new(),
+        requires: String::new(),
+        architecture: String::new(),
+        released_at: Utc::now(),
+        files: vec![RemoteFile {
+            name: name.to_string(),
+            size: size.to_string(),
+            sha256: Some(sha256.to_string()),
+            download: DownloadUrls::default(),
+            ..Default::default()
+        }],
+        prompt_template: String::new(),
+        reverse_prompt: String::new(),
+        context_size: 0,
+        author: Author::default(),
+        like_count: 0,
+        download_count: 0,
+        metrics: None,
+        source: String::new(),
+    };
+
+    // By name first, by checksum for renamed files of the same size
+    let cards = vec![
+        card("org/a", "tiny-Q4_K_M.gguf", len, ""),
+        card("org/b", "renamed.gguf", len, &sha256),
+        card("org/c", "other-size.gguf", len + 1, &sha256),
+    ];
+    let (found, _) = find_in_catalog(&cards, &path).unwrap();
+    assert_eq!(found.id, "org/a");
+    let (found, _) = find_in_catalog(&cards[1..], &path).unwrap();
+    assert_eq!(found.id, "org/b");
+    assert!(find_in_catalog(&cards[2..], &path).is_none());
+    assert!(find_in_catalog(&cards[..0], &path).is_none());
+}
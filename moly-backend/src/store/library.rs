@@ -0,0 +1,319 @@
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+};
+
+use moly_protocol::data::LibraryIssue;
+
+use super::{
+    download_files::DownloadedFile,
+    download_segments::DownloadSegment,
+    download_shards::{self, DownloadShard},
+    models::Model,
+};
+
+/// Ids of the models with a directory in `root`, which is laid out like the models
+/// directory: `<root>/<author>/<model>/<file>`.
+pub fn model_ids(root: &Path) -> Vec<String> {
+    let subdirs = |dir: &Path| -> Vec<String> {
+        std::fs::read_dir(dir)
+            .map(|entries| {
+                entries
+                    .flatten()
+                    .filter(|entry| entry.file_type().is_ok_and(|t| t.is_dir()))
+                    .filter_map(|entry| entry.file_name().into_string().ok())
+                    .collect()
+            })
+            .unwrap_or_default()
+    };
+
+    let mut ids = vec![];
+    for author in subdirs(root) {
+        for model in subdirs(&root.join(&author)) {
+            ids.push(format!("{author}/{model}"));
+        }
+    }
+    ids
+}
+
+/// Size of each of `parts` on disk, `None` if any of them is missing.
+pub fn part_sizes(parts: &[DownloadedFile]) -> Option<Vec<u64>> {
+    parts
+        .iter()
+        .map(|part| std::fs::metadata(part.path()).ok().map(|meta| meta.len()))
+        .collect()
+}
+
+/// Forget the files listed from `root` that are not there anymore, e.g. removed
+/// from a shared directory by someone else.
+pub fn forget_missing(conn: &rusqlite::Connection, root: &Path) -> rusqlite::Result<()> {
+    let root = root.to_string_lossy();
+    let files = DownloadedFile::get_finished(conn)?;
+    for file in files.values().filter(|file| file.download_dir == root) {
+        let shards = DownloadShard::get_by_file(conn, &file.id)?;
+        if part_sizes(&download_shards::parts_of(file, &shards)).is_none() {
+            log::info!("{} is not in {} anymore", file.id, root);
+            DownloadedFile::remove(&file.id, conn)?;
+            DownloadShard::remove_by_file(conn, &file.id)?;
+        }
+    }
+    Ok(())
+}
+
+/// Remove the directories of `file` that are left empty, up to `root`.
+pub fn remove_empty_dirs(file: &Path, root: &Path) {
+    for dir in file.ancestors().skip(1) {
+        if dir == root || !dir.starts_with(root) || std::fs::remove_dir(dir).is_err() {
+            break;
+        }
+    }
+}
+
+/// Compare the records with the files on disk, and fix what is found when `repair`
+/// is set. Only files laid out like downloads are looked at in `models_dir`, and
+/// nothing is ever removed from `read_only_roots` or from where files were imported.
+pub fn check_library(
+    conn: &rusqlite::Connection,
+    models_dir: &Path,
+    read_only_roots: &[PathBuf],
+    repair: bool,
+) -> anyhow::Result<Vec<LibraryIssue>> {
+    let mut issues = vec![];
+
+    // Pending downloads own their partial files
+    let mut known = HashSet::new();
+    for file in DownloadedFile::get_pending(conn)?.values() {
+        let shards = DownloadShard::get_by_file(conn, &file.id)?;
+        known.extend(
+            download_shards::parts_of(file, &shards)
+                .iter()
+                .map(|part| part.path()),
+        );
+    }
+
+    for file in DownloadedFile::get_finished(conn)?.values() {
+        let shards = DownloadShard::get_by_file(conn, &file.id)?;
+        let parts = download_shards::parts_of(file, &shards);
+        known.extend(parts.iter().map(|part| part.path()));
+
+        let Some(issue) = part_issue(file, &parts) else {
+            continue;
+        };
+        if repair {
+            DownloadedFile::remove(&file.id, conn)?;
+            DownloadSegment::remove_by_file(conn, &file.id)?;
+            DownloadShard::remove_by_file(conn, &file.id)?;
+
+            let download_dir = Path::new(&file.download_dir);
+            let read_only = read_only_roots.iter().any(|root| root == download_dir);
+            if !file.imported && !read_only {
+                for part in &parts {
+                    let path = part.path();
+                    if std::fs::remove_file(&path).is_ok() {
+                        remove_empty_dirs(&path, download_dir);
+                    }
+                }
+            }
+        }
+        issues.push(issue);
+    }
+
+    for model_id in model_ids(models_dir) {
+        let model_dir = models_dir.join(&model_id);
+        let Ok(entries) = std::fs::read_dir(&model_dir) else {
+            continue;
+        };
+        let paths: Vec<_> = entries.flatten().map(|entry| entry.path()).collect();
+        for path in &paths {
+            let is_model_file = path.is_file()
+                && path
+                    .extension()
+                    .is_some_and(|ext| ext.eq_ignore_ascii_case("gguf"));
+            if !is_model_file || known.contains(path) {
+                continue;
+            }
+            let size = std::fs::metadata(path).map_or(0, |meta| meta.len());
+            if repair {
+                std::fs::remove_file(path)?;
+                remove_empty_dirs(path, models_dir);
+            }
+            issues.push(LibraryIssue::UnknownFile {
+                path: path.clone(),
+                size,
+            });
+        }
+        if paths.is_empty() {
+            if repair {
+                std::fs::remove_dir(&model_dir)?;
+                if let Some(author_dir) = model_dir.parent() {
+                    let _ = std::fs::remove_dir(author_dir);
+                }
+            }
+            issues.push(LibraryIssue::EmptyDirectory { path: model_dir });
+        }
+    }
+
+    // After the repairs above, which may leave more models without files
+    let mut model_ids: HashSet<_> = Model::get_all(conn)?.into_keys().collect();
+    for file in DownloadedFile::get_finished(conn)?
+        .into_values()
+        .chain(DownloadedFile::get_pending(conn)?.into_values())
+    {
+        model_ids.remove(&file.model_id);
+    }
+    for model_id in model_ids {
+        if repair {
+            Model::remove(conn, &model_id)?;
+        }
+        issues.push(LibraryIssue::OrphanedModel { model_id });
+    }
+
+    for issue in &issues {
+        log::info!("Library check: {:?}", issue);
+    }
+    Ok(issues)
+}
+
+/// The first part of a downloaded file that is missing or has the wrong size.
+fn part_issue(file: &DownloadedFile, parts: &[DownloadedFile]) -> Option<LibraryIssue> {
+    parts.iter().find_map(|part| {
+        let path = part.path();
+        match std::fs::metadata(&path) {
+            Err(_) => Some(LibraryIssue::MissingFile {
+                file_id: file.id.to_string(),
+                path,
+            }),
+            // Older records may not have the size
+            Ok(meta) if part.file_size > 0 && meta.len() != part.file_size => {
+                Some(LibraryIssue::SizeMismatch {
+                    file_id: file.id.to_string(),
+                    path,
+                    expected: part.file_size,
+                    actual: meta.len(),
+                })
+            }
+            Ok(_) => None,
+        }
+    })
+}
+
+#[test]
+fn test_library() {
+    use std::sync::Arc;
+
+    let root = std::env::temp_dir().join("moly_test_library");
+    let _ = std::fs::remove_dir_all(&root);
+    std::fs::create_dir_all(root.join("org/model")).unwrap();
+    std::fs::write(root.join("org/model/model.gguf"), [0u8; 16]).unwrap();
+    std::fs::write(root.join("README.md"), "").unwrap();
+
+    assert_eq!(model_ids(&root), vec!["org/model"]);
+
+    let file = DownloadedFile {
+        id: Arc::new("org/model#model.gguf".to_string()),
+        model_id: "org/model".to_string(),
+        name: "model.gguf".to_string(),
+        downloaded: true,
+        download_dir: root.to_string_lossy().to_string(),
+        ..Default::default()
+    };
+    let missing = DownloadedFile {
+        id: Arc::new("org/model#other.gguf".to_string()),
+        name: "other.gguf".to_string(),
+        ..file.clone()
+    };
+    assert_eq!(part_sizes(std::slice::from_ref(&file)), Some(vec![16]));
+    assert_eq!(part_sizes(&[file.clone(), missing.clone()]), None);
+
+    let conn = rusqlite::Connection::open_in_memory().unwrap();
+    super::download_files::create_table_download_files(&conn).unwrap();
+    super::download_shards::create_table_download_shards(&conn).unwrap();
+    file.insert_into_db(&conn).unwrap();
+    missing.insert_into_db(&conn).unwrap();
+
+    forget_missing(&conn, &root).unwrap();
+    let files = DownloadedFile::get_finished(&conn).unwrap();
+    assert!(files.contains_key(&file.id));
+    assert!(!files.contains_key(&missing.id));
+}
+
+#[test]
+fn test_check_library() {
+    use std::sync::Arc;
+
+    let root = std::env::temp_dir().join("moly_test_check_library");
+    let _ = std::fs::remove_dir_all(&root);
+    std::fs::create_dir_all(root.join("org/model")).unwrap();
+    std::fs::create_dir_all(root.join("org/empty")).unwrap();
+    std::fs::write(root.join("org/model/ok.gguf"), [0u8; 16]).unwrap();
+    std::fs::write(root.join("org/model/short.gguf"), [0u8; 8]).unwrap();
+    std::fs::write(root.join("org/model/stray.gguf"), [0u8; 4]).unwrap();
+    std::fs::write(root.join("org/model/notes.txt"), "").unwrap();
+
+    let conn = rusqlite::Connection::open_in_memory().unwrap();
+    super::download_files::create_table_download_files(&conn).unwrap();
+    super::download_shards::create_table_download_shards(&conn).unwrap();
+    super::download_segments::create_table_download_segments(&conn).unwrap();
+    super::models::create_table_models(&conn).unwrap();
+
+    let file = |name: &str| DownloadedFile {
+        id: Arc::new(format!("org/model#{name}")),
+        model_id: "org/model".to_string(),
+        name: name.to_string(),
+        downloaded: true,
+        file_size: 16,
+        download_dir: root.to_string_lossy().to_string(),
+        ..Default::default()
+    };
+    for name in ["ok.gguf", "short.gguf", "gone.gguf"] {
+        file(name).insert_into_db(&conn).unwrap();
+    }
+    let model = |id: &str| Model {
+        id: Arc::new(id.to_string()),
+        ..Default::default()
+    };
+    model("org/model").save_to_db(&conn).unwrap();
+    model("org/orphan").save_to_db(&conn).unwrap();
+
+    let mut issues = check_library(&conn, &root, &[], false).unwrap();
+    issues.sort_by_key(|issue| format!("{issue:?}"));
+    assert_eq!(
+        issues,
+        vec![
+            LibraryIssue::EmptyDirectory {
+                path: root.join("org/empty")
+            },
+            LibraryIssue::MissingFile {
+                file_id: "org/model#gone.gguf".to_string(),
+                path: root.join("org/model/gone.gguf"),
+            },
+            LibraryIssue::OrphanedModel {
+                model_id: "org/orphan".to_string()
+            },
+            LibraryIssue::SizeMismatch {
+                file_id: "org/model#short.gguf".to_string(),
+                path: root.join("org/model/short.gguf"),
+                expected: 16,
+                actual: 8,
+            },
+            LibraryIssue::UnknownFile {
+                path: root.join("org/model/stray.gguf"),
+                size: 4,
+            },
+        ]
+    );
+
+    // Checking only reports, repairing leaves nothing to report
+    assert_eq!(check_library(&conn, &root, &[], false).unwrap().len(), 5);
+    assert_eq!(check_library(&conn, &root, &[], true).unwrap().len(), 5);
+    assert!(check_library(&conn, &root, &[], false).unwrap().is_empty());
+
+    assert!(root.join("org/model/ok.gguf").exists());
+    assert!(root.join("org/model/notes.txt").exists());
+    assert!(!root.join("org/model/short.gguf").exists());
+    assert!(!root.join("org/model/stray.gguf").exists());
+    assert!(!root.join("org/empty").exists());
+    let files = DownloadedFile::get_finished(&conn).unwrap();
+    assert_eq!(files.len(), 1);
+    assert!(Model::get_all(&conn).unwrap().contains_key("org/model"));
+}
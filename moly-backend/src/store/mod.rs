@@ -1,12 +1,26 @@
+pub mod bandwidth;
+pub mod catalog;
+pub mod checksum;
+pub mod compatibility;
+pub mod credentials;
+pub mod disk_space;
 pub mod download_files;
+pub mod download_segments;
+pub mod download_shards;
+pub mod gguf;
+pub mod import;
+pub mod library;
 pub mod models;
+pub mod models_dir;
+pub mod network;
+pub mod preferences;
 pub mod remote;
 
 pub mod model_cards;
 
 use std::path::Path;
 
-use moly_protocol::data::FileID;
+use moly_protocol::{data::FileID, protocol::FileVerification};
 
 pub use remote::*;
 
@@ -17,8 +31,12 @@ pub fn get_all_download_file(
     let models = models::Model::get_all(&conn)?;
 
     let mut downloaded_files = Vec::with_capacity(files.len());
+    let system = compatibility::SystemInfo::detect();
+
+    for (_id, mut file) in files {
+        load_information(conn, &mut file);
+        let compatibility_guess = system.guess_downloaded(&file);
 
-    for (_id, file) in files {
         let model = if let Some(model) = models.get(&file.model_id) {
             moly_protocol::data::Model {
                 id: model.id.to_string(),
@@ -37,15 +55,13 @@ pub fn get_all_download_file(
                 like_count: model.like_count,
                 download_count: model.download_count,
                 metrics: Default::default(),
+                source: String::new(),
             }
         } else {
             moly_protocol::data::Model::default()
         };
 
-        let downloaded_path = Path::new(&file.download_dir)
-            .join(&file.model_id)
-            .join(&file.name);
-
+        let downloaded_path = file.path();
         let downloaded_path = downloaded_path.to_str().map(|s| s.to_string());
 
         let downloaded_file = moly_protocol::data::DownloadedFile {
@@ -58,11 +74,12 @@ pub fn get_all_download_file(
                 downloaded_path,
                 tags: file.tags,
                 featured: false,
+                compatibility_guess: compatibility_guess.clone(),
             },
             model,
             downloaded_at: file.downloaded_at,
-            compatibility_guess: moly_protocol::data::CompatibilityGuess::PossiblySupported,
-            information: String::new(),
+            compatibility_guess,
+            information: file.information,
         };
 
         downloaded_files.push(downloaded_file);
@@ -74,13 +91,17 @@ pub fn get_all_download_file(
 pub fn get_all_pending_downloads(
     conn: &rusqlite::Connection,
 ) -> rusqlite::Result<Vec<moly_protocol::data::PendingDownload>> {
-    let files = download_files::DownloadedFile::get_pending(&conn)?;
+    let mut files: Vec<_> = download_files::DownloadedFile::get_pending(&conn)?
+        .into_values()
+        .collect();
+    files.sort_by_key(|file| file.queue_position);
 
     let models = models::Model::get_all(&conn)?;
 
     let mut result = Vec::with_capacity(files.len());
+    let system = compatibility::SystemInfo::detect();
 
-    for (_file_id, file) in files {
+    for file in files {
         let result_file = moly_protocol::data::File {
             id: file.id.to_string(),
             name: file.name.clone(),
@@ -90,6 +111,7 @@ pub fn get_all_pending_downloads(
             downloaded_path: None,
             tags: file.tags.clone(),
             featured: file.featured,
+            compatibility_guess: system.guess_downloaded(&file),
         };
 
         let model = if let Some(model) = models.get(&file.model_id) {
@@ -110,28 +132,30 @@ pub fn get_all_pending_downloads(
                 like_count: model.like_count,
                 download_count: model.download_count,
                 metrics: Default::default(),
+                source: String::new(),
             }
         } else {
             moly_protocol::data::Model::default()
         };
 
-        let file_path = Path::new(&file.download_dir)
-            .join(&file.model_id)
-            .join(&file.name);
-
-        let downloaded = if let Ok(file_meta) = std::fs::metadata(file_path) {
-            file_meta.len()
+        let shards = download_shards::DownloadShard::get_by_file(conn, &file.id)?;
+        let mut downloaded = 0;
+        for part in download_shards::parts_of(&file, &shards) {
+            downloaded += downloaded_bytes(conn, &part)?;
+        }
+        // The size is only known once the download has started
+        let progress = if file.file_size > 0 {
+            (downloaded as f64 / file.file_size as f64) * 100.0
         } else {
-            0
+            0.0
         };
-        let progress = (downloaded as f64 / file.file_size as f64) * 100.0;
 
         let pending_download = moly_protocol::data::PendingDownload {
             file: result_file,
             model,
             progress,
-            status: moly_protocol::data::PendingDownloadsStatus::Paused,
-            //status: item.status.into(),
+            transfer: moly_protocol::data::DownloadProgress::new(downloaded, file.file_size, 0.0),
+            status: file.status.into(),
         };
 
         result.push(pending_download);
@@ -140,6 +164,48 @@ pub fn get_all_pending_downloads(
     Ok(result)
 }
 
+/// How much of a file on disk is downloaded.
+fn downloaded_bytes(
+    conn: &rusqlite::Connection,
+    file: &download_files::DownloadedFile,
+) -> rusqlite::Result<u64> {
+    if file.downloaded {
+        return Ok(file.file_size);
+    }
+
+    // Segmented downloads preallocate the whole file, so its length says nothing
+    // about how much of it is already there
+    let segments = download_segments::DownloadSegment::get_by_file(conn, &file.id)?;
+    if !segments.is_empty() {
+        return Ok(segments.iter().map(|s| s.downloaded).sum());
+    }
+
+    Ok(std::fs::metadata(file.path()).map_or(0, |meta| meta.len()))
+}
+
+/// Read what the header of `file` tells about it, the first time only. The context
+/// length the model was trained with replaces the one of the model card.
+pub fn load_information(conn: &rusqlite::Connection, file: &mut download_files::DownloadedFile) {
+    if !file.information.is_empty() {
+        return;
+    }
+
+    let metadata = match gguf::read_metadata(&file.path()) {
+        Ok(metadata) => metadata,
+        Err(e) => {
+            log::warn!("Failed to read the metadata of {}: {e}", file.id);
+            return;
+        }
+    };
+    file.information = serde_json::to_string(&metadata.information()).unwrap_or_default();
+    if let Some(context_length) = metadata.context_length() {
+        file.context_size = context_length;
+    }
+    if let Err(e) = file.update_information(conn) {
+        log::warn!("Failed to save the metadata of {}: {e}", file.id);
+    }
+}
+
 pub fn remove_downloaded_file(models_dir: String, file_id: FileID) -> anyhow::Result<()> {
     let (model_id, file) = file_id
         .split_once("#")
@@ -148,5 +214,70 @@ pub fn remove_downloaded_file(models_dir: String, file_id: FileID) -> anyhow::Re
     let filename = format!("{}/{}/{}", models_dir, model_id, file);
 
     log::info!("Removing file {}", filename);
-    Ok(std::fs::remove_file(filename)?)
+    std::fs::remove_file(&filename)?;
+    library::remove_empty_dirs(Path::new(&filename), Path::new(&models_dir));
+    Ok(())
+}
+
+/// Remove the shards of `file_id` beyond the first one, which is removed with the
+/// file itself, along with their records.
+pub fn remove_file_shards(
+    conn: &rusqlite::Connection,
+    models_dir: &str,
+    file_id: &str,
+) -> anyhow::Result<()> {
+    let shards = download_shards::DownloadShard::get_by_file(conn, file_id)?;
+    let model_id = file_id.split_once('#').map_or("", |(model_id, _)| model_id);
+
+    for shard in shards.iter().skip(1) {
+        let part_id = format!("{model_id}#{}", shard.name);
+        download_segments::DownloadSegment::remove_by_file(conn, &part_id)?;
+        let _ = remove_downloaded_file(models_dir.to_string(), part_id);
+    }
+    download_shards::DownloadShard::remove_by_file(conn, file_id)?;
+
+    Ok(())
+}
+
+/// Check every part of `file`, stopping at the first one that does not match.
+pub fn verify_downloaded_file(
+    file: &download_files::DownloadedFile,
+    shards: &[download_shards::DownloadShard],
+) -> anyhow::Result<FileVerification> {
+    let mut verification = FileVerification::NoChecksum;
+    for part in download_shards::parts_of(file, shards) {
+        match verify_downloaded_part(&part)? {
+            FileVerification::Verified => verification = FileVerification::Verified,
+            FileVerification::NoChecksum => {}
+            mismatch => return Ok(mismatch),
+        }
+    }
+    Ok(verification)
+}
+
+fn verify_downloaded_part(
+    file: &download_files::DownloadedFile,
+) -> anyhow::Result<FileVerification> {
+    let file_path = file.path();
+
+    if file.sha256.is_empty() {
+        // Still make sure the file is there
+        std::fs::metadata(&file_path)?;
+        return Ok(FileVerification::NoChecksum);
+    }
+
+    let actual = checksum::sha256_of_file(&file_path)?;
+    if checksum::matches(&file.sha256, &actual) {
+        Ok(FileVerification::Verified)
+    } else {
+        log::warn!(
+            "checksum mismatch for {}: expected {}, got {actual}",
+            file.id,
+            file.sha256
+        );
+        Ok(FileVerification::ChecksumMismatch {
+            expected: file.sha256.clone(),
+            actual,
+        })
+    }
 }
@@ -1,16 +1,25 @@
 use chrono::{DateTime, Utc};
-use git2::{ProxyOptions, Repository};
+use git2::Repository;
+use moly_protocol::data::{CatalogSourceConfig, CatalogUpdate, ProxyConfig};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::io::{self, Write};
 use std::path::{Path, PathBuf};
 use std::str;
-use std::sync::Arc;
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use super::catalog::{Catalog, CatalogSource, HuggingFaceSource, LocalDirectorySource};
+use super::compatibility::SystemInfo;
+use super::preferences::Preferences;
 
 fn do_fetch<'a>(
     repo: &'a git2::Repository,
     refs: &[&str],
     remote: &'a mut git2::Remote,
+    proxy: &ProxyConfig,
+    tags: bool,
 ) -> Result<git2::AnnotatedCommit<'a>, git2::Error> {
     let mut cb = git2::RemoteCallbacks::new();
 
@@ -37,14 +46,15 @@ fn do_fetch<'a>(
 
     let mut fo = git2::FetchOptions::new();
     fo.remote_callbacks(cb);
-    if let Ok(proxy) = std::env::var("https_proxy").or_else(|_| std::env::var("all_proxy")) {
-        let mut proxy_opt = ProxyOptions::new();
-        proxy_opt.url(&proxy);
+    if let Some(proxy_opt) =
+        super::network::git_proxy_options(proxy, remote.url().unwrap_or_default())
+    {
         fo.proxy_options(proxy_opt);
     }
-    // Always fetch all tags.
-    // Perform a download and also update tips
-    // fo.download_tags(git2::AutotagOption::All);
+    // Tags are only needed to find the revision the repository is pinned to
+    if tags {
+        fo.download_tags(git2::AutotagOption::All);
+    }
     log::debug!("Fetching {} for repo", remote.name().unwrap());
     remote.fetch(refs, Some(&mut fo), None)?;
 
@@ -73,231 +83,404 @@ fn do_fetch<'a>(
     Ok(repo.reference_to_annotated_commit(&fetch_head)?)
 }
 
-fn fast_forward(
-    repo: &Repository,
-    lb: &mut git2::Reference,
-    rc: &git2::AnnotatedCommit,
-) -> Result<(), git2::Error> {
-    let name = match lb.name() {
-        Some(s) => s.to_string(),
-        None => String::from_utf8_lossy(lb.name_bytes()).to_string(),
-    };
-    let msg = format!("Fast-Forward: Setting {} to id: {}", name, rc.id());
-    log::debug!("{}", msg);
-    lb.set_target(rc.id(), &msg)?;
-    repo.set_head(&name)?;
+/// Point HEAD at `target` and check it out over whatever the working tree has,
+/// files the repository does not track included. HEAD is detached when there is
+/// no `branch` to move.
+fn reset_to(repo: &Repository, target: git2::Oid, branch: Option<&str>) -> Result<(), git2::Error> {
+    let commit = repo.find_commit(target)?;
+    match branch {
+        Some(branch) => {
+            let refname = format!("refs/heads/{}", branch);
+            let msg = format!("Reset: Setting {} to id: {}", refname, target);
+            repo.reference(&refname, target, true, &msg)?;
+            repo.set_head(&refname)?;
+        }
+        None => repo.set_head_detached(target)?,
+    }
+    repo.reset(commit.as_object(), git2::ResetType::Hard, None)?;
+    // The reset leaves the files it does not know about
     repo.checkout_head(Some(
-        git2::build::CheckoutBuilder::default()
-            // For some reason the force is required to make the working directory actually get updated
-            // I suspect we should be adding some logic to handle dirty working directory states
-            // but this is just an example so maybe not.
-            .force(),
-    ))?;
-    Ok(())
+        git2::build::CheckoutBuilder::new()
+            .force()
+            .remove_untracked(true),
+    ))
 }
 
-fn normal_merge(
+/// Fetch `branch` and move the repository to `revision`, the fetched head when
+/// `None`. Local changes are dropped, nothing is ever merged.
+fn fetch_and_reset(
     repo: &Repository,
-    local: &git2::AnnotatedCommit,
-    remote: &git2::AnnotatedCommit,
-) -> Result<(), git2::Error> {
-    let local_tree = repo.find_commit(local.id())?.tree()?;
-    let remote_tree = repo.find_commit(remote.id())?.tree()?;
-    let ancestor = repo
-        .find_commit(repo.merge_base(local.id(), remote.id())?)?
-        .tree()?;
-    let mut idx = repo.merge_trees(&ancestor, &local_tree, &remote_tree, None)?;
-
-    if idx.has_conflicts() {
-        log::debug!("Merge conflicts detected...");
-        repo.checkout_index(Some(&mut idx), None)?;
-        return Ok(());
-    }
-    let result_tree = repo.find_tree(idx.write_tree_to(repo)?)?;
-    // now create the merge commit
-    let msg = format!("Merge: {} into {}", remote.id(), local.id());
-    let sig = repo.signature()?;
-    let local_commit = repo.find_commit(local.id())?;
-    let remote_commit = repo.find_commit(remote.id())?;
-    // Do our merge commit and set current branch head to that commit.
-    let _merge_commit = repo.commit(
-        Some("HEAD"),
-        &sig,
-        &sig,
-        &msg,
-        &result_tree,
-        &[&local_commit, &remote_commit],
-    )?;
-    // Set working tree to match head.
-    repo.checkout_head(None)?;
-    Ok(())
-}
-
-fn do_merge<'a>(
-    repo: &'a Repository,
-    remote_branch: &str,
-    fetch_commit: git2::AnnotatedCommit<'a>,
+    branch: &str,
+    revision: Option<&str>,
+    proxy: &ProxyConfig,
 ) -> Result<(), git2::Error> {
-    // 1. do a merge analysis
-    let analysis = repo.merge_analysis(&[&fetch_commit])?;
-
-    // 2. Do the appropriate merge
-    if analysis.0.is_fast_forward() {
-        log::debug!("Doing a fast forward");
-        // do a fast forward
-        let refname = format!("refs/heads/{}", remote_branch);
-        match repo.find_reference(&refname) {
-            Ok(mut r) => {
-                fast_forward(repo, &mut r, &fetch_commit)?;
-            }
-            Err(_) => {
-                // The branch doesn't exist so just set the reference to the
-                // commit directly. Usually this is because you are pulling
-                // into an empty repository.
-                repo.reference(
-                    &refname,
-                    fetch_commit.id(),
-                    true,
-                    &format!("Setting {} to {}", remote_branch, fetch_commit.id()),
-                )?;
-                repo.set_head(&refname)?;
-                repo.checkout_head(Some(
-                    git2::build::CheckoutBuilder::default()
-                        .allow_conflicts(true)
-                        .conflict_style_merge(true)
-                        .force(),
-                ))?;
-            }
-        };
-    } else if analysis.0.is_normal() {
-        // do a normal merge
-        let head_commit = repo.reference_to_annotated_commit(&repo.head()?)?;
-        normal_merge(&repo, &head_commit, &fetch_commit)?;
-    } else {
-        log::debug!("Nothing to do...");
-    }
-    Ok(())
+    let mut remote = repo.find_remote("origin")?;
+    let fetch_commit = do_fetch(repo, &[branch], &mut remote, proxy, revision.is_some())?;
+    let Some(revision) = revision else {
+        return reset_to(repo, fetch_commit.id(), Some(branch));
+    };
+    let target = repo
+        .revparse_single(revision)
+        .and_then(|object| object.peel_to_commit())
+        .map_err(|e| match e.code() {
+            // Not a sign of a broken repository, see `is_corruption`
+            git2::ErrorCode::NotFound => git2::Error::new(
+                git2::ErrorCode::NotFound,
+                git2::ErrorClass::Reference,
+                format!("No revision {} in the model cards repo", revision),
+            ),
+            _ => e,
+        })?;
+    reset_to(repo, target.id(), None)
 }
 
-pub fn pull(repo: &Repository, remote_name: &str, remote_branch: &str) -> Result<(), git2::Error> {
-    let mut remote = repo.find_remote(remote_name)?;
-    let fetch_commit = do_fetch(&repo, &[remote_branch], &mut remote)?;
-    do_merge(&repo, &remote_branch, fetch_commit)
+/// Whether the repository itself is at fault, rather than the network or the
+/// revision asked for.
+fn is_corruption(e: &git2::Error) -> bool {
+    let network = matches!(
+        e.class(),
+        git2::ErrorClass::Net
+            | git2::ErrorClass::Http
+            | git2::ErrorClass::Ssl
+            | git2::ErrorClass::Ssh
+    );
+    let missing_revision =
+        e.code() == git2::ErrorCode::NotFound && e.class() == git2::ErrorClass::Reference;
+    !network && !missing_revision
 }
 
-pub fn open_or_clone<P: AsRef<Path>>(url: &str, repo_path: P) -> Result<Repository, git2::Error> {
-    log::debug!(
-        "open_or_clone: url: {}, repo_path: {:?}",
-        url,
-        repo_path.as_ref()
-    );
-    if let Ok(repo) = Repository::open(&repo_path) {
-        log::debug!("open_or_clone: repo opened");
-        Ok(repo)
-    } else {
-        log::debug!("open_or_clone: cloning repo");
-        for _ in 0..2 {
-            let r = Repository::clone(url, &repo_path);
-            if r.is_ok() {
-                return r;
-            }
+fn clone(url: &str, repo_path: &Path, proxy: &ProxyConfig) -> Result<Repository, git2::Error> {
+    log::debug!("clone: url: {}, repo_path: {:?}", url, repo_path);
+    let clone = || {
+        let mut fo = git2::FetchOptions::new();
+        if let Some(proxy_opt) = super::network::git_proxy_options(proxy, url) {
+            fo.proxy_options(proxy_opt);
+        }
+        git2::build::RepoBuilder::new()
+            .fetch_options(fo)
+            .clone(url, repo_path)
+    };
+    for _ in 0..2 {
+        let r = clone();
+        if r.is_ok() {
+            return r;
         }
-        Repository::clone(url, &repo_path)
     }
+    clone()
 }
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
-pub struct IpResult {
-    #[serde(default, rename = "countryCode")]
-    country_code: String,
-}
-
-fn get_model_cards_repo() -> String {
-    let repo_url = std::env::var("MODEL_CARDS_REPO");
-    match repo_url {
-        Ok(url) => url,
-        Err(_) => {
-            match reqwest::blocking::get("http://ip-api.com/json")
-                .and_then(|r| r.json::<IpResult>())
-            {
-                Ok(ip_result) if ip_result.country_code == "CN" => {
-                    "https://gitcode.com/xun_csh/model-cards".to_string()
-                }
-                _ => "https://github.com/moxin-org/model-cards".to_string(),
+/// Bring the clone in `repo_path` to `revision` of `url`, the head of `branch`
+/// when `None`. The clone is a read-only mirror: it is reset rather than merged
+/// into, and cloned again when git can no longer make sense of it.
+pub fn update_mirror(
+    url: &str,
+    repo_path: &Path,
+    branch: &str,
+    revision: Option<&str>,
+    proxy: &ProxyConfig,
+) -> anyhow::Result<Repository> {
+    let broken = match Repository::open(repo_path) {
+        Ok(repo) => {
+            // The clone may come from another mirror
+            let r = repo
+                .remote_set_url("origin", url)
+                .and_then(|_| fetch_and_reset(&repo, branch, revision, proxy));
+            match r {
+                Ok(()) => return Ok(repo),
+                Err(e) if is_corruption(&e) => e,
+                Err(e) => return Err(e.into()),
             }
         }
+        Err(e) if repo_path.exists() => e,
+        Err(_) => return clone_at(url, repo_path, branch, revision, proxy),
+    };
+
+    log::warn!(
+        "The model cards repo {:?} is broken, cloning it again: {}",
+        repo_path,
+        broken
+    );
+    std::fs::remove_dir_all(repo_path)?;
+    clone_at(url, repo_path, branch, revision, proxy)
+}
+
+fn clone_at(
+    url: &str,
+    repo_path: &Path,
+    branch: &str,
+    revision: Option<&str>,
+    proxy: &ProxyConfig,
+) -> anyhow::Result<Repository> {
+    let repo = clone(url, repo_path, proxy)?;
+    if revision.is_some() {
+        fetch_and_reset(&repo, branch, revision, proxy)?;
+    }
+    Ok(repo)
+}
+
+// Mirrors of the model-cards repository used when none is configured
+pub const DEFAULT_MODEL_CARDS_MIRRORS: [&str; 2] = [
+    "https://github.com/moxin-org/model-cards",
+    "https://gitcode.com/xun_csh/model-cards",
+];
+
+const MIRROR_PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The repository to sync from: `MODEL_CARDS_REPO` when set, else the mirror of
+/// `preferences` answering first. When none answers, the mirror picked last time.
+fn select_model_cards_mirror(
+    client: &reqwest::blocking::Client,
+    preferences: &Preferences,
+) -> String {
+    if let Ok(url) = std::env::var("MODEL_CARDS_REPO") {
+        return url;
+    }
+
+    let mirrors = if preferences.model_cards_mirrors.is_empty() {
+        DEFAULT_MODEL_CARDS_MIRRORS.map(String::from).to_vec()
+    } else {
+        preferences.model_cards_mirrors.clone()
+    };
+    match super::network::fastest_url(client, &mirrors, MIRROR_PROBE_TIMEOUT) {
+        Some((url, latency)) => {
+            log::info!("Model cards mirror {url} answered in {:?}", latency);
+            url
+        }
+        None => {
+            log::warn!("No model cards mirror answered");
+            preferences
+                .model_cards_mirror
+                .clone()
+                .filter(|mirror| mirrors.contains(mirror))
+                .unwrap_or_else(|| mirrors[0].clone())
+        }
     }
 }
 
 pub static REPO_NAME: &'static str = "model-cards";
 
-pub fn sync_model_cards_repo<P: AsRef<Path>>(app_data_dir: P) -> anyhow::Result<ModelCardManager> {
-    let repo_url = get_model_cards_repo();
-    log::info!("Using model_cards repo: {}", repo_url);
-    let repo_dirs = app_data_dir.as_ref().join(REPO_NAME);
+// Branch of the model-cards repository followed when it is not pinned
+const REPO_BRANCH: &str = "main";
 
-    let repo = open_or_clone(&repo_url, &repo_dirs)?;
-    let mut r = Ok(());
-    for _ in 0..2 {
-        r = pull(&repo, "origin", "main");
-        if r.is_ok() {
-            break;
+// Last index downloaded from the model-cards release, read when offline
+const INDEX_CACHE_FILENAME: &str = "model_cards_index.json";
+
+// Index of the revision synced before the last one, restored by a rollback
+const PREVIOUS_INDEX_CACHE_FILENAME: &str = "model_cards_index.previous.json";
+
+#[derive(Debug, Deserialize, Serialize)]
+struct IndexCache {
+    updated_at: DateTime<Utc>,
+    // Commit the repository was at when the index was saved
+    #[serde(default)]
+    revision: Option<String>,
+    indexs: Vec<ModelIndex>,
+}
+
+impl IndexCache {
+    fn load(path: &Path) -> anyhow::Result<Self> {
+        Ok(serde_json::from_str(&std::fs::read_to_string(path)?)?)
+    }
+
+    /// Save as the index of `app_data_dir`, the one of another revision kept for
+    /// a rollback.
+    fn save(&self, app_data_dir: &Path) -> anyhow::Result<()> {
+        let path = app_data_dir.join(INDEX_CACHE_FILENAME);
+        if let Ok(current) = Self::load(&path) {
+            if current.revision != self.revision {
+                std::fs::rename(&path, app_data_dir.join(PREVIOUS_INDEX_CACHE_FILENAME))?;
+            }
         }
+        Ok(std::fs::write(path, serde_json::to_string(self)?)?)
     }
+}
 
-    if let Err(e) = r {
-        log::error!("Failed to pull: {:?}", e);
-        log::error!("please remove the repo({:?}) and try again", &repo_dirs);
+/// The model-cards git repository, cloned in the app data directory.
+pub struct ModelCardsRepo {
+    dir: PathBuf,
+    // Mirror the repository was synced from, `None` when read from the cache
+    pub mirror: Option<String>,
+    indexs: Vec<ModelIndex>,
+    // When the index was downloaded, or the repository last changed when the
+    // index comes from it
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+impl CatalogSource for ModelCardsRepo {
+    fn name(&self) -> &str {
+        REPO_NAME
     }
 
-    let index_url = format!("{}/releases/download/index_release/index.json", repo_url);
+    fn indexes(&self) -> anyhow::Result<Vec<ModelIndex>> {
+        Ok(self.indexs.clone())
+    }
 
-    let index_list = if let Ok(remote_index) =
-        reqwest::blocking::get(index_url).and_then(|r| r.json::<Vec<ModelIndex>>())
-    {
-        remote_index
-    } else {
-        let index_list = std::fs::read_to_string(repo_dirs.join("index.json"))?;
-        let index_list: Vec<ModelIndex> = serde_json::from_str(&index_list)?;
-        index_list
+    fn load_card(&self, index: &ModelIndex) -> anyhow::Result<ModelCard> {
+        index.load_model_card(&self.dir)
+    }
+}
+
+pub fn sync_model_cards_repo<P: AsRef<Path>>(
+    app_data_dir: P,
+    preferences: &Preferences,
+) -> anyhow::Result<(ModelCardsRepo, EmbeddingState)> {
+    let proxy = &preferences.proxy;
+    let client = super::network::blocking_client(proxy)?;
+
+    let repo_url = select_model_cards_mirror(&client, preferences);
+    log::info!("Using model_cards repo: {}", repo_url);
+    let repo_dirs = app_data_dir.as_ref().join(REPO_NAME);
+    let revision = preferences.model_cards_revision.as_deref();
+
+    if let Err(e) = update_mirror(&repo_url, &repo_dirs, REPO_BRANCH, revision, proxy) {
+        log::error!("Failed to update the model cards repo: {:?}", e);
+    }
+
+    let indexs = match revision {
+        // The released index follows the branch, a pinned revision has its own
+        Some(_) => std::fs::read_to_string(repo_dirs.join("index.json"))
+            .map_err(anyhow::Error::from)
+            .and_then(|index_list| Ok(serde_json::from_str::<Vec<ModelIndex>>(&index_list)?)),
+        None => {
+            let index_url = format!("{}/releases/download/index_release/index.json", repo_url);
+            client
+                .get(index_url)
+                .send()
+                .and_then(|r| r.json::<Vec<ModelIndex>>())
+                .map_err(anyhow::Error::from)
+        }
     };
 
-    let mut indexs = HashMap::with_capacity(index_list.len());
-    for index in index_list {
-        indexs.insert(index.id.clone(), index);
-    }
-
-    let embedding_index =
-        if let Ok(embedding_index) = std::fs::read_to_string(repo_dirs.join("embedding.json")) {
-            let embedding_index: EmbeddingIndex = serde_json::from_str(&embedding_index)?;
-            if !embedding_index.check_file_exist(app_data_dir.as_ref()) {
-                let app_data_dir_path = app_data_dir.as_ref().to_path_buf();
-                let r = std::thread::spawn(move || {
-                    if let Ok(_) = embedding_index.download(&app_data_dir_path) {
-                        log::debug!("Downloaded embedding model ok");
-                        Some(embedding_index)
-                    } else {
-                        log::warn!("Failed to download embedding model");
-                        None
-                    }
-                });
-                EmbeddingState::Pending(r)
-            } else {
-                EmbeddingState::Finish(Some(embedding_index))
+    let repo = match indexs {
+        Ok(indexs) => {
+            let cache = IndexCache {
+                updated_at: Utc::now(),
+                revision: head_revision(&repo_dirs),
+                indexs,
+            };
+            if let Err(e) = cache.save(app_data_dir.as_ref()) {
+                log::warn!("Failed to cache the model index: {e}");
             }
-        } else {
-            EmbeddingState::Finish(None)
-        };
+            ModelCardsRepo {
+                dir: repo_dirs,
+                mirror: Some(repo_url),
+                indexs: cache.indexs,
+                updated_at: Some(cache.updated_at),
+            }
+        }
+        Err(e) => {
+            log::warn!("Failed to load the model index: {e}");
+            ModelCardsRepo {
+                mirror: Some(repo_url),
+                ..cached_model_cards_repo(app_data_dir.as_ref())?
+            }
+        }
+    };
+
+    let embedding_index = load_embedding_index(app_data_dir.as_ref(), &repo.dir, Some(client))?;
+    Ok((repo, embedding_index))
+}
 
-    Ok(ModelCardManager {
-        app_data_dir: app_data_dir.as_ref().to_path_buf(),
-        embedding_index,
+/// Go back to the revision synced before the current one, with its index and
+/// without network access. Rolling back again returns to the current one.
+/// Returns the revision rolled back to.
+pub fn rollback_model_cards_repo(app_data_dir: &Path) -> anyhow::Result<(ModelCardsRepo, String)> {
+    let previous_path = app_data_dir.join(PREVIOUS_INDEX_CACHE_FILENAME);
+    let previous = IndexCache::load(&previous_path)
+        .map_err(|_| anyhow::anyhow!("No previous catalog revision to roll back to"))?;
+    let Some(revision) = previous.revision.clone() else {
+        anyhow::bail!("The previous catalog revision is unknown");
+    };
+
+    let dir = app_data_dir.join(REPO_NAME);
+    let repo = Repository::open(&dir)?;
+    reset_to(&repo, git2::Oid::from_str(&revision)?, None)?;
+
+    let cache_path = app_data_dir.join(INDEX_CACHE_FILENAME);
+    std::fs::rename(&cache_path, &previous_path)?;
+    std::fs::write(&cache_path, serde_json::to_string(&previous)?)?;
+
+    let repo = ModelCardsRepo {
+        dir,
+        mirror: None,
+        indexs: previous.indexs,
+        updated_at: Some(previous.updated_at),
+    };
+    Ok((repo, revision))
+}
+
+/// The model-cards repository as the last sync left it, without any network
+/// access. The cached index is used unless the repository is newer.
+pub fn cached_model_cards_repo(app_data_dir: &Path) -> anyhow::Result<ModelCardsRepo> {
+    let dir = app_data_dir.join(REPO_NAME);
+    let commit_time = last_commit_time(&dir);
+    let head = head_revision(&dir);
+    let cache = IndexCache::load(&app_data_dir.join(INDEX_CACHE_FILENAME))
+        .ok()
+        .filter(|cache| match (&cache.revision, &head) {
+            (Some(revision), Some(head)) => revision == head,
+            _ => commit_time.map_or(true, |time| time <= cache.updated_at),
+        });
+
+    let (indexs, updated_at) = match cache {
+        Some(cache) => (cache.indexs, Some(cache.updated_at)),
+        None => {
+            let index_list = std::fs::read_to_string(dir.join("index.json"))?;
+            (serde_json::from_str(&index_list)?, commit_time)
+        }
+    };
+    Ok(ModelCardsRepo {
+        dir,
+        mirror: None,
         indexs,
-        caches: HashMap::new(),
+        updated_at,
     })
 }
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
+fn head_revision(repo_dir: &Path) -> Option<String> {
+    let repo = Repository::open(repo_dir).ok()?;
+    let commit = repo.head().ok()?.peel_to_commit().ok()?;
+    Some(commit.id().to_string())
+}
+
+fn last_commit_time(repo_dir: &Path) -> Option<DateTime<Utc>> {
+    let repo = Repository::open(repo_dir).ok()?;
+    let commit = repo.head().ok()?.peel_to_commit().ok()?;
+    DateTime::from_timestamp(commit.time().seconds(), 0)
+}
+
+/// The embedding model listed in the repository. It is downloaded in the
+/// background when missing, unless there is no `client` to do it with.
+fn load_embedding_index(
+    app_data_dir: &Path,
+    repo_dir: &Path,
+    client: Option<reqwest::blocking::Client>,
+) -> anyhow::Result<EmbeddingState> {
+    let Ok(embedding_index) = std::fs::read_to_string(repo_dir.join("embedding.json")) else {
+        return Ok(EmbeddingState::Finish(None));
+    };
+    let embedding_index: EmbeddingIndex = serde_json::from_str(&embedding_index)?;
+    if embedding_index.check_file_exist(app_data_dir) {
+        return Ok(EmbeddingState::Finish(Some(embedding_index)));
+    }
+    let Some(client) = client else {
+        return Ok(EmbeddingState::Finish(None));
+    };
+
+    let app_data_dir_path = app_data_dir.to_path_buf();
+    let r = std::thread::spawn(move || {
+        if let Ok(_) = embedding_index.download(&app_data_dir_path, &client) {
+            log::debug!("Downloaded embedding model ok");
+            Some(embedding_index)
+        } else {
+            log::warn!("Failed to download embedding model");
+            None
+        }
+    });
+    Ok(EmbeddingState::Pending(r))
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
 pub struct ModelIndex {
     pub id: String,
     pub name: String,
@@ -313,10 +496,30 @@ pub struct ModelIndex {
     pub like_count: u32,
     #[serde(default)]
     pub download_count: u32,
+    // Name of the catalog source that listed the model
+    #[serde(default)]
+    pub source: String,
 }
 
 impl ModelIndex {
-    pub fn load_model_card(&self, app_data_dir: &Path) -> anyhow::Result<ModelCard> {
+    pub fn is_chat(&self) -> bool {
+        self.model_type == "instruct" || self.model_type == "chat"
+    }
+
+    pub fn matches(&self, search_text: &str) -> bool {
+        let search_text = search_text.trim().to_ascii_lowercase();
+        self.name.to_ascii_lowercase().contains(&search_text)
+            || self
+                .architecture
+                .to_ascii_lowercase()
+                .contains(&search_text)
+            || self.id.to_ascii_lowercase().contains(&search_text)
+            || self.summary.to_ascii_lowercase().contains(&search_text)
+    }
+
+    /// The card of this model in `cards_dir`, laid out like the model-cards
+    /// repository.
+    pub fn load_model_card(&self, cards_dir: &Path) -> anyhow::Result<ModelCard> {
         let (org_name, model_name) = self
             .id
             .split_once("/")
@@ -328,10 +531,7 @@ impl ModelIndex {
             model_name
         };
 
-        let model_card_path = app_data_dir
-            .join(REPO_NAME)
-            .join(org_name)
-            .join(format!("{}.json", sub_name));
+        let model_card_path = cards_dir.join(org_name).join(format!("{}.json", sub_name));
         let model_card = std::fs::read_to_string(model_card_path)?;
         let mut model_card: ModelCard = serde_json::from_str(&model_card)?;
         model_card.like_count = self.like_count;
@@ -384,6 +584,8 @@ pub struct ModelCard {
     pub download_count: u32,
     #[serde(default)]
     pub metrics: Option<HashMap<String, f32>>,
+    #[serde(default)]
+    pub source: String,
 }
 
 #[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
@@ -399,6 +601,19 @@ pub struct RemoteFile {
     #[serde(default)]
     pub sha256: Option<String>,
     pub download: DownloadUrls,
+    // Parts of a model split in several files, in order. The first one must be
+    // named like the file itself, it is the one given to the loader. `sha256` and
+    // `download` above are ignored for sharded files.
+    #[serde(default)]
+    pub shards: Vec<RemoteShard>,
+}
+
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+pub struct RemoteShard {
+    pub name: String,
+    #[serde(default)]
+    pub sha256: Option<String>,
+    pub download: DownloadUrls,
 }
 
 #[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
@@ -411,6 +626,7 @@ impl ModelCard {
     pub fn to_model(
         remote_models: &[Self],
         conn: &rusqlite::Connection,
+        system: &SystemInfo,
     ) -> rusqlite::Result<Vec<moly_protocol::data::Model>> {
         let model_ids = remote_models
             .iter()
@@ -419,18 +635,15 @@ impl ModelCard {
         let files = super::download_files::DownloadedFile::get_by_models(conn, &model_ids)?;
 
         fn to_file(
-            model_id: &str,
-            remote_files: &[RemoteFile],
+            remote_m: &ModelCard,
             save_files: &HashMap<Arc<String>, super::download_files::DownloadedFile>,
+            system: &SystemInfo,
         ) -> rusqlite::Result<Vec<moly_protocol::data::File>> {
             let mut files = vec![];
-            for remote_f in remote_files {
-                let file_id = format!("{}#{}", model_id, remote_f.name);
+            for remote_f in &remote_m.files {
+                let file_id = format!("{}#{}", remote_m.id, remote_f.name);
                 let downloaded_path = save_files.get(&file_id).map(|file| {
-                    let file_path = Path::new(&file.download_dir)
-                        .join(&file.model_id)
-                        .join(&file.name);
-                    file_path
+                    file.path()
                         .to_str()
                         .map(|s| s.to_string())
                         .unwrap_or_default()
@@ -445,6 +658,7 @@ impl ModelCard {
                     downloaded_path,
                     tags: remote_f.tags.clone(),
                     featured: false,
+                    compatibility_guess: system.guess_remote(&remote_f.size, remote_m.context_size),
                 };
 
                 files.push(file);
@@ -464,7 +678,7 @@ impl ModelCard {
                 requires: remote_m.requires.clone(),
                 architecture: remote_m.architecture.clone(),
                 released_at: remote_m.released_at.clone(),
-                files: to_file(&remote_m.id, &remote_m.files, &files)?,
+                files: to_file(remote_m, &files, system)?,
                 author: moly_protocol::data::Author {
                     name: remote_m.author.name.clone(),
                     url: remote_m.author.url.clone(),
@@ -473,6 +687,7 @@ impl ModelCard {
                 like_count: remote_m.like_count.clone(),
                 download_count: remote_m.download_count.clone(),
                 metrics: remote_m.metrics.clone().unwrap_or_default(),
+                source: remote_m.source.clone(),
             };
 
             models.push(model);
@@ -485,8 +700,20 @@ impl ModelCard {
 pub struct ModelCardManager {
     app_data_dir: PathBuf,
     embedding_index: EmbeddingState,
-    indexs: HashMap<String, ModelIndex>,
-    caches: HashMap<String, ModelCard>,
+    sources: Vec<Arc<dyn CatalogSource>>,
+    // No source touches the network while set, the cached index is used
+    offline: bool,
+    // When the model-cards index was last updated
+    updated_at: Option<DateTime<Utc>>,
+    // Mirror the model-cards repository was last synced from
+    mirror: Option<String>,
+    catalog: Catalog,
+    // Sync of the model-cards repository running in the background
+    pending_sync: Option<Receiver<anyhow::Result<(ModelCardsRepo, EmbeddingState)>>>,
+    // Preferences changed while syncing, another sync follows the running one
+    resync: Option<Preferences>,
+    // Told what each sync changed, dropped once they stop listening
+    watchers: Arc<Mutex<Vec<Sender<anyhow::Result<CatalogUpdate>>>>>,
 }
 
 pub enum EmbeddingState {
@@ -498,65 +725,238 @@ impl ModelCardManager {
     pub fn empty(app_data_dir: PathBuf) -> Self {
         Self {
             app_data_dir,
-            indexs: HashMap::new(),
-            caches: HashMap::new(),
+            sources: vec![],
+            offline: false,
+            updated_at: None,
+            mirror: None,
+            catalog: Catalog::default(),
             embedding_index: EmbeddingState::Finish(None),
+            pending_sync: None,
+            resync: None,
+            watchers: Arc::new(Mutex::new(vec![])),
         }
     }
 
-    pub fn load_model_card(&mut self, index: &ModelIndex) -> anyhow::Result<ModelCard> {
-        let r = self
-            .caches
-            .entry(index.id.clone())
-            .or_insert(index.load_model_card(&self.app_data_dir)?);
-        Ok(r.clone())
+    pub fn new(app_data_dir: PathBuf, preferences: &Preferences, offline: bool) -> Self {
+        let mut manager = Self::empty(app_data_dir);
+        manager.offline = offline;
+        manager.set_sources(preferences);
+        manager
     }
 
-    pub fn get_index_by_id(&self, id: &str) -> Option<&ModelIndex> {
-        self.indexs.get(id)
+    pub fn is_offline(&self) -> bool {
+        self.offline
     }
 
-    pub fn search(
-        &self,
-        search_text: &str,
-        limit: usize,
-        offset: usize,
-    ) -> reqwest::Result<Vec<ModelIndex>> {
-        let search_text = search_text.trim().to_ascii_lowercase();
-        Ok(self
-            .indexs
-            .values()
-            .filter(|index| {
-                (index.model_type == "instruct" || index.model_type == "chat")
-                    && (index.name.to_ascii_lowercase().contains(&search_text)
-                        || index
-                            .architecture
-                            .to_ascii_lowercase()
-                            .contains(&search_text)
-                        || index.id.to_ascii_lowercase().contains(&search_text)
-                        || index.summary.to_ascii_lowercase().contains(&search_text))
-            })
-            .map(Clone::clone)
-            .skip(offset)
-            .take(limit)
-            .collect::<Vec<ModelIndex>>())
-    }
-
-    pub fn get_featured_model(
-        &self,
-        limit: usize,
-        offset: usize,
-    ) -> reqwest::Result<Vec<ModelIndex>> {
-        Ok(self
-            .indexs
-            .values()
-            .filter(|index| {
-                (index.model_type == "instruct" || index.model_type == "chat") && index.featured
-            })
-            .map(Clone::clone)
-            .skip(offset)
-            .take(limit)
-            .collect::<Vec<ModelIndex>>())
+    pub fn updated_at(&self) -> Option<DateTime<Utc>> {
+        self.updated_at
+    }
+
+    pub fn mirror(&self) -> Option<&str> {
+        self.mirror.as_deref()
+    }
+
+    /// Going online syncs the model-cards repository again, going offline drops
+    /// the sources that need the network.
+    pub fn set_offline(&mut self, offline: bool, preferences: &Preferences) {
+        let was_offline = std::mem::replace(&mut self.offline, offline);
+        if was_offline == offline {
+            return;
+        }
+        if !offline {
+            self.sources.clear();
+        }
+        self.set_sources(preferences);
+    }
+
+    /// Read the catalog from the sources of `preferences`, in order, the
+    /// model-cards repository alone when there are none. The repository is only
+    /// synced when it was not a source already, in the background.
+    pub fn set_sources(&mut self, preferences: &Preferences) {
+        let configs = if preferences.catalog_sources.is_empty() {
+            &[CatalogSourceConfig::ModelCards][..]
+        } else {
+            &preferences.catalog_sources[..]
+        };
+
+        let mut old_sources = std::mem::take(&mut self.sources);
+        for config in configs {
+            let source: anyhow::Result<Arc<dyn CatalogSource>> = match config {
+                CatalogSourceConfig::ModelCards => {
+                    match old_sources.iter().position(|s| s.name() == REPO_NAME) {
+                        Some(i) => Ok(old_sources.swap_remove(i)),
+                        None => self.load_model_cards_repo(preferences),
+                    }
+                }
+                CatalogSourceConfig::LocalDirectory(dir) => {
+                    Ok(Arc::new(LocalDirectorySource::new(dir.clone())))
+                }
+                CatalogSourceConfig::HuggingFace(_) if self.offline => {
+                    log::info!("Leaving the Hugging Face catalog out while offline");
+                    continue;
+                }
+                CatalogSourceConfig::HuggingFace(endpoint) => {
+                    super::network::blocking_client(&preferences.proxy).map(|client| {
+                        Arc::new(HuggingFaceSource::new(endpoint.clone(), client))
+                            as Arc<dyn CatalogSource>
+                    })
+                }
+            };
+            match source {
+                Ok(source) => self.sources.push(source),
+                Err(e) => log::error!("Failed to load the catalog source {:?}: {e}", config),
+            }
+        }
+
+        self.refresh();
+    }
+
+    /// The model-cards repository as the last sync left it, synced again in the
+    /// background unless offline.
+    fn load_model_cards_repo(
+        &mut self,
+        preferences: &Preferences,
+    ) -> anyhow::Result<Arc<dyn CatalogSource>> {
+        let repo = match cached_model_cards_repo(&self.app_data_dir) {
+            Ok(repo) => repo,
+            // Nothing synced yet, the catalog fills up once the sync is done
+            Err(_) if !self.offline => ModelCardsRepo {
+                dir: self.app_data_dir.join(REPO_NAME),
+                mirror: None,
+                indexs: vec![],
+                updated_at: None,
+            },
+            Err(e) => return Err(e),
+        };
+        self.embedding_index = load_embedding_index(&self.app_data_dir, &repo.dir, None)?;
+        self.updated_at = repo.updated_at;
+        self.mirror = None;
+        if !self.offline {
+            self.start_sync(repo.indexs.clone(), preferences);
+        }
+        Ok(Arc::new(repo))
+    }
+
+    /// Sync the model-cards repository again in the background. The watchers
+    /// are told what changed once done. When a sync is running already, the new
+    /// one starts after it, so that it uses `preferences`.
+    pub fn sync(&mut self, preferences: &Preferences) -> anyhow::Result<()> {
+        if self.offline {
+            anyhow::bail!("Cannot sync the catalog while offline, turn the offline mode off first");
+        }
+        let Some(repo) = self.sources.iter().find(|s| s.name() == REPO_NAME) else {
+            anyhow::bail!("The model-cards repository is not a catalog source");
+        };
+        if self.pending_sync.is_some() {
+            self.resync = Some(preferences.clone());
+            return Ok(());
+        }
+        let old_indexs = repo.indexes()?;
+        self.start_sync(old_indexs, preferences);
+        Ok(())
+    }
+
+    /// Two syncs would update the same clone at once, the one running is kept.
+    fn start_sync(&mut self, old_indexs: Vec<ModelIndex>, preferences: &Preferences) {
+        if self.pending_sync.is_some() {
+            return;
+        }
+        let (tx, rx) = mpsc::channel();
+        let app_data_dir = self.app_data_dir.clone();
+        let preferences = preferences.clone();
+        let watchers = self.watchers.clone();
+        std::thread::spawn(move || {
+            let r = sync_model_cards_repo(&app_data_dir, &preferences);
+            let update = match &r {
+                Ok((repo, _)) => {
+                    log::info!("sync model cards repo success");
+                    Ok(catalog_update(&old_indexs, repo))
+                }
+                Err(e) => {
+                    log::error!("Failed to sync the model cards repo: {e}");
+                    Err(e.to_string())
+                }
+            };
+            // The new catalog is ready before the watchers ask for it
+            let _ = tx.send(r);
+            notify_watchers(&watchers, update);
+        });
+        self.pending_sync = Some(rx);
+    }
+
+    /// Serve the catalog of the background sync once it is done. Returns whether
+    /// the catalog changed.
+    pub fn poll_sync(&mut self) -> bool {
+        let Some(pending_sync) = &self.pending_sync else {
+            return false;
+        };
+        let r = match pending_sync.try_recv() {
+            Ok(r) => r,
+            Err(TryRecvError::Empty) => return false,
+            Err(TryRecvError::Disconnected) => Err(anyhow::anyhow!("The sync stopped")),
+        };
+        self.pending_sync = None;
+
+        let changed = match r {
+            Ok((repo, embedding_index)) => self.serve_synced(repo, embedding_index),
+            Err(_) => false,
+        };
+        if let Some(preferences) = self.resync.take() {
+            if let Err(e) = self.sync(&preferences) {
+                log::warn!("Failed to sync the catalog again: {e}");
+            }
+        }
+        changed
+    }
+
+    fn serve_synced(&mut self, repo: ModelCardsRepo, embedding_index: EmbeddingState) -> bool {
+        // The sources may have changed while syncing
+        let Some(source) = self.sources.iter_mut().find(|s| s.name() == REPO_NAME) else {
+            return false;
+        };
+        self.embedding_index = embedding_index;
+        self.updated_at = repo.updated_at;
+        self.mirror = repo.mirror.clone();
+        *source = Arc::new(repo);
+        self.refresh();
+        true
+    }
+
+    pub fn watch(&mut self, tx: Sender<anyhow::Result<CatalogUpdate>>) {
+        self.watchers.lock().unwrap().push(tx);
+    }
+
+    /// Go back to the catalog synced before the current one, and pin `preferences`
+    /// to its revision so that the next sync keeps it. Returns the revision of the
+    /// model-cards repository rolled back to.
+    pub fn rollback(&mut self, preferences: &mut Preferences) -> anyhow::Result<String> {
+        if self.pending_sync.is_some() {
+            anyhow::bail!("The catalog is syncing, roll back once it is done");
+        }
+        let Some(source) = self.sources.iter_mut().find(|s| s.name() == REPO_NAME) else {
+            anyhow::bail!("The model-cards repository is not a catalog source");
+        };
+        let old_indexs = source.indexes()?;
+        let (repo, revision) = rollback_model_cards_repo(&self.app_data_dir)?;
+        let update = catalog_update(&old_indexs, &repo);
+        self.updated_at = repo.updated_at;
+        // Not synced from any mirror
+        self.mirror = None;
+        *source = Arc::new(repo);
+        self.refresh();
+        notify_watchers(&self.watchers, Ok(update));
+        preferences.model_cards_revision = Some(revision.clone());
+        Ok(revision)
+    }
+
+    /// List the models of every source again.
+    pub fn refresh(&mut self) {
+        self.catalog = Catalog::new(self.sources.clone());
+    }
+
+    /// The catalog as it is now, to search off the backend thread.
+    pub fn catalog(&self) -> Catalog {
+        self.catalog.clone()
     }
 
     pub fn embedding_model(&mut self) -> Option<(PathBuf, u64)> {
@@ -591,6 +991,33 @@ impl ModelCardManager {
     }
 }
 
+fn notify_watchers(
+    watchers: &Mutex<Vec<Sender<anyhow::Result<CatalogUpdate>>>>,
+    update: Result<CatalogUpdate, String>,
+) {
+    watchers.lock().unwrap().retain(|watcher| {
+        let update = update.clone().map_err(|e| anyhow::anyhow!(e));
+        watcher.send(update).is_ok()
+    });
+}
+
+/// Models added and changed in `repo` since the catalog listed `old_indexs`.
+fn catalog_update(old_indexs: &[ModelIndex], repo: &ModelCardsRepo) -> CatalogUpdate {
+    let old_indexs: HashMap<_, _> = old_indexs.iter().map(|i| (&i.id, i)).collect();
+    let mut update = CatalogUpdate {
+        updated_at: repo.updated_at,
+        ..Default::default()
+    };
+    for index in &repo.indexs {
+        match old_indexs.get(&index.id) {
+            None => update.new_models += 1,
+            Some(old_index) if *old_index != index => update.changed_models += 1,
+            Some(_) => {}
+        }
+    }
+    update
+}
+
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct EmbeddingIndex {
     id: String,
@@ -609,15 +1036,191 @@ impl EmbeddingIndex {
         file_path.exists()
     }
 
-    pub fn download(&self, app_data_dir: &Path) -> anyhow::Result<()> {
+    pub fn download(
+        &self,
+        app_data_dir: &Path,
+        client: &reqwest::blocking::Client,
+    ) -> anyhow::Result<()> {
         let file_path = self.file_path(app_data_dir);
 
         let _ = std::fs::create_dir_all(file_path.parent().unwrap());
 
-        let client = reqwest::blocking::Client::new();
         let mut resp = client.get(&self.download).send()?;
         let mut file = std::fs::File::create(file_path)?;
         std::io::copy(&mut resp, &mut file)?;
         Ok(())
     }
 }
+
+#[test]
+fn test_catalog_sources() {
+    let dir = std::env::temp_dir().join(format!("moly-sources-test-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    let write_card = |source: &str, name: &str, id: &str, summary: &str| {
+        let org_dir = dir.join(source).join("org");
+        std::fs::create_dir_all(&org_dir).unwrap();
+        let card = serde_json::json!({
+            "id": id,
+            "summary": summary,
+            "released_at": "2024-05-01T00:00:00Z",
+            "prompt_template": "chatml",
+            "reverse_prompt": "<|im_end|>",
+            "context_size": 2048,
+            "author": {"name": "org", "url": "", "description": ""},
+        });
+        std::fs::write(org_dir.join(format!("{name}.json")), card.to_string()).unwrap();
+    };
+    write_card("first", "tiny", "org/Tiny", "From the first source");
+    write_card("second", "tiny", "org/Tiny", "From the second source");
+    write_card("second", "small", "org/Small", "Small model");
+
+    let mut preferences = Preferences::default();
+    preferences.catalog_sources = vec![
+        CatalogSourceConfig::LocalDirectory(dir.join("first")),
+        CatalogSourceConfig::LocalDirectory(dir.join("second")),
+    ];
+    let manager = ModelCardManager::new(dir.clone(), &preferences, false);
+
+    let catalog = manager.catalog();
+    let found = catalog.search("", 10, 0);
+    let ids: Vec<_> = found.iter().map(|index| index.id.as_str()).collect();
+    assert_eq!(ids, ["org/Tiny", "org/Small"]);
+    assert!(found.iter().all(|index| index.source == "local-directory"));
+    assert_eq!(found[0].summary, "From the first source");
+    assert_eq!(catalog.search("", 10, 1).len(), 1);
+    assert_eq!(catalog.featured(10, 0).len(), 2);
+    assert_eq!(catalog.load_cards(&found).len(), 2);
+
+    // Only the second directory has this card
+    let card = manager.catalog().load_card(&found[1]).unwrap();
+    assert_eq!(card.summary, "Small model");
+    assert_eq!(card.source, "local-directory");
+
+    assert!(manager.catalog().find("org/Tiny").is_some());
+    assert!(manager.catalog().find("org/Missing").is_none());
+
+    // Offline, the repository is read as the last sync left it and the Hub is
+    // left out
+    write_card(REPO_NAME, "tiny", "org/Tiny", "From the repository");
+    std::fs::write(
+        dir.join(REPO_NAME).join("index.json"),
+        r#"[{"id": "org/Tiny", "name": "tiny", "model_type": "chat", "featured": true}]"#,
+    )
+    .unwrap();
+    preferences.catalog_sources = vec![
+        CatalogSourceConfig::ModelCards,
+        CatalogSourceConfig::HuggingFace(None),
+    ];
+    let manager = ModelCardManager::new(dir.clone(), &preferences, true);
+    assert!(manager.is_offline());
+    assert_eq!(manager.updated_at(), None);
+    let found = manager.catalog().search("tiny", 10, 0);
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0].source, REPO_NAME);
+    let card = manager.catalog().load_card(&found[0]).unwrap();
+    assert_eq!(card.summary, "From the repository");
+
+    let cache = IndexCache {
+        updated_at: Utc::now(),
+        revision: None,
+        indexs: vec![ModelIndex {
+            download_count: 7,
+            ..found[0].clone()
+        }],
+    };
+    let json = serde_json::to_string(&cache).unwrap();
+    std::fs::write(dir.join(INDEX_CACHE_FILENAME), json).unwrap();
+    let repo = cached_model_cards_repo(&dir).unwrap();
+    assert_eq!(repo.updated_at, Some(cache.updated_at));
+    assert_eq!(repo.indexs[0].download_count, 7);
+
+    let update = catalog_update(&found, &repo);
+    assert_eq!((update.new_models, update.changed_models), (0, 1));
+    let update = catalog_update(&[], &repo);
+    assert_eq!((update.new_models, update.changed_models), (1, 0));
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_model_cards_mirror() {
+    let dir = std::env::temp_dir().join(format!("moly-mirror-test-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    let upstream_dir = dir.join("upstream");
+    let upstream = Repository::init(&upstream_dir).unwrap();
+    let commit = |summary: &str| {
+        let index = format!(r#"[{{"id": "org/Tiny", "name": "tiny", "summary": "{summary}"}}]"#);
+        std::fs::write(upstream_dir.join("index.json"), index).unwrap();
+        let mut git_index = upstream.index().unwrap();
+        git_index.add_path(Path::new("index.json")).unwrap();
+        let tree = upstream.find_tree(git_index.write_tree().unwrap()).unwrap();
+        let sig = git2::Signature::now("moly", "moly@example.com").unwrap();
+        let parent = upstream
+            .head()
+            .ok()
+            .map(|head| head.peel_to_commit().unwrap());
+        let parents: Vec<_> = parent.iter().collect();
+        upstream
+            .commit(
+                Some("refs/heads/main"),
+                &sig,
+                &sig,
+                summary,
+                &tree,
+                &parents,
+            )
+            .unwrap()
+    };
+    let first = commit("first");
+    upstream
+        .tag_lightweight("v1", &upstream.find_object(first, None).unwrap(), false)
+        .unwrap();
+    upstream.set_head("refs/heads/main").unwrap();
+    let second = commit("second");
+
+    let url = upstream_dir.to_str().unwrap();
+    let repo_dir = dir.join(REPO_NAME);
+    let proxy = ProxyConfig::default();
+    let head = |repo: &Repository| repo.head().unwrap().peel_to_commit().unwrap().id();
+
+    let repo = update_mirror(url, &repo_dir, REPO_BRANCH, None, &proxy).unwrap();
+    assert_eq!(head(&repo), second);
+
+    // Local changes are dropped instead of merged
+    std::fs::write(repo_dir.join("index.json"), "[]").unwrap();
+    std::fs::write(repo_dir.join("stray.json"), "{}").unwrap();
+    let repo = update_mirror(url, &repo_dir, REPO_BRANCH, None, &proxy).unwrap();
+    assert_eq!(head(&repo), second);
+    assert!(std::fs::read_to_string(repo_dir.join("index.json"))
+        .unwrap()
+        .contains("second"));
+    assert!(!repo_dir.join("stray.json").exists());
+
+    // A repository git cannot open is cloned again
+    std::fs::write(repo_dir.join(".git").join("HEAD"), "garbage").unwrap();
+    let repo = update_mirror(url, &repo_dir, REPO_BRANCH, None, &proxy).unwrap();
+    assert_eq!(head(&repo), second);
+
+    let save = |repo: &Repository| {
+        let cache = IndexCache {
+            updated_at: Utc::now(),
+            revision: Some(head(repo).to_string()),
+            indexs: vec![],
+        };
+        cache.save(&dir).unwrap();
+    };
+    save(&repo);
+    let repo = update_mirror(url, &repo_dir, REPO_BRANCH, Some("v1"), &proxy).unwrap();
+    assert_eq!(head(&repo), first);
+    save(&repo);
+    assert!(update_mirror(url, &repo_dir, REPO_BRANCH, Some("v2"), &proxy).is_err());
+    assert!(repo_dir.exists());
+
+    let (repo, revision) = rollback_model_cards_repo(&dir).unwrap();
+    assert_eq!(revision, second.to_string());
+    assert_eq!(head_revision(&repo.dir), Some(revision));
+    let (_, revision) = rollback_model_cards_repo(&dir).unwrap();
+    assert_eq!(revision, first.to_string());
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
@@ -72,6 +72,11 @@ impl Model {
         Ok(())
     }
 
+    pub fn remove(conn: &rusqlite::Connection, id: &str) -> rusqlite::Result<()> {
+        conn.execute("DELETE FROM models WHERE id = ?1", params![id])?;
+        Ok(())
+    }
+
     pub fn get_all(conn: &rusqlite::Connection) -> rusqlite::Result<HashMap<String, Model>> {
         let mut stmt = conn.prepare("SELECT * FROM models")?;
         let mut rows = stmt.query([])?;
@@ -0,0 +1,309 @@
+use std::{
+    io::{Read, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use super::{
+    disk_space,
+    download_files::DownloadedFile,
+    download_shards::{self, DownloadShard},
+    library,
+};
+
+const COPY_BUFFER_SIZE: usize = 8 * 1024 * 1024;
+
+/// A file on disk that belongs in the new models directory.
+#[derive(Debug)]
+struct FileMove {
+    // Models directory the file was in
+    root: PathBuf,
+    from: PathBuf,
+    to: PathBuf,
+    len: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Moved {
+    Renamed,
+    // Renaming failed, e.g. across devices. The original is still in place.
+    Copied,
+}
+
+/// Move the files of every download into `new_dir` and point the database at it.
+/// Files in `library_roots` and imported files stay where they are. Files are
+/// renamed when possible and copied otherwise. If anything fails, the files
+/// already moved go back where they were and the database is left as is.
+///
+/// `report` gets the bytes moved so far and the total to move. Nothing may be
+/// downloading while this runs.
+pub fn migrate_models_dir(
+    sql_conn: &Mutex<rusqlite::Connection>,
+    new_dir: &Path,
+    library_roots: &[PathBuf],
+    report: &mut dyn FnMut(u64, u64),
+) -> anyhow::Result<()> {
+    std::fs::create_dir_all(new_dir)?;
+
+    let (file_ids, moves) = {
+        let conn = sql_conn.lock().unwrap();
+        planned_moves(&conn, new_dir, library_roots)?
+    };
+    let total: u64 = moves.iter().map(|file_move| file_move.len).sum();
+    report(0, total);
+
+    // Renaming is instant, what is left is copied once there is room for all of it
+    let mut done = vec![];
+    let mut to_copy = vec![];
+    let mut moved_bytes = 0;
+    for file_move in moves {
+        match rename_file(&file_move) {
+            Ok(true) => {
+                moved_bytes += file_move.len;
+                report(moved_bytes, total);
+                done.push((file_move, Moved::Renamed));
+            }
+            Ok(false) => to_copy.push(file_move),
+            Err(e) => {
+                rollback(&done);
+                return Err(move_error(&file_move, e));
+            }
+        }
+    }
+
+    let copy_len: u64 = to_copy.iter().map(|file_move| file_move.len).sum();
+    if copy_len > 0 {
+        let available = match disk_space::available_space(new_dir) {
+            Ok(available) => available,
+            Err(e) => {
+                rollback(&done);
+                return Err(e.into());
+            }
+        };
+        if available < copy_len {
+            rollback(&done);
+            anyhow::bail!(
+                "Not enough space in {:?}, {copy_len} bytes to copy and {available} available",
+                new_dir
+            );
+        }
+    }
+
+    for file_move in to_copy {
+        let r = copy_into_place(&file_move, &mut |copied| {
+            report(moved_bytes + copied, total)
+        });
+        match r {
+            Ok(()) => {
+                moved_bytes += file_move.len;
+                report(moved_bytes, total);
+                done.push((file_move, Moved::Copied));
+            }
+            Err(e) => {
+                rollback(&done);
+                return Err(move_error(&file_move, e));
+            }
+        }
+    }
+
+    let r = {
+        let mut conn = sql_conn.lock().unwrap();
+        conn.transaction().and_then(|tx| {
+            for file_id in &file_ids {
+                DownloadedFile::update_download_dir(&tx, file_id, &new_dir.to_string_lossy())?;
+            }
+            tx.commit()
+        })
+    };
+    if let Err(e) = r {
+        rollback(&done);
+        return Err(anyhow::anyhow!(
+            "Failed to update the models directory: {e}"
+        ));
+    }
+
+    // The copies are in place, the originals can go
+    for (file_move, moved) in &done {
+        if *moved == Moved::Copied {
+            if let Err(e) = std::fs::remove_file(&file_move.from) {
+                log::warn!(
+                    "Failed to remove {:?} after copying it: {e}",
+                    file_move.from
+                );
+            }
+        }
+        library::remove_empty_dirs(&file_move.from, &file_move.root);
+    }
+
+    Ok(())
+}
+
+/// Files that belong in `new_dir`, and what there is to move for them.
+fn planned_moves(
+    conn: &rusqlite::Connection,
+    new_dir: &Path,
+    library_roots: &[PathBuf],
+) -> anyhow::Result<(Vec<String>, Vec<FileMove>)> {
+    let mut files: Vec<_> = DownloadedFile::get_finished(conn)?.into_values().collect();
+    files.extend(DownloadedFile::get_pending(conn)?.into_values());
+    files.retain(|file| {
+        !file.imported && !library_roots.contains(&PathBuf::from(&file.download_dir))
+    });
+
+    let file_ids = files.iter().map(|file| file.id.to_string()).collect();
+    let mut moves = vec![];
+    for file in files {
+        let shards = DownloadShard::get_by_file(conn, &file.id)?;
+        for part in download_shards::parts_of(&file, &shards) {
+            let root = PathBuf::from(&part.download_dir);
+            let from = part.path();
+            let to = new_dir.join(&part.model_id).join(&part.name);
+
+            // Downloads that did not start yet have nothing on disk
+            let Ok(meta) = std::fs::metadata(&from) else {
+                continue;
+            };
+            if to.exists() {
+                if same_file(&from, &to) {
+                    continue;
+                }
+                anyhow::bail!("{:?} already exists", to);
+            }
+
+            moves.push(FileMove {
+                root,
+                from,
+                to,
+                len: meta.len(),
+            });
+        }
+    }
+
+    Ok((file_ids, moves))
+}
+
+fn same_file(a: &Path, b: &Path) -> bool {
+    match (a.canonicalize(), b.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+/// Whether `file_move` could be done by renaming the file, e.g. not across devices.
+fn rename_file(file_move: &FileMove) -> std::io::Result<bool> {
+    let to_dir = file_move.to.parent().unwrap_or(Path::new("."));
+    std::fs::create_dir_all(to_dir)?;
+    Ok(std::fs::rename(&file_move.from, &file_move.to).is_ok())
+}
+
+/// Copy the file, leaving the original in place. A partial copy is removed.
+fn copy_into_place(file_move: &FileMove, report: &mut dyn FnMut(u64)) -> std::io::Result<()> {
+    let r = copy_file(&file_move.from, &file_move.to, report);
+    if r.is_err() {
+        let _ = std::fs::remove_file(&file_move.to);
+    }
+    r
+}
+
+fn move_error(file_move: &FileMove, e: std::io::Error) -> anyhow::Error {
+    anyhow::anyhow!(
+        "Failed to move {:?} to {:?}: {e}",
+        file_move.from,
+        file_move.to
+    )
+}
+
+fn copy_file(from: &Path, to: &Path, report: &mut dyn FnMut(u64)) -> std::io::Result<()> {
+    let mut reader = std::fs::File::open(from)?;
+    let mut writer = std::fs::File::create(to)?;
+    let mut buffer = vec![0; COPY_BUFFER_SIZE];
+    let mut copied = 0;
+    loop {
+        let n = reader.read(&mut buffer)?;
+        if n == 0 {
+            break;
+        }
+        writer.write_all(&buffer[..n])?;
+        copied += n as u64;
+        report(copied);
+    }
+    writer.sync_all()
+}
+
+/// Put the files moved so far back where they were.
+fn rollback(done: &[(FileMove, Moved)]) {
+    for (file_move, moved) in done.iter().rev() {
+        let r = match moved {
+            Moved::Renamed => std::fs::rename(&file_move.to, &file_move.from),
+            Moved::Copied => std::fs::remove_file(&file_move.to),
+        };
+        if let Err(e) = r {
+            log::error!("Failed to restore {:?}: {e}", file_move.from);
+        }
+    }
+}
+
+#[test]
+fn test_migrate_models_dir() {
+    use std::sync::Arc;
+
+    let dir = std::env::temp_dir().join("moly_test_models_dir");
+    let _ = std::fs::remove_dir_all(&dir);
+    let old_dir = dir.join("old");
+    let new_dir = dir.join("new");
+    std::fs::create_dir_all(old_dir.join("org/model")).unwrap();
+    std::fs::write(old_dir.join("org/model/model.gguf"), [1u8; 100]).unwrap();
+
+    let conn = rusqlite::Connection::open_in_memory().unwrap();
+    super::download_files::create_table_download_files(&conn).unwrap();
+    super::download_shards::create_table_download_shards(&conn).unwrap();
+    let file = DownloadedFile {
+        id: Arc::new("org/model#model.gguf".to_string()),
+        model_id: "org/model".to_string(),
+        name: "model.gguf".to_string(),
+        downloaded: true,
+        file_size: 100,
+        download_dir: old_dir.to_string_lossy().to_string(),
+        ..Default::default()
+    };
+    file.insert_into_db(&conn).unwrap();
+    let sql_conn = Mutex::new(conn);
+
+    // A file in the way leaves everything untouched
+    std::fs::create_dir_all(new_dir.join("org/model")).unwrap();
+    std::fs::write(new_dir.join("org/model/model.gguf"), [2u8; 10]).unwrap();
+    assert!(migrate_models_dir(&sql_conn, &new_dir, &[], &mut |_, _| {}).is_err());
+    assert!(old_dir.join("org/model/model.gguf").exists());
+    let current = DownloadedFile::get_by_id(&sql_conn.lock().unwrap(), &file.id).unwrap();
+    assert_eq!(current.download_dir, file.download_dir);
+
+    std::fs::remove_file(new_dir.join("org/model/model.gguf")).unwrap();
+    let mut progress = vec![];
+    migrate_models_dir(&sql_conn, &new_dir, &[], &mut |moved, total| {
+        progress.push((moved, total))
+    })
+    .unwrap();
+
+    assert_eq!(progress.last(), Some(&(100, 100)));
+    assert_eq!(
+        std::fs::read(new_dir.join("org/model/model.gguf")).unwrap(),
+        vec![1u8; 100]
+    );
+    assert!(!old_dir.join("org").exists());
+    let current = DownloadedFile::get_by_id(&sql_conn.lock().unwrap(), &file.id).unwrap();
+    assert_eq!(current.download_dir, new_dir.to_string_lossy());
+
+    // Rolling back a copy only removes the copy
+    let mut report = |_| {};
+    let file_move = FileMove {
+        root: new_dir.clone(),
+        from: new_dir.join("org/model/model.gguf"),
+        to: old_dir.join("org/model/model.gguf"),
+        len: 100,
+    };
+    std::fs::create_dir_all(old_dir.join("org/model")).unwrap();
+    copy_file(&file_move.from, &file_move.to, &mut report).unwrap();
+    rollback(&[(file_move, Moved::Copied)]);
+    assert!(!old_dir.join("org/model/model.gguf").exists());
+    assert!(new_dir.join("org/model/model.gguf").exists());
+}
@@ -0,0 +1,355 @@
+use std::{
+    net::{TcpStream, ToSocketAddrs},
+    time::{Duration, Instant},
+};
+
+use moly_protocol::data::ProxyConfig;
+
+// Hosts Moly talks to, any of them answering is taken as being online
+const ONLINE_PROBE_HOSTS: [&str; 2] = ["github.com:443", "huggingface.co:443"];
+
+const ONLINE_PROBE_TIMEOUT: Duration = Duration::from_secs(3);
+
+// Connections to the local model server never go through these clients, they are
+// built with `no_proxy()` where they are used.
+
+/// Client for the downloads.
+pub fn async_client(config: &ProxyConfig) -> anyhow::Result<reqwest::Client> {
+    let mut builder = reqwest::Client::builder();
+    for proxy in proxies(config)? {
+        builder = builder.proxy(proxy);
+    }
+    for certificate in root_certificates(config)? {
+        builder = builder.add_root_certificate(certificate);
+    }
+    Ok(builder.build()?)
+}
+
+/// Client for the catalog and the embedding model, fetched from worker threads.
+pub fn blocking_client(config: &ProxyConfig) -> anyhow::Result<reqwest::blocking::Client> {
+    let mut builder = reqwest::blocking::Client::builder();
+    for proxy in proxies(config)? {
+        builder = builder.proxy(proxy);
+    }
+    for certificate in root_certificates(config)? {
+        builder = builder.add_root_certificate(certificate);
+    }
+    Ok(builder.build()?)
+}
+
+fn non_empty(url: &Option<String>) -> Option<&str> {
+    url.as_deref().map(str::trim).filter(|url| !url.is_empty())
+}
+
+/// Proxies of `config`. Without any, reqwest falls back to the environment.
+fn proxies(config: &ProxyConfig) -> anyhow::Result<Vec<reqwest::Proxy>> {
+    let no_proxy = reqwest::NoProxy::from_string(&config.no_proxy.join(","));
+
+    let mut proxies = vec![];
+    if let Some(url) = non_empty(&config.http_proxy) {
+        proxies.push(reqwest::Proxy::http(url)?.no_proxy(no_proxy.clone()));
+    }
+    if let Some(url) = non_empty(&config.https_proxy) {
+        proxies.push(reqwest::Proxy::https(url)?.no_proxy(no_proxy));
+    }
+    Ok(proxies)
+}
+
+fn root_certificates(config: &ProxyConfig) -> anyhow::Result<Vec<reqwest::Certificate>> {
+    let Some(path) = &config.ca_bundle else {
+        return Ok(vec![]);
+    };
+    let pem = std::fs::read(path)
+        .map_err(|e| anyhow::anyhow!("Failed to read the CA bundle {:?}: {e}", path))?;
+    Ok(reqwest::Certificate::from_pem_bundle(&pem)?)
+}
+
+/// Whether `host` is in the no-proxy list. Entries match the host and its
+/// subdomains, `*` matches every host.
+pub fn bypasses_proxy(no_proxy: &[String], host: &str) -> bool {
+    let host = host.trim_end_matches('.').to_ascii_lowercase();
+    no_proxy.iter().any(|entry| {
+        let entry = entry.trim().trim_start_matches('.').to_ascii_lowercase();
+        entry == "*"
+            || (!entry.is_empty() && (host == entry || host.ends_with(&format!(".{}", entry))))
+    })
+}
+
+/// Proxy for a git remote at `url`. Without a proxy in `config`, the
+/// `https_proxy`/`all_proxy` environment variables are used like before.
+pub fn git_proxy_options(config: &ProxyConfig, url: &str) -> Option<git2::ProxyOptions<'static>> {
+    let url = reqwest::Url::parse(url).ok()?;
+    if bypasses_proxy(&config.no_proxy, url.host_str().unwrap_or_default()) {
+        return None;
+    }
+
+    let http_proxy = non_empty(&config.http_proxy);
+    let https_proxy = non_empty(&config.https_proxy);
+    let proxy = match (http_proxy, https_proxy) {
+        (None, None) => std::env::var("https_proxy")
+            .or_else(|_| std::env::var("all_proxy"))
+            .ok()?,
+        _ if url.scheme() == "http" => http_proxy?.to_string(),
+        _ => https_proxy?.to_string(),
+    };
+
+    let mut options = git2::ProxyOptions::new();
+    options.url(&proxy);
+    Some(options)
+}
+
+/// Whether the network can be reached the way the clients reach it: a connection
+/// opens to any of the hosts Moly uses or to any of `mirrors`, through the proxy
+/// the clients would use for it.
+pub fn is_online(config: &ProxyConfig, mirrors: &[String]) -> bool {
+    let hosts = probe_hosts(config, mirrors, |name| std::env::var(name).ok());
+    any_reachable(hosts, ONLINE_PROBE_TIMEOUT)
+}
+
+/// Where to open a connection to for each of the hosts Moly uses and `mirrors`,
+/// as `host:port`. `env` reads the environment variables.
+fn probe_hosts(
+    config: &ProxyConfig,
+    mirrors: &[String],
+    env: impl Fn(&str) -> Option<String>,
+) -> Vec<String> {
+    let targets = ONLINE_PROBE_HOSTS
+        .iter()
+        .map(|host| format!("https://{host}"))
+        .chain(mirrors.iter().map(|url| url.trim().to_string()));
+
+    let mut hosts = vec![];
+    for target in targets {
+        let Ok(url) = reqwest::Url::parse(&target) else {
+            continue;
+        };
+        let Some(host) = url.host_str() else {
+            continue;
+        };
+        let probe = match proxy_for(config, url.scheme(), host, &env) {
+            Some(proxy) => host_and_port(&proxy),
+            None => url
+                .port_or_known_default()
+                .map(|port| format!("{host}:{port}")),
+        };
+        if let Some(probe) = probe {
+            if !hosts.contains(&probe) {
+                hosts.push(probe);
+            }
+        }
+    }
+    hosts
+}
+
+/// The proxy the clients use for a `scheme` request to `host`: the one of
+/// `config`, or without any the one of the environment, like reqwest does.
+fn proxy_for(
+    config: &ProxyConfig,
+    scheme: &str,
+    host: &str,
+    env: &impl Fn(&str) -> Option<String>,
+) -> Option<String> {
+    let env = |names: &[&str]| {
+        names
+            .iter()
+            .filter_map(|name| env(name))
+            .find(|value| !value.trim().is_empty())
+    };
+
+    let configured =
+        non_empty(&config.http_proxy).is_some() || non_empty(&config.https_proxy).is_some();
+    let (proxy, no_proxy) = if configured {
+        let proxy = match scheme {
+            "http" => non_empty(&config.http_proxy),
+            _ => non_empty(&config.https_proxy),
+        };
+        (proxy.map(String::from), config.no_proxy.clone())
+    } else {
+        let proxy = match scheme {
+            "http" => env(&["HTTP_PROXY", "http_proxy", "ALL_PROXY", "all_proxy"]),
+            _ => env(&["HTTPS_PROXY", "https_proxy", "ALL_PROXY", "all_proxy"]),
+        };
+        let no_proxy = env(&["NO_PROXY", "no_proxy"])
+            .map(|list| list.split(',').map(String::from).collect())
+            .unwrap_or_default();
+        (proxy, no_proxy)
+    };
+
+    proxy.filter(|_| !bypasses_proxy(&no_proxy, host))
+}
+
+fn host_and_port(url: &str) -> Option<String> {
+    let url = reqwest::Url::parse(url.trim()).ok()?;
+    Some(format!(
+        "{}:{}",
+        url.host_str()?,
+        url.port_or_known_default()?
+    ))
+}
+
+/// Whether a TCP connection to any of `hosts` opens within `timeout`. The hosts
+/// are tried in parallel, name resolution included.
+pub fn any_reachable(hosts: Vec<String>, timeout: Duration) -> bool {
+    let (tx, rx) = std::sync::mpsc::channel();
+    for host in hosts {
+        let tx = tx.clone();
+        std::thread::spawn(move || {
+            let reachable = host.to_socket_addrs().is_ok_and(|mut addrs| {
+                addrs.any(|addr| TcpStream::connect_timeout(&addr, timeout).is_ok())
+            });
+            let _ = tx.send(reachable);
+        });
+    }
+    drop(tx);
+
+    let deadline = Instant::now() + timeout;
+    while let Ok(reachable) = rx.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+        if reachable {
+            return true;
+        }
+    }
+    false
+}
+
+/// The first of `urls` to answer a HEAD request within `timeout`, with the time
+/// it took. The requests run in parallel and any response counts.
+pub fn fastest_url(
+    client: &reqwest::blocking::Client,
+    urls: &[String],
+    timeout: Duration,
+) -> Option<(String, Duration)> {
+    let (tx, rx) = std::sync::mpsc::channel();
+    for url in urls {
+        let (tx, client, url) = (tx.clone(), client.clone(), url.clone());
+        std::thread::spawn(move || {
+            let start = Instant::now();
+            if client.head(&url).timeout(timeout).send().is_ok() {
+                let _ = tx.send((url, start.elapsed()));
+            }
+        });
+    }
+    drop(tx);
+    rx.recv_timeout(timeout).ok()
+}
+
+/// Make libgit2 trust the CA bundle of `config`. The setting is global, so this
+/// runs once on startup, before the first sync: a bundle changed later applies to
+/// git on the next start.
+pub fn configure_git(config: &ProxyConfig) {
+    if let Some(path) = &config.ca_bundle {
+        // SAFETY: called on startup, before any git operation or sync thread starts
+        if let Err(e) = unsafe { git2::opts::set_ssl_cert_file(path) } {
+            log::warn!("libgit2 could not use the CA bundle {:?}: {e}", path);
+        }
+    }
+}
+
+#[test]
+fn test_proxy_config() {
+    let no_proxy = vec!["localhost".to_string(), ".internal.example.com".to_string()];
+    assert!(bypasses_proxy(&no_proxy, "localhost"));
+    assert!(bypasses_proxy(&no_proxy, "git.internal.example.com"));
+    assert!(bypasses_proxy(&no_proxy, "internal.example.com"));
+    assert!(!bypasses_proxy(&no_proxy, "example.com"));
+    assert!(!bypasses_proxy(&no_proxy, "notlocalhost"));
+    assert!(bypasses_proxy(&["*".to_string()], "github.com"));
+
+    let config = ProxyConfig {
+        https_proxy: Some("http://proxy.example.com:3128".to_string()),
+        no_proxy,
+        ..Default::default()
+    };
+    assert!(async_client(&config).is_ok());
+    assert!(blocking_client(&config).is_ok());
+    assert!(git_proxy_options(&config, "https://github.com/moxin-org/model-cards").is_some());
+    assert!(git_proxy_options(&config, "https://localhost/model-cards").is_none());
+
+    let config = ProxyConfig {
+        ca_bundle: Some(std::env::temp_dir().join("moly_test_missing_ca.pem")),
+        ..Default::default()
+    };
+    assert!(async_client(&config).is_err());
+}
+
+#[test]
+fn test_probe_hosts() {
+    let no_env = |_: &str| None;
+    let mirrors = vec!["https://hf-mirror.com/".to_string()];
+    assert_eq!(
+        probe_hosts(&ProxyConfig::default(), &mirrors, no_env),
+        ["github.com:443", "huggingface.co:443", "hf-mirror.com:443"]
+    );
+
+    // The proxies of the environment, unless the host bypasses them
+    let env = |name: &str| match name {
+        "ALL_PROXY" => Some("socks5://proxy.example.com:1080".to_string()),
+        "no_proxy" => Some("hf-mirror.com".to_string()),
+        _ => None,
+    };
+    assert_eq!(
+        probe_hosts(&ProxyConfig::default(), &mirrors, env),
+        ["proxy.example.com:1080", "hf-mirror.com:443"]
+    );
+
+    // The proxy of the settings replaces the environment
+    let config = ProxyConfig {
+        https_proxy: Some("http://proxy.internal:3128".to_string()),
+        ..Default::default()
+    };
+    assert_eq!(probe_hosts(&config, &mirrors, env), ["proxy.internal:3128"]);
+}
+
+#[test]
+fn test_reachable() {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let open = listener.local_addr().unwrap().to_string();
+    let closed = {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.local_addr().unwrap().to_string()
+    };
+    let timeout = Duration::from_secs(1);
+
+    assert!(any_reachable(vec![closed.clone(), open], timeout));
+    assert!(!any_reachable(vec![closed], timeout));
+    assert!(!any_reachable(vec!["not a host".to_string()], timeout));
+    assert!(!any_reachable(vec![], timeout));
+}
+
+#[test]
+fn test_fastest_url() {
+    use std::io::{Read, Write};
+
+    let serve = |delay: Duration| {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/model-cards", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            for mut stream in listener.incoming().flatten() {
+                let _ = stream.read(&mut [0; 1024]);
+                std::thread::sleep(delay);
+                let _ = stream.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n");
+            }
+        });
+        url
+    };
+    let slow = serve(Duration::from_millis(500));
+    let fast = serve(Duration::ZERO);
+    let closed = {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        format!("http://{}/model-cards", listener.local_addr().unwrap())
+    };
+    let client = reqwest::blocking::Client::builder()
+        .no_proxy()
+        .build()
+        .unwrap();
+    let timeout = Duration::from_secs(2);
+
+    let urls = [closed.clone(), slow.clone(), fast.clone()];
+    let (url, latency) = fastest_url(&client, &urls, timeout).unwrap();
+    assert_eq!(url, fast);
+    assert!(latency < timeout);
+    assert_eq!(
+        fastest_url(&client, &[closed.clone(), slow.clone()], timeout).map(|(url, _)| url),
+        Some(slow)
+    );
+    assert!(fastest_url(&client, &[closed], timeout).is_none());
+}
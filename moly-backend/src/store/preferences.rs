@@ -0,0 +1,133 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use moly_protocol::data::{CatalogSourceConfig, DownloadWindow, LibraryRoot, ProxyConfig};
+use serde::{Deserialize, Serialize};
+
+const PREFERENCES_FILENAME: &str = "backend_preferences.json";
+
+/// Settings owned by the backend, persisted in the app data directory so they are
+/// available before the client sends any command.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Preferences {
+    #[serde(skip)]
+    path: PathBuf,
+
+    // Base URLs (e.g. https://hf-mirror.com) tried in order before huggingface.co
+    #[serde(default)]
+    pub download_mirrors: Vec<String>,
+
+    // Start the downloads that were running when the app exited on the next launch
+    #[serde(default)]
+    pub auto_resume_downloads: bool,
+
+    // Overrides the limit given to `build_command_sender`
+    #[serde(default)]
+    pub max_concurrent_downloads: Option<usize>,
+
+    // Bytes per second shared by all downloads
+    #[serde(default)]
+    pub max_download_speed: Option<u64>,
+
+    // Bytes per second for single files, by file id
+    #[serde(default)]
+    pub file_download_speed_limits: HashMap<String, u64>,
+
+    // Downloads only run inside these windows, at any time when empty
+    #[serde(default)]
+    pub download_windows: Vec<DownloadWindow>,
+
+    // Used by every connection to the internet: catalog sync and downloads
+    #[serde(default)]
+    pub proxy: ProxyConfig,
+
+    // Maximum size of the models directory in bytes
+    #[serde(default)]
+    pub models_dir_quota: Option<u64>,
+
+    // Directories searched for models on top of the models directory
+    #[serde(default)]
+    pub library_roots: Vec<LibraryRoot>,
+
+    // One of the writable library roots, new downloads go to the models directory
+    // when unset
+    #[serde(default)]
+    pub download_root: Option<PathBuf>,
+
+    // Where the catalog is read from, in order. The model-cards repository alone
+    // when empty
+    #[serde(default)]
+    pub catalog_sources: Vec<CatalogSourceConfig>,
+
+    // Mirrors of the model-cards repository, the one answering first is synced
+    // from. `DEFAULT_MODEL_CARDS_MIRRORS` when empty
+    #[serde(default)]
+    pub model_cards_mirrors: Vec<String>,
+
+    // Mirror picked on the last sync, kept for when none answers
+    #[serde(default)]
+    pub model_cards_mirror: Option<String>,
+
+    // Commit or tag the model-cards repository is pinned to, for a team to share
+    // the same catalog. The head of its main branch when unset
+    #[serde(default)]
+    pub model_cards_revision: Option<String>,
+
+    // Never touch the network, not even to sync the catalog on startup
+    #[serde(default)]
+    pub offline_mode: bool,
+}
+
+impl Preferences {
+    pub fn load<P: AsRef<Path>>(app_data_dir: P) -> Self {
+        let path = app_data_dir.as_ref().join(PREFERENCES_FILENAME);
+
+        let mut preferences = std::fs::read_to_string(&path)
+            .ok()
+            .and_then(|json| match serde_json::from_str::<Preferences>(&json) {
+                Ok(preferences) => Some(preferences),
+                Err(e) => {
+                    log::error!("Failed to parse {:?}: {e}", path);
+                    None
+                }
+            })
+            .unwrap_or_default();
+
+        preferences.path = path;
+        preferences
+    }
+
+    pub fn save(&self) -> anyhow::Result<()> {
+        let json = serde_json::to_string_pretty(self)?;
+        std::fs::write(&self.path, json)?;
+        Ok(())
+    }
+}
+
+#[test]
+fn test_preferences() {
+    let dir = std::env::temp_dir().join("moly_test_preferences");
+    let _ = std::fs::create_dir_all(&dir);
+    let _ = std::fs::remove_file(dir.join(PREFERENCES_FILENAME));
+
+    let mut preferences = Preferences::load(&dir);
+    assert!(preferences.download_mirrors.is_empty());
+
+    preferences.download_mirrors = vec!["https://hf-mirror.com".to_string()];
+    preferences.save().unwrap();
+
+    let mut preferences = Preferences::load(&dir);
+    assert_eq!(preferences.download_mirrors, vec!["https://hf-mirror.com"]);
+
+    preferences.download_windows = vec![DownloadWindow::new((19, 0), (7, 0))];
+    preferences.save().unwrap();
+
+    let preferences = Preferences::load(&dir);
+    assert_eq!(preferences.download_windows[0].start_minute, 19 * 60);
+    assert!(preferences.download_windows[0].contains(23 * 60));
+    assert!(preferences.download_windows[0].contains(6 * 60 + 59));
+    assert!(!preferences.download_windows[0].contains(12 * 60));
+    assert_eq!(preferences.proxy, ProxyConfig::default());
+}
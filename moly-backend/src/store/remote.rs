@@ -1,18 +1,55 @@
+use std::collections::{HashMap, HashSet};
 use std::fs::File;
 use std::io::{self, Seek, Write};
 use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::mpsc::Sender;
 use std::sync::{Arc, Mutex};
 
-use moly_protocol::data::Model;
-use moly_protocol::protocol::FileDownloadResponse;
-use std::time::Duration;
+use moly_protocol::data::{DownloadProgress, Model};
+use moly_protocol::protocol::{FileDownloadError, FileDownloadResponse};
+use sha2::{Digest, Sha256};
+use std::time::{Duration, Instant};
 use tokio::time::timeout;
 
 use crate::backend_impls::DownloadControlCommand;
 
-async fn get_file_content_length(client: &reqwest::Client, url: &str) -> reqwest::Result<u64> {
-    let response = client.head(url).send().await?;
+use super::bandwidth::BandwidthLimiter;
+use super::checksum;
+use super::credentials::Credentials;
+use super::disk_space;
+use super::download_files::{DownloadStatus, DownloadedFile};
+use super::download_segments::DownloadSegment;
+use super::download_shards::{self, DownloadShard};
+use super::preferences::Preferences;
+
+const HUGGINGFACE_ENDPOINT: &str = "https://huggingface.co";
+const HUGGINGFACE_HOST: &str = "huggingface.co";
+
+// Number of parallel connections used for a single large file
+const DOWNLOAD_SEGMENTS: u32 = 4;
+// Below this size a single connection is fast enough
+const MIN_SEGMENTED_SIZE: u64 = 64 * 1024 * 1024;
+// Segment progress is saved every this many bytes. Bytes written after the last
+// save are downloaded again after a pause or a restart.
+const SEGMENT_SAVE_INTERVAL: u64 = 8 * 1024 * 1024;
+
+// Attempts made before giving up on transient failures, the first one included
+const MAX_DOWNLOAD_ATTEMPTS: u32 = 5;
+const RETRY_BASE_DELAY: Duration = Duration::from_secs(2);
+const RETRY_MAX_DELAY: Duration = Duration::from_secs(60);
+
+/// Returns the size of the file and whether the server accepts byte ranges.
+async fn get_file_content_length(
+    client: &reqwest::Client,
+    url: &str,
+    access_token: Option<&str>,
+) -> reqwest::Result<(u64, bool)> {
+    let mut request = client.head(url);
+    if let Some(token) = access_token.filter(|_| accepts_token(url)) {
+        request = request.bearer_auth(token);
+    }
+    let response = request.send().await?.error_for_status()?;
 
     let content_length = response
         .headers()
@@ -21,7 +58,81 @@ async fn get_file_content_length(client: &reqwest::Client, url: &str) -> reqwest
         .and_then(|val| val.parse::<u64>().ok())
         .unwrap_or(0);
 
-    Ok(content_length)
+    let accepts_ranges = response
+        .headers()
+        .get(reqwest::header::ACCEPT_RANGES)
+        .and_then(|val| val.to_str().ok())
+        .is_some_and(|val| val.contains("bytes"));
+
+    Ok((content_length, accepts_ranges))
+}
+
+/// Decides when progress is worth reporting: every `step` percent, and at least
+/// once per second so speed and ETA stay fresh on slow connections.
+struct ProgressThrottle {
+    step: f64,
+    last_progress: f64,
+    last_report: Instant,
+}
+
+impl ProgressThrottle {
+    fn new(step: f64) -> Self {
+        Self {
+            step,
+            last_progress: 0.0,
+            last_report: Instant::now(),
+        }
+    }
+
+    fn should_report(&mut self, downloaded: u64, content_length: u64) -> bool {
+        let progress = (downloaded as f64 / content_length as f64) * 100.0;
+        if progress > self.last_progress + self.step
+            || self.last_report.elapsed() >= Duration::from_secs(1)
+        {
+            self.last_progress = progress;
+            self.last_report = Instant::now();
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// Exponential moving average of the download speed, so the ETA does not jump
+/// around with every chunk.
+struct SpeedMeter {
+    last_sample: Option<(Instant, u64)>,
+    bytes_per_second: f64,
+}
+
+impl SpeedMeter {
+    // Weight of the newest sample
+    const SMOOTHING: f64 = 0.3;
+
+    fn new() -> Self {
+        Self {
+            last_sample: None,
+            bytes_per_second: 0.0,
+        }
+    }
+
+    fn update(&mut self, downloaded: u64) -> f64 {
+        let now = Instant::now();
+        if let Some((last_time, last_downloaded)) = self.last_sample {
+            let elapsed = now.duration_since(last_time).as_secs_f64();
+            if elapsed > 0.0 {
+                // A restarted download goes back to 0, that is not negative speed
+                let speed = downloaded.saturating_sub(last_downloaded) as f64 / elapsed;
+                self.bytes_per_second = if self.bytes_per_second == 0.0 {
+                    speed
+                } else {
+                    Self::SMOOTHING * speed + (1.0 - Self::SMOOTHING) * self.bytes_per_second
+                };
+            }
+        }
+        self.last_sample = Some((now, downloaded));
+        self.bytes_per_second
+    }
 }
 
 pub enum DownloadResult {
@@ -29,13 +140,40 @@ pub enum DownloadResult {
     Stopped(f64),
 }
 
+/// Whether the access token may be sent to `url`. Only Hugging Face itself gets
+/// it, never its mirrors or the other hosts model cards may point to.
+fn accepts_token(url: &str) -> bool {
+    reqwest::Url::parse(url)
+        .is_ok_and(|url| url.scheme() == "https" && url.host_str() == Some(HUGGINGFACE_HOST))
+}
+
+/// HTTP client for the download of a file: its requests to Hugging Face carry the
+/// access token, if any, and its transfers stay under the given bandwidth limits.
+#[derive(Clone, Copy)]
+struct DownloadClient<'a> {
+    client: &'a reqwest::Client,
+    limiters: &'a [BandwidthLimiter],
+    access_token: Option<&'a str>,
+}
+
+impl DownloadClient<'_> {
+    fn get(&self, url: &str) -> reqwest::RequestBuilder {
+        let request = self.client.get(url);
+        match self.access_token.filter(|_| accepts_token(url)) {
+            Some(token) => request.bearer_auth(token),
+            None => request,
+        }
+    }
+}
+
 async fn download_file<P: AsRef<Path>>(
-    client: &reqwest::Client,
+    client: DownloadClient<'_>,
     content_length: u64,
     url: &str,
     local_path: P,
     step: f64,
-    report_fn: &mut (dyn FnMut(f64) -> anyhow::Result<()> + Send),
+    hasher: &mut Sha256,
+    report_fn: &mut (dyn FnMut(u64) -> anyhow::Result<()> + Send),
 ) -> anyhow::Result<DownloadResult> {
     use futures_util::stream::StreamExt;
 
@@ -44,7 +182,9 @@ async fn download_file<P: AsRef<Path>>(
 
     let mut file = File::options().write(true).create(true).open(&local_path)?;
 
-    let file_length = file.metadata()?.len();
+    // Catch up the hash with what was downloaded before a pause or a restart
+    *hasher = Sha256::new();
+    let file_length = tokio::task::block_in_place(|| checksum::update_from_file(hasher, path))?;
 
     if file_length < content_length {
         file.seek(io::SeekFrom::End(0))?;
@@ -55,10 +195,20 @@ async fn download_file<P: AsRef<Path>>(
             .header("Range", range)
             .send()
             .await
+            .and_then(|resp| resp.error_for_status())
             .map_err(|e| anyhow::anyhow!(e))?;
 
         let mut downloaded: u64 = file_length;
-        let mut last_progress = 0.0;
+
+        if file_length > 0 && resp.status() != reqwest::StatusCode::PARTIAL_CONTENT {
+            // This server ignores ranges and sends the whole file, start over
+            log::warn!("{url} does not support ranges, restarting the download");
+            file.set_len(0)?;
+            file.seek(io::SeekFrom::Start(0))?;
+            *hasher = Sha256::new();
+            downloaded = 0;
+        }
+        let mut throttle = ProgressThrottle::new(step);
 
         let mut stream = resp.bytes_stream();
 
@@ -67,13 +217,13 @@ async fn download_file<P: AsRef<Path>>(
                 Some(chunk) => {
                     let chunk = chunk.map_err(|e| anyhow::anyhow!(e))?;
                     let len = chunk.len();
+                    BandwidthLimiter::acquire(client.limiters, len as u64).await;
                     file.write_all(&chunk)?;
+                    hasher.update(&chunk);
                     downloaded += len as u64;
 
-                    let progress = (downloaded as f64 / content_length as f64) * 100.0;
-                    if progress > last_progress + step {
-                        last_progress = progress;
-                        match report_fn(progress) {
+                    if throttle.should_report(downloaded, content_length) {
+                        match report_fn(downloaded) {
                             Ok(_) => {}
                             Err(_) => {}
                         }
@@ -86,160 +236,1140 @@ async fn download_file<P: AsRef<Path>>(
             }
         }
 
-        // TODO I don't know how to handle when it is complete but not 100%
-        // Maybe we should return Completed without any value?
-        Ok(DownloadResult::Completed(
-            (downloaded as f64 / content_length as f64) * 100.0,
-        ))
+        if downloaded < content_length {
+            // The server closed the connection early, the retry resumes from here
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!("Downloaded {downloaded} of {content_length} bytes from {url}"),
+            )
+            .into());
+        }
+        Ok(DownloadResult::Completed(100.0))
     } else {
         Ok(DownloadResult::Completed(100.0))
     }
 }
 
+/// Returned when a server answers a range request with the whole file.
+#[derive(Debug)]
+struct RangesNotSupported;
+
+impl std::fmt::Display for RangesNotSupported {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "The server does not support range requests")
+    }
+}
+
+impl std::error::Error for RangesNotSupported {}
+
+/// Download the missing part of `segment` from `url`, writing it at its offset in the
+/// preallocated file. Returns without error if the server closes the connection
+/// early, the caller checks whether the segment is complete.
+async fn download_segment_from_url(
+    client: DownloadClient<'_>,
+    url: &str,
+    local_path: &Path,
+    segment: &mut DownloadSegment,
+    sql_conn: &Mutex<rusqlite::Connection>,
+    downloaded: &AtomicU64,
+) -> anyhow::Result<()> {
+    use futures_util::stream::StreamExt;
+
+    let mut file = File::options().write(true).open(local_path)?;
+    file.seek(io::SeekFrom::Start(segment.start + segment.downloaded))?;
+
+    let range = format!(
+        "bytes={}-{}",
+        segment.start + segment.downloaded,
+        segment.end
+    );
+    let resp = client
+        .get(url)
+        .header("Range", range)
+        .send()
+        .await
+        .and_then(|resp| resp.error_for_status())
+        .map_err(|e| anyhow::anyhow!(e))?;
+
+    if resp.status() != reqwest::StatusCode::PARTIAL_CONTENT {
+        return Err(RangesNotSupported.into());
+    }
+
+    let mut stream = resp.bytes_stream();
+    let mut unsaved: u64 = 0;
+
+    let r = loop {
+        let chunk = match timeout(Duration::from_secs(10), stream.next()).await {
+            Ok(Some(Ok(chunk))) => chunk,
+            Ok(Some(Err(e))) => break Err(anyhow::anyhow!(e)),
+            Ok(None) => break Ok(()),
+            Err(e) => break Err(anyhow::anyhow!(e)),
+        };
+
+        // Never write over the next segment, whatever the server sends
+        let remaining = segment.len() - segment.downloaded;
+        let chunk = &chunk[..chunk.len().min(remaining as usize)];
+        BandwidthLimiter::acquire(client.limiters, chunk.len() as u64).await;
+        if let Err(e) = file.write_all(chunk) {
+            break Err(e.into());
+        }
+
+        let len = chunk.len() as u64;
+        segment.downloaded += len;
+        downloaded.fetch_add(len, Ordering::Relaxed);
+
+        unsaved += len;
+        if unsaved >= SEGMENT_SAVE_INTERVAL {
+            unsaved = 0;
+            let conn = sql_conn.lock().unwrap();
+            segment.update_downloaded(&conn)?;
+        }
+
+        if segment.is_complete() {
+            break Ok(());
+        }
+    };
+
+    {
+        let conn = sql_conn.lock().unwrap();
+        segment.update_downloaded(&conn)?;
+    }
+
+    r
+}
+
+/// Failures that may go away by trying again later: timeouts, connection resets and
+/// server side errors. Anything else (a 404, a full disk, a checksum mismatch) would
+/// fail the same way on every attempt.
+fn is_transient_error(e: &anyhow::Error) -> bool {
+    if e.is::<tokio::time::error::Elapsed>() {
+        return true;
+    }
+
+    if let Some(e) = e.downcast_ref::<reqwest::Error>() {
+        return match e.status() {
+            Some(status) => {
+                status.is_server_error()
+                    || status == reqwest::StatusCode::REQUEST_TIMEOUT
+                    || status == reqwest::StatusCode::TOO_MANY_REQUESTS
+            }
+            None => e.is_timeout() || e.is_connect() || e.is_request() || e.is_body(),
+        };
+    }
+
+    if let Some(e) = e.downcast_ref::<io::Error>() {
+        return matches!(
+            e.kind(),
+            io::ErrorKind::ConnectionReset
+                | io::ErrorKind::ConnectionAborted
+                | io::ErrorKind::BrokenPipe
+                | io::ErrorKind::TimedOut
+                | io::ErrorKind::UnexpectedEof
+                | io::ErrorKind::Interrupted
+        );
+    }
+
+    false
+}
+
+/// Exponential backoff: 2s, 4s, 8s... capped at one minute.
+fn retry_delay(attempt: u32) -> Duration {
+    RETRY_BASE_DELAY
+        .saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)))
+        .min(RETRY_MAX_DELAY)
+}
+
+/// Give a typed error to failures that clients may want to explain to the user.
+fn into_download_error(file_id: &str, e: anyhow::Error, has_token: bool) -> anyhow::Error {
+    if let Some(reqwest_error) = e.downcast_ref::<reqwest::Error>() {
+        let token_sent = has_token
+            && reqwest_error
+                .url()
+                .is_some_and(|url| accepts_token(url.as_str()));
+        if matches!(
+            reqwest_error.status(),
+            Some(reqwest::StatusCode::UNAUTHORIZED | reqwest::StatusCode::FORBIDDEN)
+        ) {
+            return FileDownloadError::Unauthorized {
+                file_id: file_id.to_string(),
+                url: reqwest_error
+                    .url()
+                    .map(|url| url.to_string())
+                    .unwrap_or_default(),
+                token_sent,
+            }
+            .into();
+        }
+        if matches!(
+            reqwest_error.status(),
+            Some(reqwest::StatusCode::NOT_FOUND | reqwest::StatusCode::GONE)
+        ) {
+            return FileDownloadError::NotFound {
+                file_id: file_id.to_string(),
+                url: reqwest_error
+                    .url()
+                    .map(|url| url.to_string())
+                    .unwrap_or_default(),
+            }
+            .into();
+        }
+    }
+    e
+}
+
+/// Errors worth retrying on the next mirror. Local failures, such as a full disk,
+/// would fail the same way whatever the URL is.
+fn is_network_error(e: &anyhow::Error) -> bool {
+    e.is::<reqwest::Error>() || e.is::<tokio::time::error::Elapsed>()
+}
+
+/// Candidate URLs for a file, in the order they should be tried: the configured
+/// mirrors first (with `HF_ENDPOINT` taking precedence), then the model card URL.
+pub fn get_download_urls(
+    file: &super::download_files::DownloadedFile,
+    mirrors: &[String],
+) -> Vec<String> {
+    let default_url = if file.download_url.is_empty() {
+        format!(
+            "{}/{}/resolve/main/{}",
+            HUGGINGFACE_ENDPOINT, file.model_id, file.name
+        )
+    } else {
+        file.download_url.clone()
+    };
+
+    let mut urls = vec![];
+
+    // Mirrors only know how to serve Hugging Face paths
+    if let Some(path) = default_url.strip_prefix(HUGGINGFACE_ENDPOINT) {
+        let hf_endpoint = std::env::var("HF_ENDPOINT").ok();
+        for mirror in hf_endpoint.iter().chain(mirrors) {
+            let url = format!("{}{}", mirror.trim().trim_end_matches('/'), path);
+            if !urls.contains(&url) {
+                urls.push(url);
+            }
+        }
+    }
+
+    if !urls.contains(&default_url) {
+        urls.push(default_url);
+    }
+
+    urls
+}
+
 #[derive(Debug, Clone)]
 pub struct ModelFileDownloader {
-    client: reqwest::Client,
+    // Rebuilt when the proxy configuration changes
+    client: Arc<Mutex<reqwest::Client>>,
     sql_conn: Arc<Mutex<rusqlite::Connection>>,
+    preferences: Arc<Mutex<Preferences>>,
     control_tx: tokio::sync::broadcast::Sender<DownloadControlCommand>,
     step: f64,
+    // Used when the preferences do not set a limit
+    default_max_downloads: usize,
+    // Files with a download task running
+    running: Arc<Mutex<HashSet<String>>>,
+    // Where to report the progress of each file. Requesting a file that is already
+    // queued or running only replaces its subscriber.
+    subscribers: Arc<Mutex<HashMap<String, Sender<anyhow::Result<FileDownloadResponse>>>>>,
+    // Shared by all downloads
+    global_limiter: BandwidthLimiter,
+    // Per-file limits of the running downloads
+    file_limiters: Arc<Mutex<HashMap<String, BandwidthLimiter>>>,
+    credentials: Arc<Mutex<Credentials>>,
+    // Nothing starts while set, e.g. while the models directory moves
+    suspended: Arc<AtomicBool>,
 }
 
 impl ModelFileDownloader {
     pub fn new(
         client: reqwest::Client,
         sql_conn: Arc<Mutex<rusqlite::Connection>>,
+        preferences: Arc<Mutex<Preferences>>,
         control_tx: tokio::sync::broadcast::Sender<DownloadControlCommand>,
         step: f64,
+        default_max_downloads: usize,
+        credentials: Arc<Mutex<Credentials>>,
     ) -> Self {
+        let global_limiter = BandwidthLimiter::new(preferences.lock().unwrap().max_download_speed);
+
         Self {
-            client,
+            client: Arc::new(Mutex::new(client)),
             sql_conn,
+            preferences,
             control_tx,
             step,
+            default_max_downloads,
+            running: Default::default(),
+            subscribers: Default::default(),
+            global_limiter,
+            file_limiters: Default::default(),
+            credentials,
+            suspended: Default::default(),
         }
     }
 
-    fn get_download_url(&self, file: &super::download_files::DownloadedFile) -> String {
-        format!(
-            "https://huggingface.co/{}/resolve/main/{}",
-            file.model_id, file.name
+    /// Token of the user, only sent to the URLs that `accepts_token`.
+    fn access_token(&self) -> Option<String> {
+        self.credentials.lock().unwrap().token()
+    }
+
+    fn client(&self) -> reqwest::Client {
+        self.client.lock().unwrap().clone()
+    }
+
+    /// Build the client again from the proxy in the preferences. Requests already
+    /// sent keep the previous one.
+    fn update_client(&self) {
+        let proxy = self.preferences.lock().unwrap().proxy.clone();
+        match super::network::async_client(&proxy) {
+            Ok(client) => *self.client.lock().unwrap() = client,
+            Err(e) => log::error!("Failed to apply the proxy configuration: {e}"),
+        }
+    }
+
+    /// Limiters a chunk of `file_id` goes through, the shared one and its own.
+    fn limiters(&self, file_id: &str) -> Vec<BandwidthLimiter> {
+        let rate = {
+            let preferences = self.preferences.lock().unwrap();
+            preferences.file_download_speed_limits.get(file_id).copied()
+        };
+
+        let file_limiter = self
+            .file_limiters
+            .lock()
+            .unwrap()
+            .entry(file_id.to_string())
+            .or_insert_with(|| BandwidthLimiter::new(rate))
+            .clone();
+
+        vec![self.global_limiter.clone(), file_limiter]
+    }
+
+    /// Apply limits changed in the preferences to the running downloads.
+    fn update_limiters(&self) {
+        let preferences = self.preferences.lock().unwrap();
+        self.global_limiter.set_rate(preferences.max_download_speed);
+        for (file_id, limiter) in self.file_limiters.lock().unwrap().iter() {
+            limiter.set_rate(preferences.file_download_speed_limits.get(file_id).copied());
+        }
+    }
+
+    /// Whether the current local time falls in one of the download windows.
+    fn in_download_window(&self) -> bool {
+        use chrono::Timelike;
+
+        let preferences = self.preferences.lock().unwrap();
+        if preferences.download_windows.is_empty() {
+            return true;
+        }
+
+        let now = chrono::Local::now();
+        let minute_of_day = (now.hour() * 60 + now.minute()) as u16;
+        preferences
+            .download_windows
+            .iter()
+            .any(|window| window.contains(minute_of_day))
+    }
+
+    /// Send `response` to whoever is following the download of `file_id`, if anyone.
+    /// Final responses (completion and errors) end the subscription.
+    fn notify(&self, file_id: &str, response: anyhow::Result<FileDownloadResponse>) {
+        let is_final = !matches!(
+            response,
+            Ok(FileDownloadResponse::Progress(..)
+                | FileDownloadResponse::Retrying(..)
+                | FileDownloadResponse::Queued(..))
+        );
+
+        let mut subscribers = self.subscribers.lock().unwrap();
+        if let Some(tx) = subscribers.get(file_id) {
+            if tx.send(response).is_err() || is_final {
+                subscribers.remove(file_id);
+            }
+        }
+    }
+
+    /// Tell the subscriber of a download that was stopped why. Whoever stopped it
+    /// saved the reason: paused, cancelled (removed) or sent back to the queue when
+    /// its window closed.
+    fn notify_stopped(&self, file_id: &str) {
+        let status = {
+            let conn = self.sql_conn.lock().unwrap();
+            DownloadedFile::get_by_id(&conn, file_id).map(|f| f.status)
+        };
+        if status == Ok(DownloadStatus::Queued) {
+            self.notify(
+                file_id,
+                Ok(FileDownloadResponse::Queued(file_id.to_string())),
+            );
+        } else {
+            // Closing the channel ends the download for the subscriber
+            self.subscribers.lock().unwrap().remove(file_id);
+        }
+    }
+
+    fn set_status(&self, file_id: &str, status: DownloadStatus) {
+        let conn = self.sql_conn.lock().unwrap();
+        if let Err(e) = DownloadedFile::update_status(file_id, &status, &conn) {
+            log::error!("Failed to save the download status of {file_id}: {e}");
+        }
+    }
+
+    fn get_download_urls(&self, file: &super::download_files::DownloadedFile) -> Vec<String> {
+        let preferences = self.preferences.lock().unwrap();
+        get_download_urls(file, &preferences.download_mirrors)
+    }
+
+    async fn get_content_length(
+        &self,
+        urls: &[String],
+        access_token: Option<&str>,
+    ) -> anyhow::Result<(u64, bool)> {
+        let client = self.client();
+        let mut last_error = anyhow::anyhow!("No download URL available");
+        for url in urls {
+            match get_file_content_length(&client, url, access_token).await {
+                Ok(r) => return Ok(r),
+                Err(e) => {
+                    log::warn!("Failed to get the file size from {url}: {e}");
+                    last_error = anyhow::anyhow!(e);
+                }
+            }
+        }
+        Err(last_error)
+    }
+
+    /// Download from the first URL that works. The partial file is kept when moving
+    /// to the next URL, so each one resumes from where the previous one stopped.
+    async fn download_from_urls(
+        &self,
+        urls: &[String],
+        content_length: u64,
+        local_path: &Path,
+        hasher: &mut Sha256,
+        client: DownloadClient<'_>,
+        report_fn: &mut (dyn FnMut(u64) -> anyhow::Result<()> + Send),
+    ) -> anyhow::Result<DownloadResult> {
+        let mut last_error = anyhow::anyhow!("No download URL available");
+        for url in urls {
+            match download_file(
+                client,
+                content_length,
+                url,
+                local_path,
+                self.step,
+                hasher,
+                report_fn,
+            )
+            .await
+            {
+                Ok(r) => return Ok(r),
+                Err(e) if is_network_error(&e) => {
+                    log::warn!("Download from {url} failed, trying the next mirror: {e}");
+                    last_error = e;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        Err(last_error)
+    }
+
+    async fn download_segment(
+        &self,
+        urls: &[String],
+        local_path: &Path,
+        mut segment: DownloadSegment,
+        downloaded: &AtomicU64,
+        client: DownloadClient<'_>,
+    ) -> anyhow::Result<()> {
+        for url in urls {
+            match download_segment_from_url(
+                client,
+                url,
+                local_path,
+                &mut segment,
+                &self.sql_conn,
+                downloaded,
+            )
+            .await
+            {
+                Ok(()) if segment.is_complete() => return Ok(()),
+                Ok(()) => {
+                    log::warn!(
+                        "{url} closed the connection early for segment {}, trying the next mirror",
+                        segment.idx
+                    );
+                }
+                Err(e) if is_network_error(&e) => {
+                    log::warn!(
+                        "Download of segment {} from {url} failed, trying the next mirror: {e}",
+                        segment.idx
+                    );
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
+        Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            format!(
+                "Failed to download segment {} of {}",
+                segment.idx, segment.file_id
+            ),
         )
+        .into())
+    }
+
+    /// Whether the file is (or should be) downloaded over several connections. A file
+    /// partially downloaded by a single stream keeps going that way.
+    fn is_segmented(
+        &self,
+        file: &super::download_files::DownloadedFile,
+        local_path: &Path,
+        accepts_ranges: bool,
+    ) -> bool {
+        let has_segments = {
+            let conn = self.sql_conn.lock().unwrap();
+            DownloadSegment::get_by_file(&conn, &file.id).is_ok_and(|s| !s.is_empty())
+        };
+
+        if has_segments {
+            // The preallocated file is useless without its segments and vice versa
+            if local_path.exists() {
+                return true;
+            }
+            let conn = self.sql_conn.lock().unwrap();
+            let _ = DownloadSegment::remove_by_file(&conn, &file.id);
+        }
+
+        let file_length = std::fs::metadata(local_path).map_or(0, |m| m.len());
+        accepts_ranges && file.file_size >= MIN_SEGMENTED_SIZE && file_length == 0
+    }
+
+    /// Download the file over several range requests at once. Progress of each
+    /// segment is kept in the database, so a paused or interrupted download resumes
+    /// every segment from where it stopped.
+    async fn download_segmented(
+        &self,
+        urls: &[String],
+        file: &super::download_files::DownloadedFile,
+        local_path: &Path,
+        client: DownloadClient<'_>,
+        report_fn: &mut (dyn FnMut(u64) -> anyhow::Result<()> + Send),
+    ) -> anyhow::Result<DownloadResult> {
+        let content_length = file.file_size;
+
+        let mut segments = {
+            let conn = self.sql_conn.lock().unwrap();
+            DownloadSegment::get_by_file(&conn, &file.id)?
+        };
+
+        if segments.is_empty() {
+            std::fs::create_dir_all(local_path.parent().unwrap())?;
+            File::create(local_path)?.set_len(content_length)?;
+
+            segments = DownloadSegment::split(&file.id, content_length, DOWNLOAD_SEGMENTS);
+            let conn = self.sql_conn.lock().unwrap();
+            for segment in &segments {
+                segment.insert_into_db(&conn)?;
+            }
+        }
+
+        let downloaded = AtomicU64::new(segments.iter().map(|s| s.downloaded).sum());
+
+        let all_segments = futures_util::future::try_join_all(
+            segments
+                .into_iter()
+                .filter(|segment| !segment.is_complete())
+                .map(|segment| {
+                    self.download_segment(urls, local_path, segment, &downloaded, client)
+                }),
+        );
+        tokio::pin!(all_segments);
+
+        let mut ticker = tokio::time::interval(Duration::from_millis(500));
+        let mut throttle = ProgressThrottle::new(self.step);
+
+        loop {
+            tokio::select! {
+                r = &mut all_segments => {
+                    r?;
+                    break;
+                }
+                _ = ticker.tick() => {
+                    let downloaded = downloaded.load(Ordering::Relaxed);
+                    if throttle.should_report(downloaded, content_length) {
+                        let _ = report_fn(downloaded);
+                    }
+                }
+            }
+        }
+
+        Ok(DownloadResult::Completed(100.0))
+    }
+
+    /// A single attempt at downloading the file, resuming whatever previous attempts
+    /// left on disk. Falls back to a single connection if ranges turn out not to work.
+    async fn download_attempt(
+        &self,
+        file: &super::download_files::DownloadedFile,
+        local_path: &Path,
+        segmented: &mut bool,
+        hasher: &mut Sha256,
+        client: DownloadClient<'_>,
+        report_fn: &mut (dyn FnMut(u64) -> anyhow::Result<()> + Send),
+    ) -> anyhow::Result<DownloadResult> {
+        // Mirrors may have changed since the previous attempt
+        let urls = self.get_download_urls(file);
+
+        if *segmented {
+            match self
+                .download_segmented(&urls, file, local_path, client, report_fn)
+                .await
+            {
+                Err(e) if e.is::<RangesNotSupported>() => {
+                    log::warn!("{e}, downloading {} over a single connection", file.id);
+                    *segmented = false;
+                    {
+                        let conn = self.sql_conn.lock().unwrap();
+                        let _ = DownloadSegment::remove_by_file(&conn, &file.id);
+                    }
+                    // Drop the preallocated content, it is not a valid prefix
+                    let _ = std::fs::remove_file(local_path);
+                }
+                r => return r,
+            }
+        }
+
+        self.download_from_urls(&urls, file.file_size, local_path, hasher, client, report_fn)
+            .await
     }
 
     async fn download(
-        self,
+        &self,
         file: super::download_files::DownloadedFile,
-        tx: Sender<anyhow::Result<FileDownloadResponse>>,
+        parts: Vec<(super::download_files::DownloadedFile, bool)>,
     ) {
         let file_id = file.id.to_string();
+        let total_bytes = file.file_size;
+        let mut speed_meter = SpeedMeter::new();
 
-        let mut send_progress = |progress| {
-            let r = tx.send(Ok(FileDownloadResponse::Progress(
-                file_id.clone(),
-                progress as f32,
-            )));
-            log::debug!("send progress {file_id} {progress} {r:?}");
+        let mut send_progress = |downloaded| {
+            let bytes_per_second = speed_meter.update(downloaded);
+            let progress = DownloadProgress::new(downloaded, total_bytes, bytes_per_second);
+            log::debug!("send progress {file_id} {progress:?}");
+            self.notify(
+                &file_id,
+                Ok(FileDownloadResponse::Progress(file_id.clone(), progress)),
+            );
             Ok(())
         };
 
+        let mut send_retrying = |attempt| {
+            self.notify(
+                &file_id,
+                Ok(FileDownloadResponse::Retrying(file_id.clone(), attempt)),
+            );
+        };
+
         let r = self
-            .download_file_from_remote(file, &mut send_progress)
+            .download_file_from_remote(file, parts, &mut send_progress, &mut send_retrying)
             .await;
 
         match r {
             Ok(Some(response)) => {
-                let _ = tx.send(Ok(response));
+                self.notify(&file_id, Ok(response));
+
+                let mut preferences = self.preferences.lock().unwrap();
+                if preferences
+                    .file_download_speed_limits
+                    .remove(&file_id)
+                    .is_some()
+                {
+                    let _ = preferences.save();
+                }
             }
-            Ok(None) => {
-                // TODO Implement file removal when download is stopped, nothing to do when it is paused
+            Ok(None) => self.notify_stopped(&file_id),
+            Err(e) => {
+                self.set_status(&file_id, DownloadStatus::Error(e.to_string()));
+                self.notify(&file_id, Err(e));
             }
+        }
+    }
+
+    /// Put a requested file at the back of the queue, or only attach `tx` to it if
+    /// it is queued or downloading already.
+    fn enqueue(
+        &self,
+        model: super::models::Model,
+        mut file: super::download_files::DownloadedFile,
+        tx: Sender<anyhow::Result<FileDownloadResponse>>,
+    ) {
+        let file_id = file.id.to_string();
+        self.subscribers.lock().unwrap().insert(file_id.clone(), tx);
+
+        if self.running.lock().unwrap().contains(&file_id) {
+            return;
+        }
+
+        let r = {
+            let conn = self.sql_conn.lock().unwrap();
+            (|| {
+                match DownloadedFile::get_by_id(&conn, &file_id) {
+                    // Resuming keeps the place in the queue and the known size
+                    Ok(existing) => {
+                        file.queue_position = existing.queue_position;
+                        file.file_size = existing.file_size;
+                    }
+                    Err(rusqlite::Error::QueryReturnedNoRows) => {
+                        file.queue_position = DownloadedFile::next_queue_position(&conn)?;
+                    }
+                    Err(e) => return Err(e),
+                }
+                file.status = DownloadStatus::Queued;
+                file.insert_into_db(&conn)?;
+                model.save_to_db(&conn)
+            })()
+        };
+
+        match r {
+            Ok(()) => self.notify(&file_id, Ok(FileDownloadResponse::Queued(file_id.clone()))),
+            Err(e) => self.notify(&file_id, Err(anyhow::anyhow!(e))),
+        }
+    }
+
+    fn max_downloads(&self) -> usize {
+        let preferences = self.preferences.lock().unwrap();
+        preferences
+            .max_concurrent_downloads
+            .unwrap_or(self.default_max_downloads)
+            .max(1)
+    }
+
+    /// Stop the running downloads, which go back to the queue, and start nothing
+    /// until `resume` is called.
+    pub fn suspend(&self) {
+        self.suspended.store(true, Ordering::SeqCst);
+        self.requeue_running();
+    }
+
+    pub fn resume(&self) {
+        self.suspended.store(false, Ordering::SeqCst);
+        let _ = self.control_tx.send(DownloadControlCommand::QueueChanged);
+    }
+
+    pub fn is_suspended(&self) -> bool {
+        self.suspended.load(Ordering::SeqCst)
+    }
+
+    /// Whether no download task is running anymore.
+    pub fn is_idle(&self) -> bool {
+        self.running.lock().unwrap().is_empty()
+    }
+
+    fn requeue_running(&self) {
+        let running: Vec<String> = self.running.lock().unwrap().iter().cloned().collect();
+        for file_id in running {
+            let is_running = {
+                let conn = self.sql_conn.lock().unwrap();
+                DownloadedFile::get_by_id(&conn, &file_id).is_ok_and(|f| {
+                    matches!(
+                        f.status,
+                        DownloadStatus::Initializing
+                            | DownloadStatus::Downloading
+                            | DownloadStatus::Retrying(_)
+                    )
+                })
+            };
+            if is_running {
+                log::info!("Sending {file_id} back to the queue");
+                self.set_status(&file_id, DownloadStatus::Queued);
+                let _ = self.control_tx.send(DownloadControlCommand::Stop(file_id));
+            }
+        }
+    }
+
+    /// Start queued files, in queue order, while there are free download slots.
+    fn schedule(&self) {
+        if self.suspended.load(Ordering::SeqCst) {
+            return;
+        }
+        if !self.in_download_window() {
+            self.requeue_running();
+            return;
+        }
+
+        let max_downloads = self.max_downloads();
+
+        let queued = {
+            let conn = self.sql_conn.lock().unwrap();
+            DownloadedFile::get_queued(&conn)
+        };
+        let queued = match queued {
+            Ok(queued) => queued,
             Err(e) => {
-                let _ = tx.send(Err(e));
+                log::error!("Failed to read the download queue: {e}");
+                return;
+            }
+        };
+
+        for file in queued {
+            {
+                let mut running = self.running.lock().unwrap();
+                if running.len() >= max_downloads {
+                    break;
+                }
+                if !running.insert(file.id.to_string()) {
+                    continue;
+                }
+            }
+
+            let downloader = self.clone();
+            tokio::spawn(async move {
+                let file_id = file.id.to_string();
+                downloader.start_download(file).await;
+                downloader.running.lock().unwrap().remove(&file_id);
+                downloader.file_limiters.lock().unwrap().remove(&file_id);
+                // Give the slot to the next file in the queue
+                let _ = downloader
+                    .control_tx
+                    .send(DownloadControlCommand::QueueChanged);
+            });
+        }
+    }
+
+    /// Make sure the rest of `file` fits on the disk and in the quota of the models
+    /// directory, before anything is written. What the other running and queued
+    /// downloads still have to write is counted as taken.
+    fn check_space(
+        &self,
+        file: &DownloadedFile,
+        parts: &[(DownloadedFile, bool)],
+    ) -> Result<(), FileDownloadError> {
+        let models_dir = Path::new(&file.download_dir);
+        let (on_disk, remaining, outstanding) = {
+            let conn = self.sql_conn.lock().unwrap();
+            let (on_disk, remaining) =
+                parts
+                    .iter()
+                    .fold((0, 0), |(on_disk, remaining), (part, _)| {
+                        let len = std::fs::metadata(part.path()).map_or(0, |meta| meta.len());
+                        let downloaded = super::downloaded_bytes(&conn, part).unwrap_or(0);
+                        (
+                            on_disk + len,
+                            remaining + part.file_size.saturating_sub(downloaded),
+                        )
+                    });
+            (on_disk, remaining, outstanding_bytes(&conn, file))
+        };
+
+        match disk_space::available_space(models_dir) {
+            Ok(available) if available.saturating_sub(outstanding) < remaining => {
+                return Err(FileDownloadError::InsufficientSpace {
+                    file_id: file.id.to_string(),
+                    required: remaining,
+                    available: available.saturating_sub(outstanding),
+                });
+            }
+            Ok(_) => {}
+            Err(e) => log::warn!("Failed to get the free space of {:?}: {e}", models_dir),
+        }
+
+        let quota = self.preferences.lock().unwrap().models_dir_quota;
+        if let Some(quota) = quota {
+            // What is already on disk of this file is counted in its size
+            let used = disk_space::directory_size(models_dir)
+                .saturating_sub(on_disk)
+                .saturating_add(outstanding);
+            let available = quota.saturating_sub(used);
+            if available < file.file_size {
+                return Err(FileDownloadError::QuotaExceeded {
+                    file_id: file.id.to_string(),
+                    required: file.file_size,
+                    available,
+                    quota,
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn start_download(&self, mut file: super::download_files::DownloadedFile) {
+        self.set_status(&file.id, DownloadStatus::Initializing);
+
+        let mut shards = {
+            let conn = self.sql_conn.lock().unwrap();
+            DownloadShard::get_by_file(&conn, &file.id).unwrap_or_default()
+        };
+
+        // Each part with whether its server accepts range requests
+        let mut parts = vec![];
+        for mut part in download_shards::parts_of(&file, &shards) {
+            if part.downloaded && part.file_size > 0 {
+                parts.push((part, false));
+                continue;
+            }
+
+            let urls = self.get_download_urls(&part);
+            let access_token = self.access_token();
+            match self
+                .get_content_length(&urls, access_token.as_deref())
+                .await
+            {
+                Ok((content_length, accepts_ranges)) => {
+                    part.file_size = content_length;
+                    parts.push((part, accepts_ranges));
+                }
+                Err(e) => {
+                    let e = into_download_error(&file.id, e, access_token.is_some());
+                    self.set_status(&file.id, DownloadStatus::Error(e.to_string()));
+                    self.notify(&file.id, Err(e));
+                    return;
+                }
+            }
+        }
+        file.file_size = parts.iter().map(|(part, _)| part.file_size).sum();
+
+        if let Err(e) = self.check_space(&file, &parts) {
+            self.set_status(&file.id, DownloadStatus::Error(e.to_string()));
+            self.notify(&file.id, Err(e.into()));
+            return;
+        }
+
+        {
+            let conn = self.sql_conn.lock().unwrap();
+            // The download may have been paused or cancelled while initializing
+            match DownloadedFile::get_by_id(&conn, &file.id) {
+                Ok(current) if current.status == DownloadStatus::Initializing => {}
+                _ => {
+                    drop(conn);
+                    self.notify_stopped(&file.id);
+                    return;
+                }
+            }
+
+            file.status = DownloadStatus::Downloading;
+            if let Err(e) = file.insert_into_db(&conn) {
+                log::error!("Failed to save the download of {}: {e}", file.id);
+            }
+
+            for (shard, (part, _)) in shards.iter_mut().zip(&parts) {
+                shard.file_size = part.file_size;
+                let _ = shard.update_progress(&conn);
             }
         }
+
+        self.download(file, parts).await;
     }
 
     pub async fn run_loop(
         downloader: Self,
-        max_downloader: usize,
         mut download_rx: tokio::sync::mpsc::UnboundedReceiver<(
             super::models::Model,
             super::download_files::DownloadedFile,
             Sender<anyhow::Result<FileDownloadResponse>>,
         )>,
     ) {
-        let semaphore = Arc::new(tokio::sync::Semaphore::new(max_downloader));
+        let mut control_rx = downloader.control_tx.subscribe();
 
-        while let Some((model, mut file, tx)) = download_rx.recv().await {
-            let url = downloader.get_download_url(&file);
+        // Downloads resumed from the previous session
+        downloader.schedule();
 
-            let f = async {
-                let content_length = get_file_content_length(&downloader.client, &url)
-                    .await
-                    .map_err(|e| anyhow::anyhow!(e))?;
+        // Download windows open and close with the clock
+        let mut window_ticker = tokio::time::interval(Duration::from_secs(30));
 
-                {
-                    file.file_size = content_length;
-                    let conn = downloader.sql_conn.lock().unwrap();
-                    // insert a pending download
-                    file.insert_into_db(&conn).map_err(|e| anyhow::anyhow!(e))?;
-                    model.save_to_db(&conn).map_err(|e| anyhow::anyhow!(e))?;
+        loop {
+            tokio::select! {
+                _ = window_ticker.tick() => {}
+                request = download_rx.recv() => {
+                    let Some((model, file, tx)) = request else {
+                        break;
+                    };
+                    downloader.enqueue(model, file, tx);
+                }
+                cmd = control_rx.recv() => {
+                    match cmd {
+                        Ok(DownloadControlCommand::Stop(file_id)) => {
+                            // Running downloads end their subscription when they stop,
+                            // queued ones have nothing running to do it
+                            if !downloader.running.lock().unwrap().contains(&file_id) {
+                                downloader.subscribers.lock().unwrap().remove(&file_id);
+                            }
+                        }
+                        Ok(DownloadControlCommand::BandwidthChanged) => {
+                            downloader.update_limiters();
+                        }
+                        Ok(DownloadControlCommand::ProxyChanged) => {
+                            downloader.update_client();
+                        }
+                        Ok(DownloadControlCommand::QueueChanged)
+                        | Err(tokio::sync::broadcast::error::RecvError::Lagged(_)) => {}
+                        Err(tokio::sync::broadcast::error::RecvError::Closed) => break,
+                    }
                 }
-
-                Ok(())
-            };
-
-            let r: anyhow::Result<()> = f.await;
-
-            if let Err(e) = r {
-                let _ = tx.send(Err(e));
-                continue;
             }
 
-            let downloader_ = downloader.clone();
-            let semaphore_ = semaphore.clone();
-            tokio::spawn(async move {
-                let permit = semaphore_.acquire_owned().await.unwrap();
-                downloader_.download(file, tx).await;
-                drop(permit);
-            });
+            downloader.schedule();
         }
     }
 
+    /// Download the parts of `file` one after the other, skipping those already on
+    /// disk. Progress is reported for the whole group.
     async fn download_file_from_remote(
         &self,
         mut file: super::download_files::DownloadedFile,
-        report_fn: &mut (dyn FnMut(f64) -> anyhow::Result<()> + Send),
+        parts: Vec<(super::download_files::DownloadedFile, bool)>,
+        report_fn: &mut (dyn FnMut(u64) -> anyhow::Result<()> + Send),
+        retry_fn: &mut (dyn FnMut(u32) + Send),
     ) -> anyhow::Result<Option<FileDownloadResponse>> {
-        let url = self.get_download_url(&file);
+        let mut offset = 0;
+        for (idx, (part, accepts_ranges)) in parts.into_iter().enumerate() {
+            if !part.downloaded {
+                let mut report_part = |downloaded| report_fn(offset + downloaded);
+                let completed = self
+                    .download_part(&file.id, &part, accepts_ranges, &mut report_part, retry_fn)
+                    .await?;
+                if !completed {
+                    return Ok(None);
+                }
 
-        let local_path = Path::new(&file.download_dir)
-            .join(&file.model_id)
-            .join(&file.name);
+                if part.id != file.id {
+                    let shard = DownloadShard {
+                        file_id: file.id.to_string(),
+                        idx: idx as u32,
+                        file_size: part.file_size,
+                        downloaded: true,
+                        ..Default::default()
+                    };
+                    let conn = self.sql_conn.lock().unwrap();
+                    shard.update_progress(&conn)?;
+                }
+            }
+            offset += part.file_size;
+        }
+
+        let local_path = file.path();
+
+        {
+            let conn = self.sql_conn.lock().unwrap();
+            file.mark_downloads();
+            let _ = file.update_downloaded(&conn);
+            super::load_information(&conn, &mut file);
+        }
+        let compatibility_guess =
+            super::compatibility::SystemInfo::detect().guess_downloaded(&file);
+
+        Ok(Some(FileDownloadResponse::Completed(
+            moly_protocol::data::DownloadedFile {
+                file: moly_protocol::data::File {
+                    id: file.id.as_ref().clone(),
+                    name: file.name.clone(),
+                    size: file.size.clone(),
+                    quantization: file.quantization.clone(),
+                    downloaded: true,
+                    downloaded_path: Some(
+                        local_path
+                            .to_str()
+                            .map(|s| s.to_string())
+                            .unwrap_or_default(),
+                    ),
+                    tags: file.tags,
+                    featured: false,
+                    compatibility_guess: compatibility_guess.clone(),
+                },
+                model: Model::default(),
+                downloaded_at: file.downloaded_at,
+                compatibility_guess,
+                information: file.information,
+            },
+        )))
+    }
+
+    /// Download and verify a single file on disk, the whole model file or one of its
+    /// shards. Statuses and errors are reported for `file_id`, the file the user
+    /// requested. Returns false if the download was stopped.
+    async fn download_part(
+        &self,
+        file_id: &str,
+        part: &super::download_files::DownloadedFile,
+        accepts_ranges: bool,
+        report_fn: &mut (dyn FnMut(u64) -> anyhow::Result<()> + Send),
+        retry_fn: &mut (dyn FnMut(u32) + Send),
+    ) -> anyhow::Result<bool> {
+        let local_path = part.path();
+
+        let mut segmented = self.is_segmented(part, &local_path, accepts_ranges);
+        let limiters = self.limiters(file_id);
+        let access_token = self.access_token();
+        let http_client = self.client();
+        let client = DownloadClient {
+            client: &http_client,
+            limiters: &limiters,
+            access_token: access_token.as_deref(),
+        };
 
-        let file_id_ = file.id.as_ref().clone();
         let mut control_rx = self.control_tx.subscribe();
+        let mut hasher = Sha256::new();
 
         let listen_control_cmd = async {
             loop {
                 let cmd = control_rx.recv().await;
-                if let Ok(DownloadControlCommand::Stop(file_id)) = cmd {
-                    if file_id == file_id_ {
+                if let Ok(DownloadControlCommand::Stop(stopped_id)) = cmd {
+                    if stopped_id == file_id {
                         return DownloadResult::Stopped(0.0);
                     }
                 }
             }
         };
 
+        // Pausing also interrupts the wait between attempts
+        let download = async {
+            let mut attempt = 1;
+            loop {
+                let r = self
+                    .download_attempt(
+                        part,
+                        &local_path,
+                        &mut segmented,
+                        &mut hasher,
+                        client,
+                        report_fn,
+                    )
+                    .await;
+
+                match r {
+                    Err(e) if is_transient_error(&e) => {
+                        if attempt >= MAX_DOWNLOAD_ATTEMPTS {
+                            return Err(FileDownloadError::RetriesExhausted {
+                                file_id: file_id.to_string(),
+                                attempts: attempt,
+                                reason: e.to_string(),
+                            }
+                            .into());
+                        }
+
+                        let delay = retry_delay(attempt);
+                        log::warn!(
+                            "Attempt {attempt} to download {} failed, retrying in {delay:?}: {e}",
+                            part.id
+                        );
+                        attempt += 1;
+                        self.set_status(file_id, DownloadStatus::Retrying(attempt));
+                        retry_fn(attempt);
+                        tokio::time::sleep(delay).await;
+                        self.set_status(file_id, DownloadStatus::Downloading);
+                    }
+                    Err(e) => return Err(into_download_error(file_id, e, access_token.is_some())),
+                    r => return r,
+                }
+            }
+        };
+
         let r = tokio::select! {
-            r = download_file(
-                &self.client,
-                file.file_size,
-                &url,
-                &local_path,
-                self.step,
-                report_fn,
-            ) => r?,
+            r = download => r?,
             r = listen_control_cmd => {
                 r
             }
@@ -247,38 +1377,194 @@ impl ModelFileDownloader {
 
         match r {
             DownloadResult::Completed(_) => {
-                {
-                    let conn = self.sql_conn.lock().unwrap();
-                    file.mark_downloads();
-                    let _ = file.update_downloaded(&conn);
+                if !part.sha256.is_empty() {
+                    self.set_status(file_id, DownloadStatus::Verifying);
                 }
 
-                Ok(Some(FileDownloadResponse::Completed(
-                    moly_protocol::data::DownloadedFile {
-                        file: moly_protocol::data::File {
-                            id: file.id.as_ref().clone(),
-                            name: file.name.clone(),
-                            size: file.size.clone(),
-                            quantization: file.quantization.clone(),
-                            downloaded: true,
-                            downloaded_path: Some(
-                                local_path
-                                    .to_str()
-                                    .map(|s| s.to_string())
-                                    .unwrap_or_default(),
-                            ),
-                            tags: file.tags,
-                            featured: false,
-                        },
-                        model: Model::default(),
-                        downloaded_at: file.downloaded_at,
-                        compatibility_guess:
-                            moly_protocol::data::CompatibilityGuess::PossiblySupported,
-                        information: String::new(),
-                    },
-                )))
-            }
-            DownloadResult::Stopped(_) => Ok(None),
+                if segmented {
+                    {
+                        let conn = self.sql_conn.lock().unwrap();
+                        let _ = DownloadSegment::remove_by_file(&conn, &part.id);
+                    }
+
+                    // Segments arrive out of order, so the file is hashed at the end
+                    if !part.sha256.is_empty() {
+                        hasher = Sha256::new();
+                        tokio::task::block_in_place(|| {
+                            checksum::update_from_file(&mut hasher, &local_path)
+                        })?;
+                    }
+                }
+
+                let actual = checksum::to_hex(hasher);
+                if !part.sha256.is_empty() && !checksum::matches(&part.sha256, &actual) {
+                    log::warn!(
+                        "checksum mismatch for {}: expected {}, got {actual}",
+                        part.id,
+                        part.sha256
+                    );
+                    // Resuming on top of corrupted content would never succeed
+                    let _ = std::fs::remove_file(&local_path);
+                    return Err(FileDownloadError::ChecksumMismatch {
+                        file_id: file_id.to_string(),
+                        expected: part.sha256.clone(),
+                        actual,
+                    }
+                    .into());
+                }
+
+                Ok(true)
+            }
+            DownloadResult::Stopped(_) => Ok(false),
         }
     }
 }
+
+/// Bytes the active downloads to the directory of `file`, other than `file`, still
+/// have to write. Files whose size is not known yet count with the size of their
+/// model card.
+fn outstanding_bytes(conn: &rusqlite::Connection, file: &DownloadedFile) -> u64 {
+    let Ok(pending) = DownloadedFile::get_pending(conn) else {
+        return 0;
+    };
+    pending
+        .values()
+        .filter(|other| {
+            other.id != file.id
+                && other.status.is_active()
+                && other.download_dir == file.download_dir
+        })
+        .map(|other| {
+            if other.file_size == 0 {
+                return other.size.parse().unwrap_or(0);
+            }
+            let shards = DownloadShard::get_by_file(conn, &other.id).unwrap_or_default();
+            download_shards::parts_of(other, &shards)
+                .iter()
+                .map(|part| {
+                    let downloaded = super::downloaded_bytes(conn, part).unwrap_or(0);
+                    part.file_size.saturating_sub(downloaded)
+                })
+                .sum::<u64>()
+        })
+        .sum()
+}
+
+#[test]
+fn test_retry_policy() {
+    assert_eq!(retry_delay(1), Duration::from_secs(2));
+    assert_eq!(retry_delay(3), Duration::from_secs(8));
+    assert_eq!(retry_delay(30), RETRY_MAX_DELAY);
+
+    let reset = anyhow::Error::from(io::Error::from(io::ErrorKind::ConnectionReset));
+    assert!(is_transient_error(&reset));
+
+    #[cfg(unix)]
+    {
+        let disk_full = anyhow::Error::from(io::Error::from_raw_os_error(libc::ENOSPC));
+        assert!(!is_transient_error(&disk_full));
+    }
+
+    let checksum = anyhow::Error::from(FileDownloadError::ChecksumMismatch {
+        file_id: "test".to_string(),
+        expected: "a".to_string(),
+        actual: "b".to_string(),
+    });
+    assert!(!is_transient_error(&checksum));
+}
+
+#[test]
+fn test_speed_meter() {
+    let mut meter = SpeedMeter::new();
+    assert_eq!(meter.update(1000), 0.0);
+
+    std::thread::sleep(Duration::from_millis(100));
+    let speed = meter.update(2000);
+    assert!(speed > 0.0 && speed <= 10_000.0);
+
+    // Going back to 0 after a restart does not make the speed negative
+    std::thread::sleep(Duration::from_millis(10));
+    assert!(meter.update(0) >= 0.0);
+
+    let progress = DownloadProgress::new(500, 1500, 100.0);
+    assert_eq!(progress.eta, Some(Duration::from_secs(10)));
+    assert_eq!(DownloadProgress::new(0, 1500, 0.0).eta, None);
+}
+
+#[test]
+fn test_accepts_token() {
+    assert!(accepts_token(
+        "https://huggingface.co/org/model/resolve/main/model.gguf"
+    ));
+    assert!(!accepts_token("http://huggingface.co/org/model"));
+    assert!(!accepts_token(
+        "https://huggingface.co.attacker.example/org/model"
+    ));
+    assert!(!accepts_token("https://hf-mirror.com/org/model"));
+    assert!(!accepts_token("https://attacker.example/?huggingface.co"));
+}
+
+#[test]
+fn test_get_download_urls() {
+    let file = super::download_files::DownloadedFile {
+        model_id: "TheBloke/Llama-2-7B-Chat-GGUF".to_string(),
+        name: "llama-2-7b-chat.Q4_K_M.gguf".to_string(),
+        download_url: "https://huggingface.co/TheBloke/Llama-2-7B-Chat-GGUF/resolve/main/llama-2-7b-chat.Q4_K_M.gguf".to_string(),
+        ..Default::default()
+    };
+
+    let urls = get_download_urls(&file, &["https://hf-mirror.com/".to_string()]);
+    assert_eq!(urls.len(), 2);
+    assert_eq!(
+        urls[0],
+        "https://hf-mirror.com/TheBloke/Llama-2-7B-Chat-GGUF/resolve/main/llama-2-7b-chat.Q4_K_M.gguf"
+    );
+    assert_eq!(urls[1], file.download_url);
+
+    // Files hosted somewhere else are only downloaded from their own URL
+    let file = super::download_files::DownloadedFile {
+        download_url: "https://example.com/model.gguf".to_string(),
+        ..file
+    };
+    let urls = get_download_urls(&file, &["https://hf-mirror.com".to_string()]);
+    assert_eq!(urls, vec!["https://example.com/model.gguf".to_string()]);
+}
+
+#[test]
+fn test_outstanding_bytes() {
+    use super::download_files::create_table_download_files;
+    use super::download_segments::create_table_download_segments;
+    use super::download_shards::create_table_download_shards;
+
+    let conn = rusqlite::Connection::open_in_memory().unwrap();
+    create_table_download_files(&conn).unwrap();
+    create_table_download_shards(&conn).unwrap();
+    create_table_download_segments(&conn).unwrap();
+
+    let dir = std::env::temp_dir().join("moly_test_outstanding_bytes");
+    let file = |id: &str, status: DownloadStatus, file_size: u64, size: &str| DownloadedFile {
+        id: Arc::new(id.to_string()),
+        name: id.to_string(),
+        size: size.to_string(),
+        file_size,
+        download_dir: dir.to_string_lossy().to_string(),
+        status,
+        ..Default::default()
+    };
+    let starting = file("starting", DownloadStatus::Initializing, 100, "");
+    for other in [
+        starting.clone(),
+        file("running", DownloadStatus::Downloading, 500, ""),
+        // Not initialized yet
+        file("queued", DownloadStatus::Queued, 0, "1000"),
+        file("paused", DownloadStatus::Paused, 800, ""),
+        DownloadedFile {
+            download_dir: "elsewhere".to_string(),
+            ..file("elsewhere", DownloadStatus::Downloading, 700, "")
+        },
+    ] {
+        other.insert_into_db(&conn).unwrap();
+    }
+
+    assert_eq!(outstanding_bytes(&conn, &starting), 1500);
+}
@@ -1,4 +1,6 @@
 use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::Duration;
 
 use chrono::{DateTime, Utc};
 
@@ -19,6 +21,8 @@ pub struct File {
     pub tags: Vec<String>,
     #[serde(default)]
     pub featured: bool,
+    #[serde(default)]
+    pub compatibility_guess: CompatibilityGuess,
 }
 
 #[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
@@ -28,11 +32,19 @@ pub struct Author {
     pub description: String,
 }
 
-#[derive(Clone, Debug, Default)]
+/// Whether a model file is expected to run on this computer, guessed from its size
+/// and the memory of the system.
+#[derive(Clone, Debug, Default, PartialEq, serde::Serialize, serde::Deserialize)]
 pub enum CompatibilityGuess {
+    // The memory of the system could not be read
     #[default]
     PossiblySupported,
+    // The processor lacks instructions the inference runtime needs
     NotSupported,
+    FitsComfortably(MemoryEstimate),
+    // Fits in memory, but only if little else is running
+    Tight(MemoryEstimate),
+    WillNotFit(MemoryEstimate),
 }
 
 impl CompatibilityGuess {
@@ -40,10 +52,34 @@ impl CompatibilityGuess {
         match self {
             CompatibilityGuess::PossiblySupported => "Possibly Supported",
             CompatibilityGuess::NotSupported => "Not Supported",
+            CompatibilityGuess::FitsComfortably(_) => "Fits Comfortably",
+            CompatibilityGuess::Tight(_) => "Tight Fit",
+            CompatibilityGuess::WillNotFit(_) => "Will Not Fit",
+        }
+    }
+
+    pub fn estimate(&self) -> Option<&MemoryEstimate> {
+        match self {
+            CompatibilityGuess::FitsComfortably(estimate)
+            | CompatibilityGuess::Tight(estimate)
+            | CompatibilityGuess::WillNotFit(estimate) => Some(estimate),
+            CompatibilityGuess::PossiblySupported | CompatibilityGuess::NotSupported => None,
         }
     }
 }
 
+/// Memory a model file needs once loaded, next to what the system has. In bytes.
+#[derive(Clone, Debug, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct MemoryEstimate {
+    // The weights, the KV cache for `context_size` tokens and the runtime buffers
+    pub required: u64,
+    pub weights: u64,
+    pub kv_cache: u64,
+    pub context_size: u64,
+    pub available_memory: u64,
+    pub total_memory: u64,
+}
+
 #[derive(Clone, Debug, Default)]
 pub struct DownloadedFile {
     pub file: File,
@@ -55,11 +91,195 @@ pub struct DownloadedFile {
 
 #[derive(Clone, Debug, Default)]
 pub enum PendingDownloadsStatus {
+    // Waiting for a free download slot
+    Queued,
     #[default]
     Initializing,
     Downloading,
     Paused,
-    Error,
+    // The last attempt failed with a transient error, the backend tries again
+    // on its own. Carries the number of the upcoming attempt.
+    Retrying(u32),
+    // Carries the reason of the failure
+    Error(String),
+    // Checking the SHA-256 of the completed file
+    Verifying,
+}
+
+/// Byte counts and throughput of a download.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct DownloadProgress {
+    pub downloaded_bytes: u64,
+    pub total_bytes: u64,
+    // Smoothed over the last few seconds, 0 while the download is not running
+    pub bytes_per_second: f64,
+    // Time left at the current speed, unknown while the speed is 0
+    pub eta: Option<Duration>,
+}
+
+impl DownloadProgress {
+    pub fn new(downloaded_bytes: u64, total_bytes: u64, bytes_per_second: f64) -> Self {
+        let eta = if bytes_per_second > 0.0 {
+            let remaining = total_bytes.saturating_sub(downloaded_bytes);
+            Some(Duration::from_secs_f64(remaining as f64 / bytes_per_second))
+        } else {
+            None
+        };
+
+        Self {
+            downloaded_bytes,
+            total_bytes,
+            bytes_per_second,
+            eta,
+        }
+    }
+
+    /// Between 0 and 100.
+    pub fn percentage(&self) -> f64 {
+        if self.total_bytes == 0 {
+            return 0.0;
+        }
+        (self.downloaded_bytes as f64 / self.total_bytes as f64) * 100.0
+    }
+}
+
+/// Time of the day, in local time, during which downloads may run. A window whose
+/// end comes before its start spans midnight, e.g. from 19:00 to 07:00.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct DownloadWindow {
+    // Minutes since midnight
+    pub start_minute: u16,
+    pub end_minute: u16,
+}
+
+impl DownloadWindow {
+    pub fn new(start: (u16, u16), end: (u16, u16)) -> Self {
+        Self {
+            start_minute: start.0 * 60 + start.1,
+            end_minute: end.0 * 60 + end.1,
+        }
+    }
+
+    pub fn contains(&self, minute_of_day: u16) -> bool {
+        if self.start_minute <= self.end_minute {
+            (self.start_minute..self.end_minute).contains(&minute_of_day)
+        } else {
+            minute_of_day >= self.start_minute || minute_of_day < self.end_minute
+        }
+    }
+}
+
+/// Bandwidth caps, in bytes per second, and the windows downloads are allowed in.
+#[derive(Clone, Debug, Default)]
+pub struct BandwidthSettings {
+    // Shared by all the running downloads, `None` means unlimited
+    pub max_bytes_per_second: Option<u64>,
+    pub file_max_bytes_per_second: HashMap<FileID, u64>,
+    // Downloads run at any time when empty
+    pub windows: Vec<DownloadWindow>,
+}
+
+/// Proxy used for every connection the backend makes to the internet. Proxy URLs
+/// may be `http://`, `https://`, `socks5://` or `socks5h://`. When neither proxy is
+/// set, the usual `HTTPS_PROXY`/`ALL_PROXY` environment variables apply.
+#[derive(Clone, Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct ProxyConfig {
+    // For plain `http://` URLs
+    #[serde(default)]
+    pub http_proxy: Option<String>,
+    #[serde(default)]
+    pub https_proxy: Option<String>,
+    // Hosts reached directly, e.g. `localhost` or `.internal.example.com`
+    #[serde(default)]
+    pub no_proxy: Vec<String>,
+    // PEM file with extra root certificates, for proxies that intercept TLS
+    #[serde(default)]
+    pub ca_bundle: Option<PathBuf>,
+}
+
+/// Space used by the models directory and left for it, in bytes.
+#[derive(Clone, Debug, Default)]
+pub struct StorageInfo {
+    pub models_dir_size: u64,
+    // Free space on the disk holding the models directory
+    pub available_space: u64,
+    pub quota: Option<u64>,
+}
+
+/// A directory holding models, laid out like the models directory. Files found
+/// in it count as downloaded. Read-only roots, e.g. a shared network directory,
+/// never get new downloads and their files are never deleted.
+#[derive(Clone, Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct LibraryRoot {
+    pub path: PathBuf,
+    #[serde(default)]
+    pub read_only: bool,
+}
+
+/// A place the model catalog is read from. Search results and featured models
+/// merge every source, in order, the first source listing a model wins.
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum CatalogSourceConfig {
+    // The model-cards git repository, synced on startup
+    ModelCards,
+    // A directory of model card JSON files, laid out like the model-cards
+    // repository: `<author>/<name>.json`, with an optional `index.json`
+    LocalDirectory(PathBuf),
+    // GGUF repositories of the Hugging Face Hub, through its REST API. The
+    // endpoint defaults to https://huggingface.co
+    HuggingFace(Option<String>),
+}
+
+/// Where the catalog stands, see `Command::GetCatalogStatus`.
+#[derive(Clone, Debug, Default)]
+pub struct CatalogStatus {
+    // No network access is made: the last cached index and cards are served and
+    // downloads are refused. Set by the user or when no network was found on startup
+    pub offline: bool,
+    // When the model-cards index was last updated, unknown before the first sync
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+/// What a sync of the catalog changed, see `Command::WatchCatalog`.
+#[derive(Clone, Debug, Default)]
+pub struct CatalogUpdate {
+    // Models the catalog did not list before the sync
+    pub new_models: usize,
+    // Models listed before whose index entry is different now
+    pub changed_models: usize,
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+/// Disagreement between the records of the library and the files on disk, found by
+/// `Command::CheckLibrary`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum LibraryIssue {
+    // A downloaded file is gone from disk, e.g. deleted outside the app
+    MissingFile {
+        file_id: FileID,
+        path: PathBuf,
+    },
+    // A downloaded file does not have the size it was downloaded with
+    SizeMismatch {
+        file_id: FileID,
+        path: PathBuf,
+        expected: u64,
+        actual: u64,
+    },
+    // A model file in the models directory that no download owns, e.g. left
+    // behind by a cancelled download
+    UnknownFile {
+        path: PathBuf,
+        size: u64,
+    },
+    // A model directory left empty in the models directory
+    EmptyDirectory {
+        path: PathBuf,
+    },
+    // A model no file belongs to anymore
+    OrphanedModel {
+        model_id: ModelID,
+    },
 }
 
 #[derive(Clone, Debug, Default)]
@@ -67,6 +287,7 @@ pub struct PendingDownload {
     pub file: File,
     pub model: Model,
     pub progress: f64,
+    pub transfer: DownloadProgress,
     pub status: PendingDownloadsStatus,
 }
 
@@ -87,4 +308,8 @@ pub struct Model {
     pub like_count: u32,
     pub download_count: u32,
     pub metrics: HashMap<String, f32>,
+    // Catalog source the model was found in: `model-cards`, `local-directory` or
+    // `huggingface`. Empty for models that only exist in the library
+    #[serde(default)]
+    pub source: String,
 }
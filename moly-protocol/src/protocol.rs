@@ -6,10 +6,184 @@ use std::sync::mpsc::Sender;
 
 #[derive(Clone, Debug)]
 pub enum FileDownloadResponse {
-    Progress(FileID, f32),
+    Progress(FileID, DownloadProgress),
+    // The download failed with a transient error and will be attempted again
+    // after a short delay. Carries the number of the upcoming attempt.
+    Retrying(FileID, u32),
+    // All download slots are busy, the file starts when its turn comes
+    Queued(FileID),
     Completed(DownloadedFile),
 }
 
+#[derive(Clone, Debug)]
+pub enum ModelsDirMigrationResponse {
+    // Bytes moved so far, out of the total of the files to move
+    Progress { moved_bytes: u64, total_bytes: u64 },
+    Completed,
+}
+
+/// Download failures that clients may want to tell apart from generic errors.
+/// They are sent wrapped in `anyhow::Error`, use `downcast_ref` to inspect them.
+#[derive(Clone, Debug)]
+pub enum FileDownloadError {
+    // The downloaded content does not match the SHA-256 published in the model card.
+    // The corrupted file is removed, so downloading it again starts from scratch.
+    ChecksumMismatch {
+        file_id: FileID,
+        expected: String,
+        actual: String,
+    },
+    // The server does not have the file (404 or 410). Retrying will not help.
+    NotFound {
+        file_id: FileID,
+        url: String,
+    },
+    // The server refused the request (401 or 403). The repository is most likely
+    // gated: it needs a Hugging Face access token from an account that accepted
+    // the model license.
+    Unauthorized {
+        file_id: FileID,
+        url: String,
+        // Whether an access token was sent, i.e. it is missing or it was rejected
+        token_sent: bool,
+    },
+    // The disk holding the models directory does not have room for the rest of
+    // the file. Checked before the download starts, nothing is written.
+    InsufficientSpace {
+        file_id: FileID,
+        required: u64,
+        available: u64,
+    },
+    // The file would take the models directory over the quota set by the user
+    QuotaExceeded {
+        file_id: FileID,
+        required: u64,
+        // Bytes the models directory may still grow by
+        available: u64,
+        quota: u64,
+    },
+    // Every attempt failed with a transient error (timeouts, 5xx, connection
+    // resets). Resuming later may succeed.
+    RetriesExhausted {
+        file_id: FileID,
+        attempts: u32,
+        reason: String,
+    },
+}
+
+impl FileDownloadError {
+    /// Whether downloading the file again is expected to fail the same way.
+    pub fn is_permanent(&self) -> bool {
+        !matches!(self, FileDownloadError::RetriesExhausted { .. })
+    }
+}
+
+impl std::fmt::Display for FileDownloadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FileDownloadError::ChecksumMismatch {
+                file_id,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "Checksum mismatch for {file_id}: expected {expected}, got {actual}"
+            ),
+            FileDownloadError::NotFound { file_id, url } => {
+                write!(f, "{file_id} was not found at {url}")
+            }
+            FileDownloadError::Unauthorized {
+                file_id,
+                token_sent: false,
+                ..
+            } => write!(
+                f,
+                "{file_id} is in a gated repository. Add a Hugging Face access token \
+                in the settings and accept the model license on huggingface.co"
+            ),
+            FileDownloadError::Unauthorized {
+                file_id,
+                token_sent: true,
+                ..
+            } => write!(
+                f,
+                "Access to {file_id} was denied. Check that your Hugging Face access \
+                token is valid and that you accepted the model license on huggingface.co"
+            ),
+            FileDownloadError::InsufficientSpace {
+                file_id,
+                required,
+                available,
+            } => write!(
+                f,
+                "Not enough disk space for {file_id}: {} needed, {} available",
+                format_size(*required),
+                format_size(*available)
+            ),
+            FileDownloadError::QuotaExceeded {
+                file_id,
+                required,
+                available,
+                quota,
+            } => write!(
+                f,
+                "{file_id} does not fit in the {} quota of the models directory: {} \
+                needed, {} left",
+                format_size(*quota),
+                format_size(*required),
+                format_size(*available)
+            ),
+            FileDownloadError::RetriesExhausted {
+                file_id,
+                attempts,
+                reason,
+            } => write!(
+                f,
+                "Download of {file_id} failed after {attempts} attempts: {reason}"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for FileDownloadError {}
+
+fn format_size(bytes: u64) -> String {
+    const GB: f64 = (1 << 30) as f64;
+    const MB: f64 = (1 << 20) as f64;
+    if bytes as f64 >= GB {
+        format!("{:.2} GB", bytes as f64 / GB)
+    } else {
+        format!("{:.1} MB", bytes as f64 / MB)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum FileVerification {
+    Verified,
+    // The model card does not publish a checksum for this file
+    NoChecksum,
+    ChecksumMismatch { expected: String, actual: String },
+}
+
+/// What became of a file given to `Command::ImportLocalFiles`.
+#[derive(Clone, Debug)]
+pub enum ImportedFile {
+    // Recognized as a file of the catalog, listed under its model
+    Matched { path: PathBuf, file_id: FileID },
+    // Not in the catalog, listed under a model made up from its metadata
+    Unmatched { path: PathBuf, file_id: FileID },
+    // Listed already, nothing changed
+    AlreadyKnown { path: PathBuf, file_id: FileID },
+    Failed { path: PathBuf, reason: String },
+}
+
+#[derive(Clone, Debug)]
+pub enum ImportResponse {
+    // Files handled so far, out of the GGUF files found
+    Progress { imported: usize, total: usize },
+    Completed(Vec<ImportedFile>),
+}
+
 #[derive(Clone, Debug)]
 pub enum ContextOverflowPolicy {
     StopAtLimit,
@@ -53,14 +227,42 @@ pub struct LoadedModelInfo {
 
 #[derive(Clone, Debug)]
 pub struct ModelResourcesInfo {
-    pub ram_usage: f32,
+    // Resident memory of the process running the model, in bytes
+    pub ram_usage: u64,
+    // Processor time used since the previous sample, in percent of one core
     pub cpu_usage: f32,
 }
 
+#[derive(Clone, Debug, PartialEq)]
+pub enum LoadModelStage {
+    // The model files are handed to the inference plugin
+    Preloading,
+    // The inference runtime is instantiated
+    Instantiating,
+    // Waiting for the model server to answer
+    StartingServer,
+    // The model answers, `Completed` follows
+    Ready,
+}
+
+impl LoadModelStage {
+    pub fn as_str(&self) -> &str {
+        match self {
+            LoadModelStage::Preloading => "Preparing",
+            LoadModelStage::Instantiating => "Starting runtime",
+            LoadModelStage::StartingServer => "Starting server",
+            LoadModelStage::Ready => "Ready",
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub enum LoadModelResponse {
-    Progress(FileID, f32),
+    // The current stage and the overall progress of the load, from 0 to 1
+    Progress(FileID, LoadModelStage, f32),
     Completed(LoadedModelInfo),
+    // Sampled every few seconds while the model stays loaded, until the receiver
+    // is dropped
     ModelResourcesUsage(ModelResourcesInfo),
 }
 
@@ -83,16 +285,96 @@ pub enum LocalServerResponse {
 pub enum Command {
     GetFeaturedModels(Sender<Result<Vec<Model>>>),
 
-    // Change DowanloadFiles Location
-    ChangeModelsDir(PathBuf),
+    // Move the downloaded files to a new models directory. Running downloads are
+    // paused meanwhile and continue in the new directory, new downloads are refused
+    // until it is done. On failure, the files stay in the current directory.
+    ChangeModelsDir(PathBuf, Sender<Result<ModelsDirMigrationResponse>>),
 
     // The argument is a string with the keywords to search for.
     SearchModels(String, Sender<Result<Vec<Model>>>),
 
     DownloadFile(FileID, Sender<Result<FileDownloadResponse>>),
+    // Base URLs tried in order before the model card URL, e.g. "https://hf-mirror.com"
+    SetDownloadMirrors(Vec<String>, Sender<Result<()>>),
+    GetDownloadMirrors(Sender<Result<Vec<String>>>),
+    // Mirrors of the model-cards repository, the fastest to answer is synced from.
+    // The built-in GitHub and GitCode mirrors when empty. Used from the next sync on.
+    SetModelCardsMirrors(Vec<String>, Sender<Result<()>>),
+    GetModelCardsMirrors(Sender<Result<Vec<String>>>),
     PauseDownload(FileID, Sender<Result<()>>),
     CancelDownload(FileID, Sender<Result<()>>),
+    // Moves the given files to the front of the download queue, in this order
+    ReorderDownloads(Vec<FileID>, Sender<Result<()>>),
+    // Moves a file to the front of the download queue
+    PrioritizeDownload(FileID, Sender<Result<()>>),
+    // Moves a file to the back of the download queue
+    DeferDownload(FileID, Sender<Result<()>>),
+    // Takes effect as running downloads finish, none of them is interrupted
+    SetMaxConcurrentDownloads(usize, Sender<Result<()>>),
+    // Whether downloads interrupted by closing the app start again on the next launch
+    SetAutoResumeDownloads(bool, Sender<Result<()>>),
+    // Bytes per second shared by all downloads, `None` removes the cap
+    SetDownloadSpeedLimit(Option<u64>, Sender<Result<()>>),
+    // Bytes per second for a single file, applied on top of the shared cap
+    SetFileDownloadSpeedLimit(FileID, Option<u64>, Sender<Result<()>>),
+    // Downloads only run inside these windows, an empty list allows any time.
+    // Running downloads go back to the queue when their window closes.
+    SetDownloadWindows(Vec<DownloadWindow>, Sender<Result<()>>),
+    GetBandwidthSettings(Sender<Result<BandwidthSettings>>),
+    // Token sent to Hugging Face for gated repositories, `None` forgets it
+    SetAccessToken(Option<String>, Sender<Result<()>>),
+    // Whether a token is saved. The token itself is never sent back.
+    HasAccessToken(Sender<Result<bool>>),
+    // Proxy for the catalog sync and the downloads. Running downloads switch to it on
+    // their next attempt.
+    SetProxyConfig(ProxyConfig, Sender<Result<()>>),
+    GetProxyConfig(Sender<Result<ProxyConfig>>),
+    // Maximum size of the models directory in bytes, `None` removes it. Downloads
+    // that would go over it fail with `FileDownloadError::QuotaExceeded`.
+    SetModelsDirQuota(Option<u64>, Sender<Result<()>>),
+    GetStorageInfo(Sender<Result<StorageInfo>>),
+    // Directories searched for models on top of the models directory. Their files
+    // are listed again right away.
+    SetLibraryRoots(Vec<LibraryRoot>, Sender<Result<()>>),
+    // Every root, the models directory first
+    GetLibraryRoots(Sender<Result<Vec<LibraryRoot>>>),
+    // Writable root new downloads go to, `None` for the models directory
+    SetDownloadRoot(Option<PathBuf>, Sender<Result<()>>),
+    // Where the catalog is read from, the model-cards repository alone when empty.
+    // The sources are loaded again right away.
+    SetCatalogSources(Vec<CatalogSourceConfig>, Sender<Result<()>>),
+    GetCatalogSources(Sender<Result<Vec<CatalogSourceConfig>>>),
+    // Stop or allow network access. Going online syncs the catalog right away.
+    SetOfflineMode(bool, Sender<Result<()>>),
+    GetCatalogStatus(Sender<Result<CatalogStatus>>),
+    // Sync the model-cards repository again in the background. The cached catalog
+    // is served meanwhile and `WatchCatalog` receives what changed once done.
+    RefreshCatalog(Sender<Result<()>>),
+    // Receives what every catalog sync changed, or the error it failed with, for
+    // as long as the receiver is kept
+    WatchCatalog(Sender<Result<CatalogUpdate>>),
+    // Pin the model-cards repository to a commit or tag, for every machine of a
+    // team to see the same catalog. `None` follows its main branch again. Takes
+    // effect on the next sync, right away when online. A sync running already is
+    // followed by another one.
+    SetModelCardsRevision(Option<String>, Sender<Result<()>>),
+    GetModelCardsRevision(Sender<Result<Option<String>>>),
+    // Go back to the catalog synced before the current one, without network
+    // access, and pin the model-cards repository to it. Answers the revision.
+    RollbackCatalog(Sender<Result<String>>),
+    // Lists GGUF files that are already on disk as downloaded, without moving them.
+    // Directories are scanned for GGUF files, subdirectories included. Runs in the
+    // background, progress is reported after each file.
+    ImportLocalFiles(Vec<PathBuf>, Sender<Result<ImportResponse>>),
+    // Compares the records of the library with the files on disk. With `true`, the
+    // issues are also fixed: records of missing or broken files and unknown files
+    // are removed, files in read-only roots and imported files are never touched.
+    // Returns what was found.
+    CheckLibrary(bool, Sender<Result<Vec<LibraryIssue>>>),
+    // Imported files are only forgotten, they stay on disk
     DeleteFile(FileID, Sender<Result<()>>),
+    // Re-check the SHA-256 of a file that is already on disk
+    VerifyFile(FileID, Sender<Result<FileVerification>>),
 
     GetCurrentDownloads(Sender<Result<Vec<PendingDownload>>>),
     GetDownloadedFiles(Sender<Result<Vec<DownloadedFile>>>),
@@ -265,8 +265,8 @@ impl App {
                 DownloadPendingNotification::DownloadedFile(file) => {
                     popup.set_data(&file, DownloadResult::Success);
                 }
-                DownloadPendingNotification::DownloadErrored(file) => {
-                    popup.set_data(&file, DownloadResult::Failure);
+                DownloadPendingNotification::DownloadErrored(file, error) => {
+                    popup.set_data(&file, DownloadResult::Failure(error));
                 }
             }
 
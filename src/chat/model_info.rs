@@ -78,6 +78,13 @@ live_design! {
             }
         }
 
+        resources_tag = <ModelAttributeTag> {
+            visible: false,
+            draw_bg: {
+                color: #E3FBCC,
+            }
+        }
+
         icon_tick_tag = <RoundedView> {
             align: {x: 1.0, y: 0.5}, 
             visible: false,
@@ -372,8 +372,14 @@ impl ModelSelector {
 
     fn update_loading_model_state(&mut self, cx: &mut Cx, store: &Store) {
         if store.chats.model_loader.is_loading() {
-            self.model_selector_loading(id!(loading))
-                .show_and_animate(cx);
+            let progress = store
+                .chats
+                .model_loader
+                .progress()
+                .map_or(0.0, |(_, progress)| progress);
+            let mut loading = self.model_selector_loading(id!(loading));
+            loading.set_progress(cx, progress);
+            loading.show_and_animate(cx);
         } else {
             self.model_selector_loading(id!(loading)).hide();
         }
@@ -403,7 +409,15 @@ impl ModelSelector {
             };
 
             let caption = if is_loading {
-                format!("Loading {}", file.name.trim())
+                match store.chats.model_loader.progress() {
+                    Some((stage, progress)) => format!(
+                        "Loading {} ({} {:.0}%)",
+                        file.name.trim(),
+                        stage.as_str(),
+                        progress * 100.0
+                    ),
+                    None => format!("Loading {}", file.name.trim()),
+                }
             } else {
                 file.name.trim().to_string()
             };
@@ -411,11 +425,27 @@ impl ModelSelector {
             let file_size = format_model_size(file.size.trim()).unwrap_or("".into());
             let is_file_size_visible = !file_size.is_empty() && !is_loading;
 
+            let resources = store
+                .chats
+                .model_loader
+                .resources()
+                .filter(|_| !is_loading && Some(&file.id) == loaded_file.map(|f| &f.id))
+                .map(|usage| {
+                    format!(
+                        "RAM {}, CPU {:.0}%",
+                        format_model_size(&usage.ram_usage.to_string()).unwrap_or_default(),
+                        usage.cpu_usage
+                    )
+                });
+            let is_resources_visible = resources.is_some();
+            let resources = resources.unwrap_or_default();
+
             selected_view.apply_over(
                 cx,
                 live! {
                     label = { text: (caption), draw_text: { color: (text_color) }}
                     file_size_tag = { visible: (is_file_size_visible), caption = { text: (file_size), draw_text: { color: (text_color) }}}
+                    resources_tag = { visible: (is_resources_visible), caption = { text: (resources) }}
                 },
             );
 
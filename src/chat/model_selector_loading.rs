@@ -18,6 +18,8 @@ live_design! {
         draw_bg: {
             instance radius: 1.0,
             instance dither: 0.9
+            // Part of the bar filled, the rest is dimmed
+            instance progress: 1.0
 
             fn get_color(self) -> vec4 {
                 return mix(
@@ -40,6 +42,9 @@ live_design! {
                 if self.border_width > 0.0 {
                     sdf.stroke(self.get_border_color(), self.border_width)
                 }
+                if self.pos.x > self.progress {
+                    return sdf.result * 0.3;
+                }
                 return sdf.result;
             }
         }
@@ -121,6 +126,17 @@ impl ModelSelectorLoadingRef {
         }
     }
 
+    /// Fill the bar up to `progress`, from 0 to 1.
+    pub fn set_progress(&mut self, cx: &mut Cx, progress: f32) {
+        let Some(mut inner) = self.borrow_mut() else {
+            return;
+        };
+        let progress = progress as f64;
+        inner
+            .view(id!(line))
+            .apply_over(cx, live! { draw_bg: { progress: (progress) } });
+    }
+
     pub fn hide(&mut self) {
         let Some(mut inner) = self.borrow_mut() else {
             return;
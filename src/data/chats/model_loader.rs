@@ -2,7 +2,10 @@ use anyhow::anyhow;
 use makepad_widgets::SignalToUI;
 use moly_protocol::{
     data::FileID,
-    protocol::{Command, LoadModelOptions, LoadModelResponse, LoadedModelInfo},
+    protocol::{
+        Command, LoadModelOptions, LoadModelResponse, LoadModelStage, LoadedModelInfo,
+        ModelResourcesInfo,
+    },
 };
 use std::{
     sync::{
@@ -26,6 +29,10 @@ pub enum ModelLoaderStatus {
 struct ModelLoaderInner {
     status: ModelLoaderStatus,
     file_id: Option<FileID>,
+    // Stage and overall progress of the load in course
+    progress: Option<(LoadModelStage, f32)>,
+    // Latest usage sampled by the backend while the model is loaded
+    resources: Option<ModelResourcesInfo>,
 }
 
 /// Unit for handling the non-blocking loading of models across threads.
@@ -61,28 +68,36 @@ impl ModelLoader {
 
         self.set_status(ModelLoaderStatus::Loading);
         self.set_file_id(Some(file_id.clone()));
+        self.set_progress(None);
+        self.set_resources(None);
 
-        let response = dispatch_load_command(command_sender, file_id.clone(), override_port).recv();
+        let rx = dispatch_load_command(command_sender, file_id.clone(), override_port);
 
-        let result = if let Ok(response) = response {
-            match response {
-                Ok(LoadModelResponse::Completed(info)) => {
+        let result = loop {
+            match rx.recv() {
+                Ok(Ok(LoadModelResponse::Progress(_, stage, progress))) => {
+                    self.set_progress(Some((stage, progress)));
+                    SignalToUI::set_ui_signal();
+                }
+                Ok(Ok(LoadModelResponse::ModelResourcesUsage(usage))) => {
+                    self.set_resources(Some(usage));
+                }
+                Ok(Ok(LoadModelResponse::Completed(info))) => {
                     self.set_status(ModelLoaderStatus::Loaded(info));
-                    Ok(())
+                    self.watch_resources(file_id, rx);
+                    break Ok(());
                 }
-                Ok(response) => {
+                Ok(Err(err)) => {
                     self.set_status(ModelLoaderStatus::Failed);
-                    Err(anyhow!("Unexpected response: {:?}", response))
+                    break Err(anyhow!(err));
                 }
-                Err(err) => {
+                Err(_) => {
                     self.set_status(ModelLoaderStatus::Failed);
-                    Err(anyhow!(err))
+                    break Err(anyhow!("Internal communication error"));
                 }
             }
-        } else {
-            self.set_status(ModelLoaderStatus::Failed);
-            Err(anyhow!("Internal communication error"))
         };
+        self.set_progress(None);
 
         SignalToUI::set_ui_signal();
         result
@@ -102,6 +117,27 @@ impl ModelLoader {
         });
     }
 
+    /// Keep the usage the backend samples up to date until it stops sending, when the
+    /// model is ejected or another one is loaded.
+    fn watch_resources(
+        &self,
+        file_id: FileID,
+        rx: Receiver<Result<LoadModelResponse, anyhow::Error>>,
+    ) {
+        let mut self_clone = self.clone();
+        thread::spawn(move || {
+            for response in rx {
+                if self_clone.file_id().as_ref() != Some(&file_id) || !self_clone.is_loaded() {
+                    break;
+                }
+                if let Ok(LoadModelResponse::ModelResourcesUsage(usage)) = response {
+                    self_clone.set_resources(Some(usage));
+                    SignalToUI::set_ui_signal();
+                }
+            }
+        });
+    }
+
     fn set_status(&mut self, status: ModelLoaderStatus) {
         self.0.lock().unwrap().status = status;
     }
@@ -110,6 +146,24 @@ impl ModelLoader {
         self.0.lock().unwrap().file_id = file_id;
     }
 
+    fn set_progress(&mut self, progress: Option<(LoadModelStage, f32)>) {
+        self.0.lock().unwrap().progress = progress;
+    }
+
+    fn set_resources(&mut self, resources: Option<ModelResourcesInfo>) {
+        self.0.lock().unwrap().resources = resources;
+    }
+
+    /// Stage and overall progress, from 0 to 1, of the model being loaded.
+    pub fn progress(&self) -> Option<(LoadModelStage, f32)> {
+        self.0.lock().unwrap().progress.clone()
+    }
+
+    /// Memory and processor usage of the loaded model, once the backend sampled it.
+    pub fn resources(&self) -> Option<ModelResourcesInfo> {
+        self.0.lock().unwrap().resources.clone()
+    }
+
     pub fn file_id(&self) -> Option<FileID> {
         self.0.lock().unwrap().file_id.clone()
     }
@@ -1,21 +1,25 @@
 use makepad_widgets::SignalToUI;
 use moly_backend::Backend;
 use moly_protocol::data::*;
-use moly_protocol::protocol::{Command, FileDownloadResponse};
+use moly_protocol::protocol::{Command, FileDownloadError, FileDownloadResponse};
 use std::sync::mpsc::{channel, Receiver, Sender};
 use std::thread;
 
 pub enum DownloadFileAction {
-    Progress(f64),
-    Error,
+    Queued,
+    Progress(DownloadProgress),
+    Retrying(u32),
+    Error(anyhow::Error),
     StreamingDone,
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Debug)]
 pub enum DownloadState {
+    Queued(f64),
     Initializing(f64),
     Downloading(f64),
-    Errored(f64),
+    Retrying(f64, u32),
+    Errored(f64, String),
     Completed,
 }
 
@@ -25,6 +29,9 @@ pub struct Download {
     pub sender: Sender<DownloadFileAction>,
     pub receiver: Receiver<DownloadFileAction>,
     pub state: DownloadState,
+    pub transfer: DownloadProgress,
+    // Set when the backend reported a failure it can tell apart, e.g. a gated model
+    pub error: Option<FileDownloadError>,
     pub notification_pending: bool,
 }
 
@@ -36,6 +43,8 @@ impl Download {
             sender: tx,
             receiver: rx,
             state: DownloadState::Initializing(progress),
+            transfer: DownloadProgress::default(),
+            error: None,
             notification_pending: false,
         };
 
@@ -62,15 +71,21 @@ impl Download {
                                 .unwrap();
                         }
                         FileDownloadResponse::Progress(_file, value) => store_download_tx
-                            .send(DownloadFileAction::Progress(value as f64))
+                            .send(DownloadFileAction::Progress(value))
+                            .unwrap(),
+                        FileDownloadResponse::Retrying(_file, attempt) => store_download_tx
+                            .send(DownloadFileAction::Retrying(attempt))
+                            .unwrap(),
+                        FileDownloadResponse::Queued(_file) => store_download_tx
+                            .send(DownloadFileAction::Queued)
                             .unwrap(),
                     },
                     Err(err) => {
+                        eprintln!("Error downloading file: {:?}", err);
+
                         store_download_tx
-                            .send(DownloadFileAction::Error)
+                            .send(DownloadFileAction::Error(err))
                             .unwrap();
-
-                        eprintln!("Error downloading file: {:?}", err)
                     },
                 }
             } else {
@@ -87,22 +102,43 @@ impl Download {
     pub fn process_download_progress(&mut self) {
         for msg in self.receiver.try_iter() {
             match msg {
+                DownloadFileAction::Queued => {
+                    let current_progress = self.get_progress();
+                    self.state = DownloadState::Queued(current_progress);
+                }
                 DownloadFileAction::StreamingDone => {
                     self.state = DownloadState::Completed;
                     self.notification_pending = true;
                 }
                 DownloadFileAction::Progress(value) => {
-                    self.state = DownloadState::Downloading(value)
+                    self.state = DownloadState::Downloading(value.percentage());
+                    self.transfer = value;
+                }
+                DownloadFileAction::Retrying(attempt) => {
+                    let current_progress = self.get_progress();
+                    self.state = DownloadState::Retrying(current_progress, attempt);
+                    self.reset_speed();
                 }
-                DownloadFileAction::Error => {
+                DownloadFileAction::Error(err) => {
                     let current_progress = self.get_progress();
-                    self.state = DownloadState::Errored(current_progress);
+                    self.error = err.downcast_ref::<FileDownloadError>().cloned();
+                    self.state = DownloadState::Errored(current_progress, err.to_string());
+                    self.reset_speed();
                     self.notification_pending = true;
                 }
             }
         }
     }
 
+    /// Nothing is being transferred, keep the byte counts but drop speed and ETA.
+    fn reset_speed(&mut self) {
+        self.transfer = DownloadProgress::new(
+            self.transfer.downloaded_bytes,
+            self.transfer.total_bytes,
+            0.0,
+        );
+    }
+
     pub fn is_initializing(&self) -> bool {
         matches!(self.state, DownloadState::Initializing(..))
     }
@@ -113,9 +149,11 @@ impl Download {
 
     pub fn get_progress(&self) -> f64 {
         match self.state {
+            DownloadState::Queued(progress) => progress,
             DownloadState::Initializing(progress) => progress,
             DownloadState::Downloading(progress) => progress,
-            DownloadState::Errored(progress) => progress,
+            DownloadState::Retrying(progress, _) => progress,
+            DownloadState::Errored(progress, _) => progress,
             DownloadState::Completed => 1.0,
         }
     }
@@ -4,15 +4,18 @@ use anyhow::{Context, Result};
 use download::{Download, DownloadState};
 use moly_backend::Backend;
 use moly_protocol::{
-    data::{DownloadedFile, File, FileID, Model, PendingDownload, PendingDownloadsStatus},
-    protocol::Command,
+    data::{
+        DownloadProgress, DownloadedFile, File, FileID, LibraryIssue, Model, PendingDownload,
+        PendingDownloadsStatus,
+    },
+    protocol::{Command, FileDownloadError},
 };
 use std::{collections::HashMap, rc::Rc, sync::mpsc::channel};
 
 #[derive(Debug)]
 pub enum DownloadPendingNotification {
     DownloadedFile(File),
-    DownloadErrored(File),
+    DownloadErrored(File, Option<FileDownloadError>),
 }
 pub struct Downloads {
     pub backend: Rc<Backend>,
@@ -20,6 +23,8 @@ pub struct Downloads {
     pub pending_downloads: Vec<PendingDownload>,
     pub current_downloads: HashMap<FileID, Download>,
     pub pending_notifications: Vec<DownloadPendingNotification>,
+    // Found by the last library check
+    pub library_issues: Vec<LibraryIssue>,
 }
 
 impl Downloads {
@@ -30,6 +35,7 @@ impl Downloads {
             pending_downloads: Vec::new(),
             current_downloads: HashMap::new(),
             pending_notifications: Vec::new(),
+            library_issues: Vec::new(),
         }
     }
 
@@ -62,22 +68,26 @@ impl Downloads {
         if let Ok(response) = rx.recv() {
             match response {
                 Ok(files) => {
+                    // Already in queue order
                     self.pending_downloads = files;
 
-                    self.pending_downloads
-                        .sort_by(|a, b| b.file.id.cmp(&a.file.id));
-
-                    // There is a issue with the backend response where all pending
-                    // downloads come with status `Paused` even if they are downloading.
-                    self.pending_downloads.iter_mut().for_each(|d| {
-                        if let Some(current) = self.current_downloads.get(&d.file.id) {
-                            if current.is_initializing() {
-                                d.status = PendingDownloadsStatus::Initializing;
-                            } else {
-                                d.status = PendingDownloadsStatus::Downloading;
-                            }
+                    // Downloads resumed by the backend on startup, follow their progress
+                    for pending in &self.pending_downloads {
+                        let is_active = !matches!(
+                            pending.status,
+                            PendingDownloadsStatus::Paused | PendingDownloadsStatus::Error(_)
+                        );
+                        if is_active && !self.current_downloads.contains_key(&pending.file.id) {
+                            self.current_downloads.insert(
+                                pending.file.id.clone(),
+                                Download::new(
+                                    pending.file.clone(),
+                                    pending.progress,
+                                    &self.backend.as_ref(),
+                                ),
+                            );
                         }
-                    });
+                    }
                 }
                 Err(err) => eprintln!("Error fetching pending downloads: {:?}", err),
             }
@@ -99,6 +109,7 @@ impl Downloads {
                 file: file.clone(),
                 model: model.clone(),
                 progress: 0.0,
+                transfer: DownloadProgress::default(),
                 status: PendingDownloadsStatus::Initializing,
             };
             self.pending_downloads.push(pending_download);
@@ -165,6 +176,11 @@ impl Downloads {
                     self.pending_downloads.iter_mut().for_each(|d| {
                         if d.file.id == *file_id {
                             d.status = PendingDownloadsStatus::Paused;
+                            d.transfer = DownloadProgress::new(
+                                d.transfer.downloaded_bytes,
+                                d.transfer.total_bytes,
+                                0.0,
+                            );
                         }
                     });
                 }
@@ -173,6 +189,36 @@ impl Downloads {
         };
     }
 
+    pub fn prioritize_download_file(&mut self, file_id: &FileID) {
+        let (tx, rx) = channel();
+        self.backend
+            .as_ref()
+            .command_sender
+            .send(Command::PrioritizeDownload(file_id.clone(), tx))
+            .unwrap();
+
+        match rx.recv() {
+            Ok(Ok(())) => self.load_pending_downloads(),
+            Ok(Err(err)) => eprintln!("Error prioritizing download: {:?}", err),
+            Err(_) => {}
+        }
+    }
+
+    pub fn defer_download_file(&mut self, file_id: &FileID) {
+        let (tx, rx) = channel();
+        self.backend
+            .as_ref()
+            .command_sender
+            .send(Command::DeferDownload(file_id.clone(), tx))
+            .unwrap();
+
+        match rx.recv() {
+            Ok(Ok(())) => self.load_pending_downloads(),
+            Ok(Err(err)) => eprintln!("Error deferring download: {:?}", err),
+            Err(_) => {}
+        }
+    }
+
     pub fn cancel_download_file(&mut self, file_id: &FileID) {
         if let Some(current_download) = self.current_downloads.get(file_id) {
             if current_download.is_initializing() {
@@ -215,6 +261,28 @@ impl Downloads {
         Ok(())
     }
 
+    /// Compare the downloaded files with what is on disk, fixing the issues when
+    /// `repair` is set.
+    pub fn check_library(&mut self, repair: bool) -> Result<()> {
+        let (tx, rx) = channel();
+        self.backend
+            .as_ref()
+            .command_sender
+            .send(Command::CheckLibrary(repair, tx))
+            .context("Failed to send check library command")?;
+
+        self.library_issues = rx
+            .recv()
+            .context("Failed to receive check library response")?
+            .context("Check library operation failed")?;
+
+        if repair {
+            self.load_downloaded_files();
+            self.load_pending_downloads();
+        }
+        Ok(())
+    }
+
     pub fn next_download_notification(&mut self) -> Option<DownloadPendingNotification> {
         self.pending_notifications.pop()
     }
@@ -244,17 +312,26 @@ impl Downloads {
                 .find(|d| d.file.id == id.to_string())
             {
                 match download.state {
+                    DownloadState::Queued(_) => {
+                        pending.status = PendingDownloadsStatus::Queued;
+                    }
                     DownloadState::Initializing(_) => {
                         pending.status = PendingDownloadsStatus::Initializing;
                     }
                     DownloadState::Downloading(_) => {
                         pending.status = PendingDownloadsStatus::Downloading;
                     }
-                    DownloadState::Errored(_) => {
-                        pending.status = PendingDownloadsStatus::Error;
+                    DownloadState::Retrying(_, attempt) => {
+                        pending.status = PendingDownloadsStatus::Retrying(attempt);
+                    }
+                    DownloadState::Errored(_, ref reason) => {
+                        pending.status = PendingDownloadsStatus::Error(reason.clone());
                         if download.must_show_notification() {
                             self.pending_notifications.push(
-                                DownloadPendingNotification::DownloadErrored(download.file.clone()),
+                                DownloadPendingNotification::DownloadErrored(
+                                    download.file.clone(),
+                                    download.error.clone(),
+                                ),
                             );
                         }
                     }
@@ -267,6 +344,10 @@ impl Downloads {
                     }
                 };
                 pending.progress = download.get_progress();
+                // Keep the byte counts loaded from the backend until the first report
+                if download.transfer.total_bytes > 0 {
+                    pending.transfer = download.transfer;
+                }
             }
 
             if download.is_complete() {
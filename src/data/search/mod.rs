@@ -20,6 +20,7 @@ pub enum SortCriteria {
 pub enum SearchAction {
     Results(Vec<Model>),
     Error,
+    CatalogUpdated(CatalogUpdate),
 }
 
 #[derive(Clone)]
@@ -43,6 +44,7 @@ pub struct Search {
     pub sender: Sender<SearchAction>,
     pub receiver: Receiver<SearchAction>,
     pub state: SearchState,
+    pub catalog_status: CatalogStatus,
 }
 
 impl Search {
@@ -56,6 +58,7 @@ impl Search {
             sender: tx,
             receiver: rx,
             state: SearchState::Idle,
+            catalog_status: CatalogStatus::default(),
         };
         search
     }
@@ -97,6 +100,53 @@ impl Search {
         });
     }
 
+    pub fn load_catalog_status(&mut self) {
+        let (tx, rx) = channel();
+        self.backend
+            .as_ref()
+            .command_sender
+            .send(Command::GetCatalogStatus(tx))
+            .unwrap();
+
+        if let Ok(response) = rx.recv() {
+            match response {
+                Ok(status) => {
+                    self.catalog_status = status;
+                }
+                Err(err) => eprintln!("Error fetching the catalog status: {:?}", err),
+            }
+        };
+    }
+
+    /// Keep the catalog status and the models shown up to date with the syncs the
+    /// backend runs in the background.
+    pub fn watch_catalog(&self) {
+        let (tx, rx) = channel();
+        self.backend
+            .as_ref()
+            .command_sender
+            .send(Command::WatchCatalog(tx))
+            .unwrap();
+
+        let store_search_tx = self.sender.clone();
+        thread::spawn(move || {
+            while let Ok(response) = rx.recv() {
+                match response {
+                    Ok(update) => {
+                        if store_search_tx
+                            .send(SearchAction::CatalogUpdated(update))
+                            .is_err()
+                        {
+                            break;
+                        }
+                        SignalToUI::set_ui_signal();
+                    }
+                    Err(err) => eprintln!("Error syncing the catalog: {:?}", err),
+                }
+            }
+        });
+    }
+
     pub fn load_search_results(&mut self, query: String) {
         self.run_or_enqueue(query);
     }
@@ -183,7 +233,7 @@ impl Search {
     }
 
     pub fn process_results(&mut self) -> Result<Option<Vec<Model>>> {
-        for msg in self.receiver.try_iter() {
+        while let Ok(msg) = self.receiver.try_recv() {
             match msg {
                 SearchAction::Results(models) => {
                     let previous_state = self.state.to_owned();
@@ -212,6 +262,16 @@ impl Search {
                     self.state = SearchState::Errored;
                     return Err(anyhow!("Error fetching models from the server"));
                 }
+                SearchAction::CatalogUpdated(update) => {
+                    self.catalog_status.updated_at = update.updated_at;
+                    if update.new_models + update.changed_models > 0 {
+                        // Show what is there now for the same search
+                        match self.keyword.clone() {
+                            Some(keyword) => self.run_or_enqueue(keyword),
+                            None => self.load_featured_models(),
+                        }
+                    }
+                }
             }
         }
         Ok(None)
@@ -8,7 +8,11 @@ use chrono::{DateTime, Utc};
 use makepad_widgets::{DefaultNone, SignalToUI};
 use moly_backend::Backend;
 use moly_protocol::data::{Author, DownloadedFile, File, FileID, Model, ModelID, PendingDownload};
+use moly_protocol::protocol::{Command, ImportResponse, ImportedFile, ModelsDirMigrationResponse};
+use std::path::PathBuf;
 use std::rc::Rc;
+use std::sync::mpsc::{channel, Receiver};
+use std::thread;
 
 pub const DEFAULT_MAX_DOWNLOAD_THREADS: usize = 3;
 
@@ -39,6 +43,7 @@ pub struct ModelWithDownloadInfo {
     pub like_count: u32,
     pub download_count: u32,
     pub files: Vec<FileWithDownloadInfo>,
+    pub source: String,
 }
 pub struct Store {
     /// This is the backend representation, including the sender and receiver ends of the channels to
@@ -49,6 +54,25 @@ pub struct Store {
     pub downloads: Downloads,
     pub chats: Chats,
     pub preferences: Preferences,
+
+    /// Set while the backend moves the downloaded files to another directory.
+    pub models_dir_migration: Option<ModelsDirMigration>,
+
+    /// Set while the backend imports files from this computer.
+    pub local_import: Option<LocalImport>,
+}
+
+pub struct ModelsDirMigration {
+    pub path: PathBuf,
+    // Between 0 and 100
+    pub progress: f64,
+    receiver: Receiver<Result<ModelsDirMigrationResponse>>,
+}
+
+pub struct LocalImport {
+    // Between 0 and 100
+    pub progress: f64,
+    receiver: Receiver<Result<ImportResponse>>,
 }
 
 impl Default for Store {
@@ -74,14 +98,21 @@ impl Store {
             downloads: Downloads::new(backend.clone()),
             chats: Chats::new(backend),
             preferences,
+            models_dir_migration: None,
+            local_import: None,
         };
 
         store.downloads.load_downloaded_files();
         store.downloads.load_pending_downloads();
+        if let Err(err) = store.downloads.check_library(false) {
+            eprintln!("Error checking the library: {:?}", err);
+        }
 
         store.chats.load_chats();
         store.init_current_chat();
 
+        store.search.load_catalog_status();
+        store.search.watch_catalog();
         store.search.load_featured_models();
         store
     }
@@ -237,6 +268,7 @@ impl Store {
             released_at: model.released_at,
             author: model.author.clone(),
             files,
+            source: model.source.clone(),
         }
     }
 
@@ -262,13 +294,160 @@ impl Store {
         Ok(())
     }
 
+    /// List the GGUF files in `paths` as downloaded, directories included. The
+    /// backend works in the background, the results arrive as event signals.
+    pub fn import_local_files(&mut self, paths: Vec<PathBuf>) {
+        if self.local_import.is_some() {
+            return;
+        }
+
+        let (tx, rx) = channel();
+        let (store_tx, store_rx) = channel();
+        self.backend
+            .command_sender
+            .send(Command::ImportLocalFiles(paths, tx))
+            .unwrap();
+
+        thread::spawn(move || {
+            for response in rx {
+                let is_done = !matches!(response, Ok(ImportResponse::Progress { .. }));
+                if store_tx.send(response).is_err() {
+                    break;
+                }
+                SignalToUI::set_ui_signal();
+                if is_done {
+                    break;
+                }
+            }
+        });
+
+        self.local_import = Some(LocalImport {
+            progress: 0.0,
+            receiver: store_rx,
+        });
+    }
+
+    /// Ask the backend to move the downloaded files to `path`. The preferences
+    /// point at it once all the files are there.
+    pub fn change_models_dir(&mut self, path: PathBuf) {
+        if self.models_dir_migration.is_some() {
+            return;
+        }
+
+        let (tx, rx) = channel();
+        let (store_tx, store_rx) = channel();
+        self.backend
+            .command_sender
+            .send(Command::ChangeModelsDir(path.clone(), tx))
+            .unwrap();
+
+        thread::spawn(move || {
+            for response in rx {
+                let is_done = !matches!(response, Ok(ModelsDirMigrationResponse::Progress { .. }));
+                if store_tx.send(response).is_err() {
+                    break;
+                }
+                SignalToUI::set_ui_signal();
+                if is_done {
+                    break;
+                }
+            }
+        });
+
+        self.models_dir_migration = Some(ModelsDirMigration {
+            path,
+            progress: 0.0,
+            receiver: store_rx,
+        });
+    }
+
     pub fn process_event_signal(&mut self) {
+        self.update_models_dir_migration();
+        self.update_local_import();
         self.update_downloads();
         self.update_chat_messages();
         self.update_search_results();
         self.update_load_model();
     }
 
+    fn update_models_dir_migration(&mut self) {
+        let Some(migration) = &mut self.models_dir_migration else {
+            return;
+        };
+
+        let mut result = None;
+        for response in migration.receiver.try_iter() {
+            match response {
+                Ok(ModelsDirMigrationResponse::Progress {
+                    moved_bytes,
+                    total_bytes,
+                }) => {
+                    if total_bytes > 0 {
+                        migration.progress = moved_bytes as f64 / total_bytes as f64 * 100.0;
+                    }
+                }
+                Ok(ModelsDirMigrationResponse::Completed) => result = Some(Ok(())),
+                Err(err) => result = Some(Err(err)),
+            }
+        }
+
+        match result {
+            Some(Ok(())) => {
+                let path = migration.path.clone();
+                self.preferences.set_downloaded_files_dir(path);
+                self.models_dir_migration = None;
+            }
+            Some(Err(err)) => {
+                eprintln!("Error moving the downloaded files: {:?}", err);
+                self.models_dir_migration = None;
+            }
+            None => {}
+        }
+    }
+
+    fn update_local_import(&mut self) {
+        let Some(import) = &mut self.local_import else {
+            return;
+        };
+
+        let mut result = None;
+        for response in import.receiver.try_iter() {
+            match response {
+                Ok(ImportResponse::Progress { imported, total }) => {
+                    if total > 0 {
+                        import.progress = imported as f64 / total as f64 * 100.0;
+                    }
+                }
+                Ok(ImportResponse::Completed(imported)) => result = Some(Ok(imported)),
+                Err(err) => result = Some(Err(err)),
+            }
+        }
+
+        match result {
+            Some(Ok(imported)) => {
+                for file in imported {
+                    match file {
+                        ImportedFile::Matched { file_id, .. } => {
+                            self.search
+                                .update_downloaded_file_in_search_results(&file_id, true);
+                        }
+                        ImportedFile::Failed { path, reason } => {
+                            eprintln!("Failed to import {}: {}", path.display(), reason);
+                        }
+                        _ => {}
+                    }
+                }
+                self.downloads.load_downloaded_files();
+                self.local_import = None;
+            }
+            Some(Err(err)) => {
+                eprintln!("Error importing models: {:?}", err);
+                self.local_import = None;
+            }
+            None => {}
+        }
+    }
+
     fn update_search_results(&mut self) {
         match self.search.process_results() {
             Ok(Some(models)) => {
@@ -1,6 +1,9 @@
 use crate::shared::{
     actions::DownloadAction,
-    utils::{format_model_downloaded_size, format_model_size},
+    utils::{
+        format_bytes, format_download_speed, format_eta, format_model_downloaded_size,
+        format_model_size,
+    },
 };
 use makepad_widgets::*;
 use moly_protocol::data::{FileID, PendingDownload, PendingDownloadsStatus};
@@ -280,6 +283,32 @@ impl Widget for DownloadItem {
                 self.button(id!(retry_button)).set_visible(false);
                 self.button(id!(cancel_button)).set_visible(true);
             }
+            PendingDownloadsStatus::Retrying(attempt) => {
+                let retrying_color = vec3(0.86, 0.41, 0.04); //#DC6803
+
+                label.set_text(&format!(
+                    "Retrying (attempt {}) {:.1}%",
+                    attempt, download.progress
+                ));
+                label.apply_over(
+                    cx,
+                    live! { draw_text: { color: (retrying_color) }
+                    },
+                );
+
+                self.view(id!(progress_bar)).apply_over(
+                    cx,
+                    live! {
+                        width: (progress_bar_width)
+                        draw_bg: { color: (retrying_color) }
+                    },
+                );
+
+                self.button(id!(pause_button)).set_visible(true);
+                self.button(id!(play_button)).set_visible(false);
+                self.button(id!(retry_button)).set_visible(false);
+                self.button(id!(cancel_button)).set_visible(true);
+            }
             PendingDownloadsStatus::Paused => {
                 let paused_color = vec3(0.4, 0.44, 0.52); //#667085
 
@@ -303,10 +332,60 @@ impl Widget for DownloadItem {
                 self.button(id!(retry_button)).set_visible(false);
                 self.button(id!(cancel_button)).set_visible(true);
             }
-            PendingDownloadsStatus::Error => {
+            PendingDownloadsStatus::Queued => {
+                let queued_color = vec3(0.4, 0.44, 0.52); //#667085
+
+                label.set_text(&format!("Queued {:.1}%", download.progress));
+                label.apply_over(
+                    cx,
+                    live! { draw_text: { color: (queued_color) }
+                    },
+                );
+
+                self.view(id!(progress_bar)).apply_over(
+                    cx,
+                    live! {
+                        width: (progress_bar_width)
+                        draw_bg: { color: (queued_color) }
+                    },
+                );
+
+                self.button(id!(pause_button)).set_visible(true);
+                self.button(id!(play_button)).set_visible(false);
+                self.button(id!(retry_button)).set_visible(false);
+                self.button(id!(cancel_button)).set_visible(true);
+            }
+            PendingDownloadsStatus::Verifying => {
+                let downloading_color = vec3(0.035, 0.572, 0.314); //#099250
+
+                label.set_text("Verifying checksum");
+                label.apply_over(
+                    cx,
+                    live! { draw_text: { color: (downloading_color) }
+                    },
+                );
+
+                self.view(id!(progress_bar)).apply_over(
+                    cx,
+                    live! {
+                        width: (progress_bar_width)
+                        draw_bg: { color: (downloading_color) }
+                    },
+                );
+
+                self.button(id!(pause_button)).set_visible(false);
+                self.button(id!(play_button)).set_visible(false);
+                self.button(id!(retry_button)).set_visible(false);
+                self.button(id!(cancel_button)).set_visible(false);
+            }
+            PendingDownloadsStatus::Error(ref reason) => {
                 let failed_color = vec3(0.7, 0.11, 0.09); // #B42318
 
-                label.set_text(&format!("Error {:.1}%", download.progress));
+                if reason.is_empty() {
+                    label.set_text(&format!("Error {:.1}%", download.progress));
+                } else {
+                    label.set_text(&format!("Error {:.1}%: {}", download.progress, reason));
+                }
                 label.apply_over(
                     cx,
                     live! { draw_text: { color: (failed_color) }
@@ -328,12 +407,32 @@ impl Widget for DownloadItem {
             }
         }
 
-        let total_size = format_model_size(&download.file.size).unwrap_or("-".to_string());
-        let downloaded_size = format_model_downloaded_size(&download.file.size, download.progress)
-            .unwrap_or("-".to_string());
+        let transfer = &download.transfer;
+        let mut size_text = if transfer.total_bytes > 0 {
+            format!(
+                "{} / {}",
+                format_bytes(transfer.downloaded_bytes),
+                format_bytes(transfer.total_bytes)
+            )
+        } else {
+            let total_size = format_model_size(&download.file.size).unwrap_or("-".to_string());
+            let downloaded_size =
+                format_model_downloaded_size(&download.file.size, download.progress)
+                    .unwrap_or("-".to_string());
+            format!("{} / {}", downloaded_size, total_size)
+        };
+
+        if transfer.bytes_per_second > 0.0 {
+            size_text.push_str(&format!(
+                " | {}",
+                format_download_speed(transfer.bytes_per_second)
+            ));
+            if let Some(eta) = transfer.eta {
+                size_text.push_str(&format!(" | {}", format_eta(eta)));
+            }
+        }
 
-        self.label(id!(downloaded_size))
-            .set_text(&format!("{} / {}", downloaded_size, total_size));
+        self.label(id!(downloaded_size)).set_text(&size_text);
 
         self.view.draw_walk(cx, scope, walk)
     }
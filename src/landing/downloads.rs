@@ -161,7 +161,11 @@ impl Widget for Downloads {
             .filter(|d| {
                 matches!(
                     d.status,
-                    PendingDownloadsStatus::Downloading | PendingDownloadsStatus::Initializing
+                    PendingDownloadsStatus::Downloading
+                        | PendingDownloadsStatus::Initializing
+                        | PendingDownloadsStatus::Queued
+                        | PendingDownloadsStatus::Retrying(_)
+                        | PendingDownloadsStatus::Verifying
                 )
             })
             .count();
@@ -177,7 +181,7 @@ impl Widget for Downloads {
 
         let failed_count = pending_downloads
             .iter()
-            .filter(|d| matches!(d.status, PendingDownloadsStatus::Error))
+            .filter(|d| matches!(d.status, PendingDownloadsStatus::Error(_)))
             .count();
 
         if failed_count > 0 {
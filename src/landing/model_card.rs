@@ -112,6 +112,24 @@ live_design! {
                 }
             }
 
+            model_source_tag = <ModelAttributeTag> {
+                width: Fit,
+                height: Fit,
+
+                draw_bg: {
+                    color: #0000,
+                    border_color: #98A2B3,
+                    border_width: 1.0,
+                },
+                attr_name = {
+                    draw_text: { color: #000 }
+                    text: "Source"
+                }
+                attr_value = {
+                    margin: {left: 10},
+                    draw_text: { color: #000 }
+                }
+            }
 
             <View> {
                 width: 260,
@@ -399,6 +417,12 @@ impl Widget for ModelCard {
         self.label(id!(author_description))
             .set_text(author_description);
 
+        let source = &model.source;
+        self.view(id!(model_source_tag))
+            .set_visible(!source.is_empty());
+        self.label(id!(model_source_tag.attr_value))
+            .set_text(source);
+
         let released_at_str = formatted_model_release_date(&model);
         self.label(id!(model_released_at_tag.attr_value))
             .set_text(&released_at_str);
@@ -1,5 +1,5 @@
 use makepad_widgets::*;
-use moly_protocol::data::{File, FileID, PendingDownloadsStatus};
+use moly_protocol::data::{CompatibilityGuess, File, FileID, PendingDownloadsStatus};
 
 use super::model_files_tags::ModelFilesTagsWidgetExt;
 use crate::{
@@ -152,6 +152,11 @@ live_design! {
                     color: #000
                 }
             }
+            compatibility = <Label> {
+                draw_text:{
+                    text_style: <REGULAR_FONT>{font_size: 8},
+                }
+            }
         }
 
         cell2 = {
@@ -221,11 +226,20 @@ impl Widget for ModelFilesItem {
         let filename = &files_info.file.name;
         let size = format_model_size(&files_info.file.size).unwrap_or("-".to_string());
         let quantization = &files_info.file.quantization;
+
+        let (compatibility, compatibility_color) =
+            compatibility_note(&files_info.file.compatibility_guess)
+                .unwrap_or((String::new(), vec3(0.0, 0.0, 0.0)));
+
         self.apply_over(
             cx,
             live! {
                 cell1 = {
                     filename = { text: (filename) }
+                    compatibility = {
+                        text: (compatibility)
+                        draw_text: { color: (compatibility_color) }
+                    }
                 }
                 cell2 = { full_size = { text: (size) }}
                 cell3 = {
@@ -241,19 +255,28 @@ impl Widget for ModelFilesItem {
 
             let is_resume_download_visible =
                 matches!(download.status, PendingDownloadsStatus::Paused);
-            let is_pause_download_visible =
-                matches!(download.status, PendingDownloadsStatus::Downloading);
+            let is_pause_download_visible = matches!(
+                download.status,
+                PendingDownloadsStatus::Downloading
+                    | PendingDownloadsStatus::Queued
+                    | PendingDownloadsStatus::Retrying(_)
+            );
             let is_retry_download_visible =
-                matches!(download.status, PendingDownloadsStatus::Error);
-            let is_cancel_download_visible =
-                !matches!(download.status, PendingDownloadsStatus::Initializing);
+                matches!(download.status, PendingDownloadsStatus::Error(_));
+            let is_cancel_download_visible = !matches!(
+                download.status,
+                PendingDownloadsStatus::Initializing | PendingDownloadsStatus::Verifying
+            );
 
             let status_color = match download.status {
-                PendingDownloadsStatus::Downloading | PendingDownloadsStatus::Initializing => {
-                    vec3(0.035, 0.572, 0.314)
-                } // #099250
-                PendingDownloadsStatus::Paused => vec3(0.4, 0.44, 0.52), // #667085
-                PendingDownloadsStatus::Error => vec3(0.7, 0.11, 0.09),  // #B42318
+                PendingDownloadsStatus::Downloading
+                | PendingDownloadsStatus::Initializing
+                | PendingDownloadsStatus::Verifying => vec3(0.035, 0.572, 0.314), // #099250
+                PendingDownloadsStatus::Paused | PendingDownloadsStatus::Queued => {
+                    vec3(0.4, 0.44, 0.52)
+                } // #667085
+                PendingDownloadsStatus::Retrying(_) => vec3(0.86, 0.41, 0.04), // #DC6803
+                PendingDownloadsStatus::Error(_) => vec3(0.7, 0.11, 0.09),     // #B42318
             };
 
             self.apply_over(
@@ -318,6 +341,30 @@ impl Widget for ModelFilesItem {
     }
 }
 
+/// Whether the file fits in memory, with the memory it needs, and the color to
+/// show it in. Nothing when the memory of the system could not be read.
+fn compatibility_note(guess: &CompatibilityGuess) -> Option<(String, Vec3)> {
+    let color = match guess {
+        CompatibilityGuess::PossiblySupported => return None,
+        CompatibilityGuess::FitsComfortably(_) => vec3(0.035, 0.572, 0.314), // #099250
+        CompatibilityGuess::Tight(_) => vec3(0.86, 0.41, 0.04),              // #DC6803
+        CompatibilityGuess::WillNotFit(_) | CompatibilityGuess::NotSupported => {
+            vec3(0.7, 0.11, 0.09) // #B42318
+        }
+    };
+
+    let text = match guess.estimate() {
+        Some(estimate) => format!(
+            "{}, needs {} of {}",
+            guess.as_str(),
+            format_model_size(&estimate.required.to_string()).unwrap_or_default(),
+            format_model_size(&estimate.total_memory.to_string()).unwrap_or_default(),
+        ),
+        None => guess.as_str().to_string(),
+    };
+    Some((text, color))
+}
+
 impl WidgetMatchEvent for ModelFilesItem {
     fn handle_actions(&mut self, cx: &mut Cx, actions: &Actions, scope: &mut Scope) {
         let widget_uid = self.widget_uid();
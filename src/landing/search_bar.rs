@@ -1,7 +1,8 @@
 use crate::data::search::SortCriteria;
-use crate::data::store::StoreAction;
+use crate::data::store::{Store, StoreAction};
 use crate::landing::sorting::SortingWidgetExt;
 use makepad_widgets::*;
+use moly_protocol::data::CatalogStatus;
 
 live_design! {
     import makepad_widgets::base::*;
@@ -59,6 +60,14 @@ live_design! {
             }
         }
 
+        catalog_status = <Label> {
+            margin: {top: -20},
+            draw_text:{
+                text_style: <REGULAR_FONT>{font_size: 9},
+                color: #667085
+            }
+        }
+
         input_container = <RoundedView> {
             width: 800,
             height: Fit,
@@ -184,10 +193,31 @@ impl Widget for SearchBar {
     }
 
     fn draw_walk(&mut self, cx: &mut Cx2d, scope: &mut Scope, walk: Walk) -> DrawStep {
+        let store = scope.data.get::<Store>().unwrap();
+        let status = catalog_status_text(&store.search.catalog_status);
+        self.label(id!(catalog_status)).set_text(&status);
+
         self.view.draw_walk(cx, scope, walk)
     }
 }
 
+fn catalog_status_text(status: &CatalogStatus) -> String {
+    let updated_at = match status.updated_at {
+        Some(updated_at) => format!(
+            "Catalog last updated at {}",
+            updated_at
+                .with_timezone(&chrono::Local)
+                .format("%b %-d, %C%y %H:%M")
+        ),
+        None => "Catalog never updated".to_string(),
+    };
+    if status.offline {
+        format!("Offline · {updated_at}")
+    } else {
+        updated_at
+    }
+}
+
 impl WidgetMatchEvent for SearchBar {
     fn handle_actions(&mut self, cx: &mut Cx, actions: &Actions, scope: &mut Scope) {
         let input = self.text_input(id!(input));
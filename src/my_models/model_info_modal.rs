@@ -104,6 +104,7 @@ live_design! {
                 spacing: 20,
 
                 metadata = <MolyHtml> {}
+                information = <MolyHtml> {}
                 actions = <View> {
                     width: Fill, height: Fit
                     flow: Right,
@@ -208,6 +209,19 @@ impl Widget for ModelInfoModal {
 
         self.html(id!(wrapper.body.metadata)).set_text(&metadata);
 
+        // information read from the file itself
+        let information = serde_json::from_str::<serde_json::Value>(&downloaded_file.information)
+            .ok()
+            .and_then(|value| serde_json::to_string_pretty(&value).ok());
+        if let Some(information) = &information {
+            self.stringified_model_data.push('\n');
+            self.stringified_model_data.push_str(information);
+            self.html(id!(wrapper.body.information))
+                .set_text(&format!("<pre>{}</pre>", information));
+        }
+        self.view(id!(wrapper.body.information))
+            .set_visible(information.is_some());
+
         self.view
             .draw_walk(cx, scope, walk.with_abs_pos(DVec2 { x: 0., y: 0. }))
     }
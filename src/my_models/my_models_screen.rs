@@ -1,6 +1,5 @@
 use makepad_widgets::*;
-use moly_protocol::data::DownloadedFile;
-use moly_protocol::protocol::Command;
+use moly_protocol::data::{DownloadedFile, LibraryIssue};
 use std::path::PathBuf;
 
 use crate::{data::store::Store, shared::utils::BYTES_PER_MB};
@@ -69,6 +68,24 @@ live_design! {
         text: "Show in Folder"
     }
 
+    RepairLibraryButton = <MolyButton> {
+        width: Fit,
+        height: 28,
+        padding: {top: 6, bottom: 6, left: 14, right: 14}
+
+        draw_bg: {
+            radius: 2.0,
+            color: #FEFEFE,
+            color_hover: #999,
+        }
+
+        draw_text:{
+            text_style: <REGULAR_FONT>{font_size: 11},
+            color: #000
+        }
+        text: "Repair"
+    }
+
     SearchBar = <RoundedView> {
         width: Fit,
         height: Fit,
@@ -162,7 +179,26 @@ live_design! {
                 align: {x: 0.0, y: 0.5}
 
                 download_location = <DownloadLocationButton> {}
+                import_models = <DownloadLocationButton> {
+                    text: "Import Models"
+                }
                 show_in_files = <ShowInFilesButton> {}
+
+                library_issues = <View> {
+                    visible: false
+                    width: Fit, height: Fit
+                    spacing: 10
+                    align: {x: 0.0, y: 0.5}
+
+                    summary = <Label> {
+                        draw_text:{
+                            text_style: <REGULAR_FONT>{font_size: 11}
+                            color: #B42318
+                        }
+                    }
+                    repair_library = <RepairLibraryButton> {}
+                }
+
                 <View> { width: Fill, height: Fit }
                 search = <SearchBar> {}
             }
@@ -198,6 +234,14 @@ impl Widget for MyModelsScreen {
         let models_summary_label = self.view.label(id!(header.models_summary));
         models_summary_label.set_text(&summary);
 
+        let library_issues = &scope.data.get::<Store>().unwrap().downloads.library_issues;
+        self.view
+            .view(id!(library_issues))
+            .set_visible(!library_issues.is_empty());
+        self.view
+            .label(id!(library_issues.summary))
+            .set_text(&library_issues_summary(library_issues));
+
         self.view
             .label(id!(download_location.label))
             .label(id!(show_in_files.label))
@@ -249,16 +293,26 @@ impl WidgetMatchEvent for MyModelsScreen {
                 .pick_folder();
 
             if let Some(path) = res {
-                scope.preferences.set_downloaded_files_dir(path.clone());
-                scope
-                    .backend
-                    .as_ref()
-                    .command_sender
-                    .send(Command::ChangeModelsDir(path))
-                    .unwrap();
+                scope.change_models_dir(path);
+            }
+        }
+
+        if self.button(id!(import_models)).clicked(actions) {
+            // Every GGUF file in the folder is imported, subfolders included
+            if let Some(path) = rfd::FileDialog::new().pick_folder() {
+                let store = scope.data.get_mut::<Store>().unwrap();
+                store.import_local_files(vec![path]);
             }
         }
 
+        if self.button(id!(repair_library)).clicked(actions) {
+            let store = scope.data.get_mut::<Store>().unwrap();
+            if let Err(err) = store.downloads.check_library(true) {
+                eprintln!("Error repairing the library: {:?}", err);
+            }
+            self.redraw(cx);
+        }
+
         if let Some(keywords) = self.text_input(id!(search.input)).changed(actions) {
             if !keywords.is_empty() {
                 cx.action(MyModelsSearchAction::Search(keywords.to_string()));
@@ -276,6 +330,36 @@ pub enum MyModelsSearchAction {
     None,
 }
 
+fn library_issues_summary(issues: &[LibraryIssue]) -> String {
+    let mut missing = 0;
+    let mut incomplete = 0;
+    let mut unknown = 0;
+    let mut leftovers = 0;
+    for issue in issues {
+        match issue {
+            LibraryIssue::MissingFile { .. } => missing += 1,
+            LibraryIssue::SizeMismatch { .. } => incomplete += 1,
+            LibraryIssue::UnknownFile { .. } => unknown += 1,
+            LibraryIssue::EmptyDirectory { .. } | LibraryIssue::OrphanedModel { .. } => {
+                leftovers += 1
+            }
+        }
+    }
+
+    let parts: Vec<_> = [
+        (missing, "missing"),
+        (incomplete, "incomplete"),
+        (unknown, "unknown"),
+        (leftovers, "leftover"),
+    ]
+    .iter()
+    .filter(|(count, _)| *count > 0)
+    .map(|(count, kind)| format!("{} {}", count, kind))
+    .collect();
+
+    format!("Library problems: {}", parts.join(", "))
+}
+
 fn generate_models_summary(downloaded_files: &Vec<DownloadedFile>) -> String {
     let total_diskspace_mb = total_files_disk_space(downloaded_files);
     let disk_space_label = if total_diskspace_mb >= 1024.0 {
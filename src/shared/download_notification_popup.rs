@@ -1,5 +1,6 @@
 use makepad_widgets::*;
 use moly_protocol::data::{File, FileID};
+use moly_protocol::protocol::FileDownloadError;
 
 use crate::shared::actions::DownloadAction;
 
@@ -210,7 +211,8 @@ pub enum DownloadNotificationPopupAction {
 pub enum DownloadResult {
     #[default]
     Success,
-    Failure,
+    // Carries the reason when the backend could tell it apart from a generic error
+    Failure(Option<FileDownloadError>),
 }
 
 #[derive(Live, LiveHook, Widget)]
@@ -284,9 +286,12 @@ impl WidgetMatchEvent for DownloadNotificationPopup {
 
 impl DownloadNotificationPopup {
     pub fn update_content(&mut self) {
-        match self.download_result {
+        match &self.download_result {
             DownloadResult::Success => self.show_success_content(),
-            DownloadResult::Failure => self.show_failure_content(),
+            DownloadResult::Failure(error) => {
+                let error = error.clone();
+                self.show_failure_content(error)
+            }
         }
     }
 
@@ -304,22 +309,44 @@ impl DownloadNotificationPopup {
             .set_text(&(format!("{} successfuly downloaded.", &self.filename)));
     }
 
-    fn show_failure_content(&mut self) {
+    fn show_failure_content(&mut self, error: Option<FileDownloadError>) {
         self.view(id!(success_icon)).set_visible(false);
         self.view(id!(failure_icon)).set_visible(true);
 
         self.view(id!(success_actions)).set_visible(false);
         self.view(id!(failure_actions)).set_visible(true);
 
-        self.label(id!(title))
-            .set_text("Errors while downloading models");
-
-        self.label(id!(summary)).set_text(
-            &(format!(
-                "{} encountered some errors when downloading.",
-                &self.filename
-            )),
-        );
+        match error {
+            // The message tells what to do: add a token or accept the license
+            Some(error @ FileDownloadError::Unauthorized { token_sent, .. }) => {
+                let title = if token_sent {
+                    "Access to this model was denied"
+                } else {
+                    "This model requires a Hugging Face access token"
+                };
+                self.label(id!(title)).set_text(title);
+                self.label(id!(summary)).set_text(&error.to_string());
+            }
+            Some(
+                error @ (FileDownloadError::InsufficientSpace { .. }
+                | FileDownloadError::QuotaExceeded { .. }),
+            ) => {
+                self.label(id!(title))
+                    .set_text("Not enough space for this model");
+                self.label(id!(summary)).set_text(&error.to_string());
+            }
+            _ => {
+                self.label(id!(title))
+                    .set_text("Errors while downloading models");
+
+                self.label(id!(summary)).set_text(
+                    &(format!(
+                        "{} encountered some errors when downloading.",
+                        &self.filename
+                    )),
+                );
+            }
+        }
     }
 }
 
@@ -1,5 +1,6 @@
 use anyhow::Result;
 use makepad_widgets::math_f32::{vec4, Vec4};
+use std::time::Duration;
 
 pub const BYTES_PER_MB: f64 = 1_048_576.0; // (1024^2)
 pub const HUGGING_FACE_BASE_URL: &str = "https://huggingface.co";
@@ -24,6 +25,39 @@ pub fn format_model_downloaded_size(size: &str, progress: f64) -> Result<String>
     }
 }
 
+pub fn format_bytes(bytes: u64) -> String {
+    let size_mb = bytes as f64 / BYTES_PER_MB;
+
+    if size_mb >= 1024.0 {
+        format!("{:.2} GB", size_mb / 1024.0)
+    } else {
+        format!("{:.2} MB", size_mb)
+    }
+}
+
+pub fn format_download_speed(bytes_per_second: f64) -> String {
+    let speed_mb = bytes_per_second / BYTES_PER_MB;
+
+    if speed_mb >= 1.0 {
+        format!("{:.1} MB/s", speed_mb)
+    } else {
+        format!("{:.0} KB/s", bytes_per_second / 1024.0)
+    }
+}
+
+/// Coarse remaining time, e.g. "1h 05m left" or "40s left".
+pub fn format_eta(eta: Duration) -> String {
+    let secs = eta.as_secs();
+
+    if secs >= 3600 {
+        format!("{}h {:02}m left", secs / 3600, (secs % 3600) / 60)
+    } else if secs >= 60 {
+        format!("{}m {:02}s left", secs / 60, secs % 60)
+    } else {
+        format!("{}s left", secs)
+    }
+}
+
 pub fn hugging_face_model_url(model_id: &str) -> String {
     format!("{}/{}", HUGGING_FACE_BASE_URL, model_id)
 }